@@ -0,0 +1,117 @@
+//! Times an `--auto --fuzzy` run over a few thousand statement records and
+//! counts the allocations it makes, to show what comparing each record with
+//! its candidates costs. The run is made once searching one record at a
+//! time and once with `--threads 0`, one thread per CPU.
+//!
+//! Run with `cargo bench --bench matching`.
+
+use ledger_reconcile::{reconcile, Options, ReconcileConfig};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use std::{env, fs};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const TRANSACTIONS: u32 = 2000;
+const RECORDS: u32 = 3000;
+const KEYS: u32 = 28;
+
+fn main() {
+    let dir = env::temp_dir();
+    let path = |name: &str| {
+        dir.join(format!(
+            "ledger-reconcile-bench-{}-{}",
+            std::process::id(),
+            name
+        ))
+        .to_string_lossy()
+        .into_owned()
+    };
+
+    // Transactions share their date and amount in groups of
+    // `TRANSACTIONS / KEYS`, so the index leaves every record that many
+    // candidates, whose payees all differ from the record's and are compared
+    // by similarity; no record is cleared.
+    let mut ledger = String::new();
+    for n in 0..TRANSACTIONS {
+        ledger.push_str(&format!(
+            "2025-01-{:02} Payee {} Store\n    Expenses:Misc  ${}.00\n    Assets:Checking\n\n",
+            n % KEYS + 1,
+            n,
+            n % KEYS + 1
+        ));
+    }
+    let mut csv = String::from("Date,Description,Amount\n");
+    for n in 0..RECORDS {
+        csv.push_str(&format!(
+            "2025-01-{:02},CARD PURCHASE REF {},-{}.00\n",
+            n % KEYS + 1,
+            n,
+            n % KEYS + 1
+        ));
+    }
+
+    let mut config = ReconcileConfig {
+        ledger: path("bench.ledger"),
+        statements: vec![path("bench.csv")],
+        output: path("bench-out.ledger"),
+        options: Options {
+            auto: true,
+            yes: true,
+            fuzzy: true,
+            similarity: 80,
+            ..Options::default()
+        },
+    };
+    fs::write(&config.ledger, ledger).unwrap();
+    fs::write(&config.statements[0], csv).unwrap();
+
+    let mut reports = Vec::new();
+    for (name, threads) in [("serial", None), ("--threads 0", Some(0))] {
+        config.options.threads = threads;
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let report = reconcile(&config).unwrap();
+        let elapsed = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        println!(
+            "{}: {} records against {} transactions: {} unmatched in {:.2?}, {} allocations ({} per record)",
+            name,
+            RECORDS,
+            TRANSACTIONS,
+            report.unmatched,
+            elapsed,
+            allocations,
+            allocations / RECORDS as usize
+        );
+        reports.push(report);
+    }
+    assert_eq!(reports[0], reports[1], "--threads changed the results");
+
+    for path in [&config.ledger, &config.statements[0], &config.output] {
+        fs::remove_file(path).ok();
+    }
+}
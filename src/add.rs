@@ -0,0 +1,223 @@
+//! The `add` command: writing down a transaction by hand, without a
+//! statement record to draft it from.
+//!
+//! The date, payee, amount and accounts are asked for one by one, and the
+//! entry is drafted and checked just like the ones added for unmatched
+//! records, then appended to the ledger, or put in date order under
+//! `--sorted-insert`.
+
+use crate::ledger::{self, PostingStyle};
+use crate::rules::Rules;
+use crate::{
+    amount, backup_ledger, color, confirm_accounts, date, known_accounts, prompt, read_input,
+    write_output, Drafter, Options, Prompter, ReconcileError, MISC_ACCOUNT,
+};
+use chrono::NaiveDate;
+use log::{info, warn};
+use std::rc::Rc;
+
+/// Asks on the terminal for a transaction and adds it to the ledger at
+/// `ledger_path`, which is rewritten in place. Returns the entry, or `None`
+/// if a question was cancelled and nothing was written.
+pub fn add_entry(ledger_path: &str, options: &Options) -> Result<Option<String>, ReconcileError> {
+    let today = chrono::Local::now().date_naive();
+    add_entry_with(ledger_path, options, today, &mut prompt::Terminal)
+}
+
+/// Like [`add_entry`], but asking `prompter`, and offering `today` as the date.
+pub fn add_entry_with(
+    ledger_path: &str,
+    options: &Options,
+    today: NaiveDate,
+    prompter: &mut dyn Prompter,
+) -> Result<Option<String>, ReconcileError> {
+    if ledger_path == "-" {
+        return Err(ReconcileError::InvalidOptions(
+            "add writes the entry back to the ledger, so it cannot be read from stdin".to_string(),
+        ));
+    }
+    let content = read_input(ledger_path).map_err(ReconcileError::io(ledger_path))?;
+    let layout = ledger::Layout::detect(&content);
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let original = lines.len();
+
+    let mut drafter = Drafter::load(
+        options,
+        &Rules::default(),
+        PostingStyle::detect(&lines, options.syntax),
+    )?;
+    let mut known = known_accounts(&lines, &drafter, options);
+    let accounts: Vec<String> = known.iter().cloned().collect();
+
+    let Some(entry) = ask(prompter, options, today, &mut drafter, &accounts) else {
+        return Ok(None);
+    };
+    let (separators, syntax) = (options.separators, options.syntax);
+    let validate = Rc::new(move |entry: &str| ledger::check_entry(entry, separators, syntax));
+    let Some(entry) = prompter
+        .edit("Edit the new transaction:", &entry, validate)
+        .and_then(|entry| confirm_accounts(prompter, &entry, &mut known, separators))
+    else {
+        return Ok(None);
+    };
+    let entry = entry.trim_end().to_string();
+
+    lines.push(entry.clone());
+    if options.sorted_insert {
+        lines = ledger::merge_by_date(&lines, original, syntax).0;
+    }
+    if options.dry_run {
+        println!("{}", entry);
+        println!("Dry run: nothing was written to {}", ledger_path);
+        return Ok(Some(entry));
+    }
+    if options.backup {
+        let backup_path = backup_ledger(ledger_path).map_err(ReconcileError::io(ledger_path))?;
+        info!("Backed up original ledger to {}", backup_path);
+    }
+    write_output(ledger_path, &layout.render(&lines)).map_err(ReconcileError::io(ledger_path))?;
+    info!("{}", color::added("Added new transaction to ledger:"));
+    info!("{}", color::added(&entry));
+    Ok(Some(entry))
+}
+
+/// Asks for each field of the entry, repeating a question until its answer
+/// reads, and drafts the entry from them.
+fn ask(
+    prompter: &mut dyn Prompter,
+    options: &Options,
+    today: NaiveDate,
+    drafter: &mut Drafter,
+    accounts: &[String],
+) -> Option<String> {
+    let date = loop {
+        let answer = prompter.text("Date:", &today.format("%Y-%m-%d").to_string())?;
+        match date::parse_date(&answer) {
+            Some(date) => break date,
+            None => warn!("{:?} is not a date; write it as YYYY-MM-DD.", answer),
+        }
+    };
+    let payee = loop {
+        let answer = prompter.text("Payee:", "")?;
+        if !answer.trim().is_empty() {
+            break answer.trim().to_string();
+        }
+    };
+    let (quantity, commodity) = loop {
+        let answer = prompter.text("Amount:", "")?;
+        match amount::parse(&answer, options.separators) {
+            Some(parsed) => break (parsed.quantity, parsed.commodity),
+            None => warn!("{:?} is not an amount.", answer),
+        }
+    };
+    let misc_account = options.misc_account.as_deref().unwrap_or(MISC_ACCOUNT);
+    let account = prompter.account("Account:", misc_account, accounts)?;
+    let from = prompter.account("Paid from:", &drafter.asset_account, accounts)?;
+    if !from.trim().is_empty() {
+        drafter.asset_account = from.trim().to_string();
+    }
+
+    let commodity = commodity
+        .or_else(|| options.commodity.clone())
+        .unwrap_or_else(|| "$".to_string());
+    // The amount is the one booked to the account, so the bank moves its
+    // negation, as it would for a withdrawal on a statement.
+    Some(drafter.draft(
+        None,
+        &date.format("%Y-%m-%d").to_string(),
+        &payee,
+        &options.separators.format(-quantity),
+        &commodity,
+        account.trim(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::Scripted;
+    use std::{env, fs};
+
+    #[test]
+    fn test_answers_are_asked_again_until_they_read() {
+        let options = Options::default();
+        let mut drafter =
+            Drafter::load(&options, &Rules::default(), PostingStyle::default()).unwrap();
+        let mut prompter = Scripted::new(&[
+            "14/02",
+            "",
+            "",
+            "Florist",
+            "nope",
+            "$25.00",
+            "Expenses:Gifts",
+            "Assets:Checking",
+        ]);
+        let today = NaiveDate::from_ymd_opt(2025, 2, 14).unwrap();
+
+        let entry = ask(&mut prompter, &options, today, &mut drafter, &[]).unwrap();
+        let expected = drafter.draft(
+            None,
+            "2025-02-14",
+            "Florist",
+            "-25.00",
+            "$",
+            "Expenses:Gifts",
+        );
+        assert_eq!(entry, expected);
+        let amounts: Vec<&str> = entry
+            .lines()
+            .skip(1)
+            .filter_map(|posting| posting.split_whitespace().nth(1))
+            .collect();
+        assert_eq!(amounts, ["$25.00", "-$25.00"], "{}", entry);
+        assert!(entry.contains("Assets:Checking"), "{}", entry);
+    }
+
+    #[test]
+    fn test_add_entry_inserts_by_date_and_writes_nothing_when_cancelled() {
+        let path = env::temp_dir().join(format!(
+            "ledger-reconcile-{}-add.ledger",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(
+            &path,
+            "2025-01-01 Opening\n    Assets:Checking  $100.00\n    Equity\n\n2025-03-01 Rent\n    Expenses:Rent  $50.00\n    Assets:Checking\n",
+        )
+        .unwrap();
+        let edited = "2025-02-14 Florist\n    Expenses:Gifts  $25.00\n    Assets:Checking";
+        let mut prompter = Scripted::new(&[
+            "",
+            "Florist",
+            "25",
+            "Expenses:Gifts",
+            "",
+            edited,
+            "Yes, open",
+        ]);
+        let options = Options {
+            sorted_insert: true,
+            ..Options::default()
+        };
+        let today = NaiveDate::from_ymd_opt(2025, 2, 14).unwrap();
+
+        let entry = add_entry_with(&path, &options, today, &mut prompter).unwrap();
+
+        assert_eq!(entry.as_deref(), Some(edited));
+        let updated = fs::read_to_string(&path).unwrap();
+        assert!(
+            updated.contains(&format!("    Equity\n\n{}\n\n2025-03-01 Rent", edited)),
+            "{}",
+            updated
+        );
+
+        let mut cancelled = Scripted::new(&["", prompt::ESC]);
+        assert_eq!(
+            add_entry_with(&path, &options, today, &mut cancelled).unwrap(),
+            None
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), updated);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,405 @@
+//! Parsing and comparing monetary amounts.
+
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A quantity together with the commodity it was written in, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Amount {
+    pub quantity: Decimal,
+    /// A symbol like `$` or `€`, or a code like `USD`, as written.
+    pub commodity: Option<String>,
+}
+
+impl Amount {
+    /// Writes the amount with the decimal mark of `separators`. Symbols come
+    /// before the quantity and codes after it, as in `-$4.00` and `4.00 EUR`.
+    pub fn format(&self, separators: Separators) -> String {
+        let quantity = separators.format(self.quantity.abs());
+        let sign = if self.quantity.is_sign_negative() {
+            "-"
+        } else {
+            ""
+        };
+        match &self.commodity {
+            None => format!("{}{}", sign, quantity),
+            Some(code) if code.chars().all(char::is_alphabetic) => {
+                format!("{}{} {}", sign, quantity, code)
+            }
+            Some(symbol) => format!("{}{}{}", sign, symbol, quantity),
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(Separators::default()))
+    }
+}
+
+/// How numbers are written: the decimal mark and the thousands separator.
+///
+/// A mark left out follows from the other one, `.` pairing with `,`. With
+/// neither given, each amount is read as described on [`parse`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Separators {
+    pub decimal: Option<char>,
+    pub thousands: Option<char>,
+}
+
+impl Separators {
+    /// The marks to read numbers with, or `None` to infer them per number.
+    fn marks(self) -> Option<(char, char)> {
+        let other = |mark| if mark == ',' { '.' } else { ',' };
+        match (self.decimal, self.thousands) {
+            (Some(decimal), Some(thousands)) => Some((decimal, thousands)),
+            (Some(decimal), None) => Some((decimal, other(decimal))),
+            (None, Some(thousands)) => Some((other(thousands), thousands)),
+            (None, None) => None,
+        }
+    }
+
+    /// Writes `value` with this decimal mark and no thousands separators.
+    pub fn format(self, value: Decimal) -> String {
+        match self.marks() {
+            Some((decimal, _)) => value.to_string().replace('.', &decimal.to_string()),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// A named number notation, as a shortcut for the separator flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// `1,234.56`
+    Us,
+    /// `1.234,56`
+    Eu,
+}
+
+impl Locale {
+    pub fn separators(self) -> Separators {
+        let (decimal, thousands) = match self {
+            Locale::Us => ('.', ','),
+            Locale::Eu => (',', '.'),
+        };
+        Separators {
+            decimal: Some(decimal),
+            thousands: Some(thousands),
+        }
+    }
+}
+
+/// Parses an amount such as `$1,000.00`, `-€50,00`, `50.00 USD` or `(12.34)`.
+///
+/// The commodity may come before or after the number, with or without a space.
+/// A leading or embedded `-`, or surrounding parentheses, make the value
+/// negative. Anything from a price (`@`) or balance assertion (`=`) on is
+/// ignored.
+///
+/// With explicit `separators`, spaces and the thousands separator are dropped,
+/// and any other `.` or `,`, or a thousands separator after the decimal mark,
+/// makes the number unreadable. Otherwise the marks
+/// are inferred US-style: the last `.` or `,` is the decimal mark when both
+/// appear, and a lone `,` is a decimal comma unless exactly three digits follow
+/// it, so `€50,00` is fifty but `1,000` is a thousand.
+pub fn parse(raw: &str, separators: Separators) -> Option<Amount> {
+    let trimmed = raw.split(['@', '=']).next()?.trim();
+    let first_digit = trimmed.find(|c: char| c.is_ascii_digit())?;
+    let last_digit = trimmed.rfind(|c: char| c.is_ascii_digit())?;
+
+    let outside = |text: &str| {
+        text.chars()
+            .filter(|c| !c.is_whitespace() && !"-+()\"".contains(*c))
+            .collect::<String>()
+    };
+    let before = outside(&trimmed[..first_digit]);
+    let after = outside(&trimmed[last_digit + 1..]);
+    let commodity = [before, after].into_iter().find(|text| !text.is_empty());
+
+    let negative = trimmed.contains('-') || (trimmed.starts_with('(') && trimmed.ends_with(')'));
+    let number = &trimmed[first_digit..=last_digit];
+    let plain = match separators.marks() {
+        Some(marks) => with_marks(number, marks)?,
+        None => plain_number(number),
+    };
+    let value = Decimal::from_str(&plain).ok()?;
+
+    Some(Amount {
+        quantity: if negative { -value } else { value },
+        commodity,
+    })
+}
+
+/// Parses just the quantity of an amount; see [`parse`].
+pub fn parse_amount(raw: &str, separators: Separators) -> Option<Decimal> {
+    parse(raw, separators).map(|amount| amount.quantity)
+}
+
+/// Rewrites a number written with the given `(decimal, thousands)` marks
+/// with `.` as the only mark.
+fn with_marks(number: &str, (decimal, thousands): (char, char)) -> Option<String> {
+    let mut plain = String::new();
+    for c in number.chars() {
+        match c {
+            '0'..='9' => plain.push(c),
+            _ if c == decimal => plain.push('.'),
+            _ if c == thousands && plain.contains('.') => return None,
+            _ if c == thousands || c.is_whitespace() => {}
+            '.' | ',' => return None,
+            _ => {}
+        }
+    }
+    Some(plain)
+}
+
+/// Rewrites the digits and separators of a number with `.` as the only mark,
+/// inferring which mark is the decimal one.
+fn plain_number(number: &str) -> String {
+    let decimal_mark = inferred_decimal(number);
+    number
+        .chars()
+        .filter_map(|c| match c {
+            '0'..='9' => Some(c),
+            _ if Some(c) == decimal_mark => Some('.'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The decimal mark of a number read without explicit separators: the last
+/// `.` or `,` when both appear, a lone `.`, or a lone `,` unless exactly three
+/// digits follow it.
+fn inferred_decimal(number: &str) -> Option<char> {
+    match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) if number.matches('.').count() == 1 => Some('.'),
+        (None, Some(comma)) if number.matches(',').count() == 1 && number.len() - comma != 4 => {
+            Some(',')
+        }
+        _ => None,
+    }
+}
+
+/// Whether `raw` reads as an amount with its marks where they belong: one
+/// decimal mark at most, and thousands separators only between groups of
+/// three digits before it. `50,00.0` still parses, as 5000.0, but isn't well
+/// formed.
+pub fn well_formed(raw: &str, separators: Separators) -> bool {
+    if parse(raw, separators).is_none() {
+        return false;
+    }
+    let trimmed = raw.split(['@', '=']).next().unwrap_or_default().trim();
+    let (Some(first_digit), Some(last_digit)) = (
+        trimmed.find(|c: char| c.is_ascii_digit()),
+        trimmed.rfind(|c: char| c.is_ascii_digit()),
+    ) else {
+        return false;
+    };
+    let number: String = trimmed[first_digit..=last_digit]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    let (decimal, thousands) =
+        separators
+            .marks()
+            .unwrap_or_else(|| match inferred_decimal(&number) {
+                Some(',') => (',', '.'),
+                Some(_) => ('.', ','),
+                None if number.contains('.') => (',', '.'),
+                None => ('.', ','),
+            });
+    let (integer, fraction) = number.split_once(decimal).unwrap_or((&number, ""));
+    let digits = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    let mut groups = integer.split(thousands);
+    let leading = groups.next().unwrap_or_default();
+
+    (fraction.is_empty() || digits(fraction))
+        && digits(leading)
+        && (integer == leading || leading.len() <= 3)
+        && groups.all(|group| group.len() == 3 && digits(group))
+}
+
+/// The ISO code for the common currency symbols, so `$50` and `50 USD` agree.
+pub fn canonical(commodity: &str) -> &str {
+    match commodity {
+        "$" => "USD",
+        "€" => "EUR",
+        "£" => "GBP",
+        "¥" => "JPY",
+        _ => commodity,
+    }
+}
+
+/// Whether two commodities are the same, treating a missing one as unknown and
+/// so agreeing with anything.
+pub fn same_commodity(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => canonical(a).eq_ignore_ascii_case(canonical(b)),
+        _ => true,
+    }
+}
+
+/// Whether two amounts agree in magnitude to within `tolerance`.
+///
+/// Signs are ignored because a statement line usually corresponds to both legs
+/// of a balanced transaction; [`signs_agree`] compares them once the bank's own
+/// leg is known.
+pub fn amounts_match(statement: Decimal, ledger: Decimal, tolerance: Decimal) -> bool {
+    difference(statement, ledger) <= tolerance
+}
+
+/// How far apart two amounts are in magnitude, ignoring their signs.
+pub fn difference(statement: Decimal, ledger: Decimal) -> Decimal {
+    (statement.abs() - ledger.abs()).abs()
+}
+
+/// Whether two amounts go the same way, counting zero as either.
+pub fn signs_agree(statement: Decimal, ledger: Decimal) -> bool {
+    statement.is_zero()
+        || ledger.is_zero()
+        || statement.is_sign_negative() == ledger.is_sign_negative()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    const INFERRED: Separators = Separators {
+        decimal: None,
+        thousands: None,
+    };
+
+    fn amount(quantity: &str, commodity: Option<&str>) -> Option<Amount> {
+        Some(Amount {
+            quantity: dec(quantity),
+            commodity: commodity.map(String::from),
+        })
+    }
+
+    #[test]
+    fn test_parse_amount_strips_symbols_and_separators() {
+        assert_eq!(
+            parse_amount("$1,000.00", Locale::Us.separators()),
+            Some(dec("1000.00"))
+        );
+        assert_eq!(
+            parse_amount("-$50.00", Locale::Us.separators()),
+            Some(dec("-50.00"))
+        );
+        assert_eq!(
+            parse_amount("$-50", Locale::Us.separators()),
+            Some(dec("-50"))
+        );
+        assert_eq!(
+            parse_amount("(12.34)", Locale::Us.separators()),
+            Some(dec("-12.34"))
+        );
+        assert_eq!(parse_amount("Groceries", Locale::Us.separators()), None);
+    }
+
+    #[test]
+    fn test_parse_reads_the_commodity_on_either_side() {
+        assert_eq!(parse("€50,00", INFERRED), amount("50.00", Some("€")));
+        assert_eq!(parse("$50.00", INFERRED), amount("50.00", Some("$")));
+        assert_eq!(parse("50.00 USD", INFERRED), amount("50.00", Some("USD")));
+        assert_eq!(
+            parse("-1,000.00 USD", INFERRED),
+            amount("-1000.00", Some("USD"))
+        );
+        assert_eq!(parse("€1.234,56", INFERRED), amount("1234.56", Some("€")));
+        assert_eq!(parse("£12", INFERRED), amount("12", Some("£")));
+        assert_eq!(parse("10 EUR @ $1.10", INFERRED), amount("10", Some("EUR")));
+        assert_eq!(parse("1,000", INFERRED), amount("1000", None));
+    }
+
+    #[test]
+    fn test_explicit_separators() {
+        let us = Locale::Us.separators();
+        let eu = Locale::Eu.separators();
+
+        assert_eq!(parse_amount("1.234,56", eu), Some(dec("1234.56")));
+        assert_eq!(parse_amount("1,234.56", us), parse_amount("1.234,56", eu));
+        assert_eq!(parse_amount("-€1 234,56", eu), Some(dec("-1234.56")));
+        assert_eq!(parse_amount("1.234", eu), Some(dec("1234")));
+        assert_eq!(parse_amount("1.234", us), Some(dec("1.234")));
+        assert_eq!(parse_amount("1,234.56", eu), None);
+
+        let decimal_comma = Separators {
+            decimal: Some(','),
+            thousands: None,
+        };
+        assert_eq!(decimal_comma.marks(), Some((',', '.')));
+        assert_eq!(decimal_comma.format(dec("-12.50")), "-12,50");
+        assert_eq!(INFERRED.format(dec("-12.50")), "-12.50");
+    }
+
+    #[test]
+    fn test_well_formed_amounts_group_thousands_by_three() {
+        for raw in [
+            "50.00",
+            "-$1,234.56",
+            "1,000",
+            "(25.00)",
+            "4.50 USD",
+            "1234567.8",
+        ] {
+            assert!(well_formed(raw, Separators::default()), "{}", raw);
+        }
+        for raw in ["50,00.0", "1,00,000.00", "12a34", "1.2.3,4"] {
+            assert!(!well_formed(raw, Separators::default()), "{}", raw);
+        }
+
+        let eu = Locale::Eu.separators();
+        assert!(well_formed("1.234,56 €", eu));
+        assert!(well_formed("1 234,56", eu));
+        assert!(!well_formed("12.34,56", eu));
+    }
+
+    #[test]
+    fn test_same_commodity() {
+        assert!(same_commodity(Some("$"), Some("USD")));
+        assert!(same_commodity(Some("eur"), Some("€")));
+        assert!(same_commodity(None, Some("£")));
+        assert!(!same_commodity(Some("$"), Some("€")));
+        assert!(!same_commodity(Some("USD"), Some("CAD")));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(parse("-$4.00", INFERRED).unwrap().to_string(), "-$4.00");
+        assert_eq!(parse("4.00 EUR", INFERRED).unwrap().to_string(), "4.00 EUR");
+        assert_eq!(parse("4", INFERRED).unwrap().to_string(), "4");
+        assert_eq!(
+            parse("-1234.5 EUR", INFERRED)
+                .unwrap()
+                .format(Locale::Eu.separators()),
+            "-1234,5 EUR"
+        );
+    }
+
+    #[test]
+    fn test_amounts_match_with_tolerance() {
+        assert!(amounts_match(dec("50.00"), dec("50"), Decimal::ZERO));
+        assert!(!amounts_match(dec("49.99"), dec("50.00"), dec("0.00")));
+        assert!(amounts_match(dec("49.99"), dec("50.00"), dec("0.01")));
+        assert!(amounts_match(dec("-49.99"), dec("50.00"), dec("0.01")));
+    }
+
+    #[test]
+    fn test_signs_agree_counts_zero_as_either_way() {
+        assert!(signs_agree(dec("-50.00"), dec("-50")));
+        assert!(!signs_agree(dec("-50.00"), dec("50.00")));
+        assert!(signs_agree(dec("0.00"), dec("-1")));
+        assert!(signs_agree(dec("-0.00"), dec("1")));
+    }
+}
@@ -0,0 +1,211 @@
+//! Beancount's transaction syntax.
+//!
+//! A beancount transaction puts its flag after the date and quotes its payee
+//! and narration:
+//!
+//! ```text
+//! 2025-01-03 * "Blue Bottle" "Coffee"
+//!   Expenses:Coffee  4.50 USD
+//!   Assets:Checking
+//! ```
+//!
+//! `*` marks a cleared transaction and `!` a pending one. A transaction
+//! written with the `txn` keyword is taken as not yet reconciled, so marking
+//! it swaps the keyword for a flag. Postings read as in hledger, and the
+//! `key: value` metadata lines beancount allows under a transaction are
+//! skipped.
+
+use crate::amount;
+use crate::dialect::LedgerDialect;
+use crate::ledger::{self, Status, Syntax, Transaction};
+use std::borrow::Cow;
+
+/// The beancount dialect.
+pub struct Beancount;
+
+impl LedgerDialect for Beancount {
+    fn is_header(&self, line: &str) -> bool {
+        is_header(line)
+    }
+
+    fn header_rule(&self) -> &'static str {
+        "the first line must be a date followed by a flag or `txn`"
+    }
+
+    fn parse_transactions(&self, lines: &[String]) -> Vec<Transaction> {
+        ledger::blocks(lines, Syntax::Beancount, is_header)
+    }
+
+    fn status(&self, header: &str) -> Option<Status> {
+        status(header)
+    }
+
+    fn description<'a>(&self, header: &'a str) -> Cow<'a, str> {
+        Cow::Owned(description(header))
+    }
+
+    fn is_metadata(&self, line: &str) -> bool {
+        is_metadata(line)
+    }
+
+    fn mark_cleared(&self, header: &str, status: Status) -> String {
+        with_status(header, status)
+    }
+
+    /// Beancount only knows currency codes, so `$` is written as `USD`.
+    fn commodity<'a>(&self, commodity: &'a str) -> &'a str {
+        amount::canonical(commodity)
+    }
+
+    fn render(&self, date: &str, description: &str, postings: &[String]) -> String {
+        let mut entry = entry_header(date, description);
+        for posting in postings {
+            entry.push('\n');
+            entry.push_str(posting);
+        }
+        entry
+    }
+}
+
+/// The flag or `txn` keyword after the date of a transaction header, with
+/// its byte offset in the line.
+fn flag(header: &str) -> Option<(usize, &str)> {
+    if !header.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let date_end = header.find(char::is_whitespace)?;
+    let rest = &header[date_end..];
+    let start = date_end + (rest.len() - rest.trim_start().len());
+    let token = header[start..].split_whitespace().next()?;
+    ["*", "!", "txn"].contains(&token).then_some((start, token))
+}
+
+/// Returns true if the line opens a transaction, rather than another dated
+/// directive such as `open` or `balance`.
+fn is_header(line: &str) -> bool {
+    flag(line).is_some()
+}
+
+/// The status given by a header's flag; `txn` has none.
+fn status(header: &str) -> Option<Status> {
+    let (_, token) = flag(header)?;
+    token.chars().next().and_then(Status::from_marker)
+}
+
+/// Rewrites a header line so its flag is `status`'s, replacing `txn` or the
+/// flag already there.
+fn with_status(header: &str, status: Status) -> String {
+    match flag(header) {
+        Some((start, token)) => format!(
+            "{}{}{}",
+            &header[..start],
+            status.marker(),
+            &header[start + token.len()..]
+        ),
+        None => header.to_string(),
+    }
+}
+
+/// The payee and narration of a header, unquoted and joined by a space.
+fn description(header: &str) -> String {
+    let Some((start, token)) = flag(header) else {
+        return String::new();
+    };
+    let rest = &header[start + token.len()..];
+
+    let mut strings = Vec::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut text = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => text.extend(chars.next()),
+                        '"' => break,
+                        _ => text.push(c),
+                    }
+                }
+                strings.push(text);
+            }
+            ';' => break,
+            _ => {}
+        }
+    }
+    strings
+        .iter()
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns true for an indented `key: value` metadata line.
+fn is_metadata(line: &str) -> bool {
+    let body = line.trim_start();
+    let Some((key, value)) = body.split_once(':') else {
+        return false;
+    };
+    key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && (value.is_empty() || value.starts_with(char::is_whitespace))
+}
+
+/// The header of a new entry, flagged `txn` and with `description` as its
+/// narration.
+fn entry_header(date: &str, description: &str) -> String {
+    format!(
+        "{} txn \"{}\"",
+        date,
+        description.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_sets_the_status() {
+        assert_eq!(status("2025-01-03 * \"Coffee\""), Some(Status::Cleared));
+        assert_eq!(status("2025-01-03 ! \"Coffee\""), Some(Status::Pending));
+        assert_eq!(status("2025-01-03 txn \"Coffee\""), None);
+        assert!(!is_header("2025-01-01 open Assets:Checking USD"));
+        assert!(!is_header("2025-01-31 balance Assets:Checking  100.00 USD"));
+
+        assert_eq!(
+            with_status("2025-01-03 txn \"Blue Bottle\" \"Coffee\"", Status::Cleared),
+            "2025-01-03 * \"Blue Bottle\" \"Coffee\""
+        );
+        assert_eq!(
+            with_status("2025-01-03 * \"Coffee\"  ; paid", Status::Pending),
+            "2025-01-03 ! \"Coffee\"  ; paid"
+        );
+    }
+
+    #[test]
+    fn test_description_joins_payee_and_narration() {
+        assert_eq!(
+            description("2025-01-03 * \"Blue Bottle\" \"Coffee\" #work ; note"),
+            "Blue Bottle Coffee"
+        );
+        assert_eq!(
+            description("2025-01-03 txn \"Say \\\"hi\\\"\""),
+            "Say \"hi\""
+        );
+        assert_eq!(
+            description(&entry_header("2025-01-03", "Say \"hi\"")),
+            "Say \"hi\""
+        );
+    }
+
+    #[test]
+    fn test_metadata_lines_are_not_postings() {
+        assert!(is_metadata("  receipt: \"2025-01-03.pdf\""));
+        assert!(is_metadata("  invoice-id: 42"));
+        assert!(!is_metadata("  Assets:Checking  -4.50 USD"));
+        assert!(!is_metadata("  * Assets:Checking"));
+    }
+}
@@ -0,0 +1,105 @@
+//! Saving progress part-way through a run so an interrupted one can be resumed.
+
+use crate::report::Decision;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+
+/// Where the checkpoint for a run writing to `output_path` is kept.
+pub fn path_for(output_path: &str) -> String {
+    format!("{}.checkpoint", output_path)
+}
+
+/// The state of a run after some of its statement records were decided.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The ledger as it was read, to tell whether the checkpoint still applies.
+    pub ledger: Vec<String>,
+    /// How many statement records the run had.
+    pub records: usize,
+    /// Index of the first record not yet decided.
+    pub next_record: usize,
+    /// The ledger lines with the changes made so far.
+    pub lines: Vec<String>,
+    /// Every decision made so far, in order.
+    pub decisions: Vec<Decision>,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to `path`, replacing any earlier one only once the
+    /// new one is complete so an interruption mid-write loses nothing.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let partial = format!("{}.partial", path);
+        fs::write(&partial, serde_json::to_string(self)?)?;
+        fs::rename(&partial, path)
+    }
+
+    /// Reads the checkpoint at `path`, or `None` if there is none.
+    pub fn load(path: &str) -> io::Result<Option<Checkpoint>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        serde_json::from_str(&content).map(Some).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: not a readable checkpoint: {}", path, err),
+            )
+        })
+    }
+}
+
+/// Deletes the checkpoint at `path`, if there is one.
+pub fn remove(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Action, StatementRecord};
+    use std::env;
+
+    #[test]
+    fn test_checkpoint_round_trips() {
+        let path = env::temp_dir()
+            .join(format!(
+                "ledger-reconcile-{}.checkpoint",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            ledger: vec!["2025-01-01 Coffee".to_string()],
+            records: 2,
+            next_record: 1,
+            lines: vec!["* 2025-01-01 Coffee".to_string()],
+            decisions: vec![Decision {
+                record_index: 0,
+                record: StatementRecord {
+                    date: "2025-01-01".to_string(),
+                    description: "Coffee".to_string(),
+                    amount: "-4.00".to_string(),
+                },
+                candidates: Vec::new(),
+                action: Action::Cleared,
+                line: Some(1),
+            }],
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), Some(checkpoint));
+
+        fs::write(&path, "{").unwrap();
+        assert_eq!(
+            Checkpoint::load(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,43 @@
+//! Colors for the per-record messages, so a long session can be scanned at a
+//! glance: green for cleared, yellow for ignored or unmatched, and blue for
+//! added records.
+//!
+//! The messages go to stderr, and are only colored while it is a terminal and
+//! `NO_COLOR` isn't set; [`disable_colors`] turns colors off for `--no-color`. The
+//! text is the same either way.
+
+use console::{style, StyledObject};
+use std::fmt::Display;
+
+/// A message about a record that was cleared.
+pub fn cleared<D: Display>(message: D) -> StyledObject<D> {
+    style(message).for_stderr().green()
+}
+
+/// A message about a record that was ignored or matched nothing.
+pub fn skipped<D: Display>(message: D) -> StyledObject<D> {
+    style(message).for_stderr().yellow()
+}
+
+/// A message about a new entry added to the ledger.
+pub fn added<D: Display>(message: D) -> StyledObject<D> {
+    style(message).for_stderr().blue()
+}
+
+/// Turns colors off for the rest of the run.
+pub fn disable_colors() {
+    console::set_colors_enabled(false);
+    console::set_colors_enabled_stderr(false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_colors_leave_the_text_alone() {
+        disable_colors();
+        assert_eq!(cleared("Marked").to_string(), "Marked");
+        assert_eq!(added(format_args!("Added {}", 1)).to_string(), "Added 1");
+    }
+}
@@ -0,0 +1,408 @@
+//! Selecting CSV columns by index or header name.
+
+use crate::amount::{self, Separators};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer};
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// A CSV column, given either as a zero-based index or as a header name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnSpec {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for ColumnSpec {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.trim().parse::<usize>() {
+            Ok(index) => ColumnSpec::Index(index),
+            Err(_) => ColumnSpec::Name(value.trim().to_string()),
+        })
+    }
+}
+
+/// Config files may give a column as a bare integer or as a string.
+impl<'de> Deserialize<'de> for ColumnSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Index(usize),
+            Text(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Index(index) => ColumnSpec::Index(index),
+            Raw::Text(text) => match text.parse() {
+                Ok(spec) => spec,
+                Err(never) => match never {},
+            },
+        })
+    }
+}
+
+impl ColumnSpec {
+    /// Resolves this column against the CSV header row, returning its index.
+    ///
+    /// Header names are compared case-insensitively, ignoring surrounding whitespace.
+    pub fn resolve(&self, headers: &csv::StringRecord) -> Result<usize, String> {
+        match self {
+            ColumnSpec::Index(index) if *index < headers.len() => Ok(*index),
+            ColumnSpec::Index(index) => Err(format!(
+                "column index {} is out of range (the CSV has {} columns)",
+                index,
+                headers.len()
+            )),
+            ColumnSpec::Name(name) => headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("column \"{}\" not found in the CSV header", name)),
+        }
+    }
+}
+
+/// The field delimiters a statement's delimiter is guessed from, preferred in
+/// this order on a tie.
+const DELIMITERS: [u8; 3] = [b',', b';', b'\t'];
+
+/// Guesses a CSV file's field delimiter: whichever of `,`, `;` and tab
+/// appears most often outside quotes on its first line.
+pub fn detect_delimiter(content: &str) -> u8 {
+    let first = content
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    let mut counts = [0; DELIMITERS.len()];
+    let mut quoted = false;
+    for byte in first.bytes() {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if let Some(index) = DELIMITERS.iter().position(|&d| d == byte && !quoted) {
+            counts[index] += 1;
+        }
+    }
+    // The last of several maxima wins, so the candidates are tried in reverse.
+    DELIMITERS
+        .into_iter()
+        .zip(counts)
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .map_or(b',', |(delimiter, _)| delimiter)
+}
+
+/// The sign given to values in the debit column of a split debit/credit export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebitSign {
+    #[default]
+    Negative,
+    Positive,
+}
+
+/// Where the amount of a CSV record comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AmountColumns {
+    /// A single signed amount column.
+    Single(ColumnSpec),
+    /// Separate debit and credit columns, at most one of which is filled per row.
+    Split {
+        debit: ColumnSpec,
+        credit: ColumnSpec,
+        debit_sign: DebitSign,
+    },
+}
+
+/// Which CSV columns hold the date, description, amount and, optionally, the
+/// bank's transaction ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub date: ColumnSpec,
+    /// One column, or several whose fields are joined with a space, for banks
+    /// that split the payee and the detail between a `Name` and a `Memo`.
+    pub description: Vec<ColumnSpec>,
+    pub amount: AmountColumns,
+    pub id: Option<ColumnSpec>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            date: ColumnSpec::Index(0),
+            description: vec![ColumnSpec::Index(1)],
+            amount: AmountColumns::Single(ColumnSpec::Index(2)),
+            id: None,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// The first column given by header name rather than index, if any.
+    pub fn named(&self) -> Option<&str> {
+        let amount = match &self.amount {
+            AmountColumns::Single(spec) => vec![spec],
+            AmountColumns::Split { debit, credit, .. } => vec![debit, credit],
+        };
+        [&self.date]
+            .into_iter()
+            .chain(&self.description)
+            .chain(amount)
+            .chain(&self.id)
+            .find_map(|spec| match spec {
+                ColumnSpec::Name(name) => Some(name.as_str()),
+                ColumnSpec::Index(_) => None,
+            })
+    }
+
+    /// Resolves every configured column against the CSV header row.
+    pub fn resolve(&self, headers: &csv::StringRecord) -> Result<ResolvedColumns, String> {
+        let amount = match &self.amount {
+            AmountColumns::Single(spec) => ResolvedAmount::Single(spec.resolve(headers)?),
+            AmountColumns::Split {
+                debit,
+                credit,
+                debit_sign,
+            } => ResolvedAmount::Split {
+                debit: debit.resolve(headers)?,
+                credit: credit.resolve(headers)?,
+                debit_sign: *debit_sign,
+            },
+        };
+
+        Ok(ResolvedColumns {
+            date: self.date.resolve(headers)?,
+            description: self
+                .description
+                .iter()
+                .map(|spec| spec.resolve(headers))
+                .collect::<Result<_, _>>()?,
+            amount,
+            id: self
+                .id
+                .as_ref()
+                .map(|spec| spec.resolve(headers))
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ResolvedAmount {
+    Single(usize),
+    Split {
+        debit: usize,
+        credit: usize,
+        debit_sign: DebitSign,
+    },
+}
+
+/// A [`ColumnMapping`] with every column resolved to an index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedColumns {
+    date: usize,
+    description: Vec<usize>,
+    amount: ResolvedAmount,
+    id: Option<usize>,
+}
+
+impl ResolvedColumns {
+    /// The number of fields a record needs for every mapped column to exist.
+    pub fn width(&self) -> usize {
+        let amount = match self.amount {
+            ResolvedAmount::Single(index) => index,
+            ResolvedAmount::Split { debit, credit, .. } => debit.max(credit),
+        };
+        self.description
+            .iter()
+            .fold(self.date, |widest, &index| widest.max(index))
+            .max(amount)
+            .max(self.id.unwrap_or_default())
+            + 1
+    }
+
+    /// Pulls the `[date, description, amount, id]` fields out of a CSV record;
+    /// the ID is empty when no ID column is mapped. The description columns'
+    /// non-empty fields are joined with a space, and split amounts are
+    /// combined and written back with the decimal mark of `separators`.
+    pub fn extract(&self, record: &csv::StringRecord, separators: Separators) -> Vec<String> {
+        let field = |index: usize| record.get(index).unwrap_or_default().to_string();
+
+        let amount = match self.amount {
+            ResolvedAmount::Single(index) => field(index),
+            ResolvedAmount::Split {
+                debit,
+                credit,
+                debit_sign,
+            } => split_amount(&field(debit), &field(credit), debit_sign, separators),
+        };
+
+        let description = self
+            .description
+            .iter()
+            .map(|&index| record.get(index).unwrap_or_default().trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        vec![
+            field(self.date),
+            description,
+            amount,
+            self.id.map(field).unwrap_or_default(),
+        ]
+    }
+}
+
+/// Combines a debit and a credit cell into one signed amount.
+///
+/// Empty cells count as zero; if neither cell holds a number the result is empty.
+fn split_amount(
+    debit: &str,
+    credit: &str,
+    debit_sign: DebitSign,
+    separators: Separators,
+) -> String {
+    let debit_value = amount::parse_amount(debit, separators).map(|value| value.abs());
+    let credit_value = amount::parse_amount(credit, separators).map(|value| value.abs());
+    if debit_value.is_none() && credit_value.is_none() {
+        return String::new();
+    }
+
+    let debit_value = debit_value.unwrap_or_default();
+    let credit_value = credit_value.unwrap_or_default();
+    let signed = match debit_sign {
+        DebitSign::Negative => credit_value - debit_value,
+        DebitSign::Positive => debit_value - credit_value,
+    };
+
+    separators.format(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> csv::StringRecord {
+        csv::StringRecord::from(vec!["Posted Date", "Payee", "Amount"])
+    }
+
+    #[test]
+    fn test_parse_column_spec() {
+        assert_eq!("2".parse::<ColumnSpec>(), Ok(ColumnSpec::Index(2)));
+        assert_eq!(
+            "Payee".parse::<ColumnSpec>(),
+            Ok(ColumnSpec::Name("Payee".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_resolve_by_index_and_name() {
+        assert_eq!(ColumnSpec::Index(1).resolve(&headers()), Ok(1));
+        assert_eq!(
+            ColumnSpec::Name("posted date".to_string()).resolve(&headers()),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_resolve_missing_column_names_it() {
+        let error = ColumnSpec::Name("Memo".to_string())
+            .resolve(&headers())
+            .unwrap_err();
+        assert!(error.contains("\"Memo\""));
+
+        assert!(ColumnSpec::Index(7).resolve(&headers()).is_err());
+    }
+
+    #[test]
+    fn test_named_finds_columns_given_by_name() {
+        let mut mapping = ColumnMapping::default();
+        assert_eq!(mapping.named(), None);
+
+        mapping.id = Some(ColumnSpec::Name("Reference".to_string()));
+        assert_eq!(mapping.named(), Some("Reference"));
+    }
+
+    #[test]
+    fn test_extract_split_debit_and_credit() {
+        let headers = csv::StringRecord::from(vec!["Date", "Description", "Debit", "Credit"]);
+        let mut mapping = ColumnMapping {
+            amount: AmountColumns::Split {
+                debit: ColumnSpec::Name("Debit".to_string()),
+                credit: ColumnSpec::Name("Credit".to_string()),
+                debit_sign: DebitSign::Negative,
+            },
+            ..ColumnMapping::default()
+        };
+
+        let debit_row = csv::StringRecord::from(vec!["2025-01-01", "Groceries", "$50.00", ""]);
+        let credit_row = csv::StringRecord::from(vec!["2025-01-02", "Refund", "", "12.50"]);
+
+        let resolved = mapping.resolve(&headers).unwrap();
+        assert_eq!(
+            resolved.extract(&debit_row, Separators::default())[2],
+            "-50.00"
+        );
+        assert_eq!(
+            resolved.extract(&credit_row, Separators::default())[2],
+            "12.50"
+        );
+
+        if let AmountColumns::Split { debit_sign, .. } = &mut mapping.amount {
+            *debit_sign = DebitSign::Positive;
+        }
+        let resolved = mapping.resolve(&headers).unwrap();
+        assert_eq!(
+            resolved.extract(&debit_row, Separators::default())[2],
+            "50.00"
+        );
+        assert_eq!(
+            resolved.extract(&credit_row, Separators::default())[2],
+            "-12.50"
+        );
+    }
+
+    #[test]
+    fn test_description_columns_are_joined() {
+        let headers = csv::StringRecord::from(vec!["Date", "Name", "Memo", "Amount"]);
+        let mapping = ColumnMapping {
+            description: vec![
+                ColumnSpec::Name("Name".to_string()),
+                ColumnSpec::Name("Memo".to_string()),
+            ],
+            amount: AmountColumns::Single(ColumnSpec::Name("Amount".to_string())),
+            ..ColumnMapping::default()
+        };
+        let resolved = mapping.resolve(&headers).unwrap();
+        assert_eq!(resolved.width(), 4);
+
+        let extract = |fields: Vec<&str>| {
+            resolved.extract(&csv::StringRecord::from(fields), Separators::default())[1].clone()
+        };
+        assert_eq!(
+            extract(vec!["2025-01-03", "AMAZON ", "order 5678", "-20.00"]),
+            "AMAZON order 5678"
+        );
+        assert_eq!(
+            extract(vec!["2025-01-03", "AMAZON", "", "-20.00"]),
+            "AMAZON"
+        );
+    }
+
+    #[test]
+    fn test_delimiter_is_the_most_frequent_outside_quotes() {
+        assert_eq!(detect_delimiter("Date,Description,Amount\n"), b',');
+        assert_eq!(
+            detect_delimiter("Datum;Omschrijving;Bedrag\n1;2,50;3\n"),
+            b';'
+        );
+        assert_eq!(detect_delimiter("Date\tDescription\tAmount\n"), b'\t');
+        assert_eq!(
+            detect_delimiter("\"Date; time\",\"Payee; note\",Amount\n"),
+            b','
+        );
+        assert_eq!(detect_delimiter("Date\n"), b',');
+    }
+}
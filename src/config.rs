@@ -0,0 +1,543 @@
+//! Loading saved settings from a TOML config file.
+//!
+//! Keys mirror the long command-line flags (`date-col`, `amount-tolerance`,
+//! ...). Top-level keys apply to every run, and `[profile.<name>]` tables hold
+//! per-bank settings selected with `--profile`, which override the top level:
+//!
+//! ```toml
+//! ledger = "~/finance/main.ledger"
+//! backup = true
+//!
+//! [profile.chase]
+//! date-col = "Posting Date"
+//! amount-col = "Amount"
+//! csv-date-format = "%m/%d/%Y"
+//! ```
+
+use chrono::NaiveDate;
+use ledger_reconcile::{
+    ColumnSpec, CsvSort, DebitSign, Encoding, InputFormat, Locale, MatchField, MenuAction,
+    Normalization, Status, Syntax,
+};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Reads `desc-col`, which may be one column, a comma-separated string of
+/// them, or an array.
+fn column_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<ColumnSpec>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Many(Vec<ColumnSpec>),
+        One(ColumnSpec),
+    }
+
+    Ok(
+        Option::<Raw>::deserialize(deserializer)?.map(|raw| match raw {
+            Raw::Many(columns) => columns,
+            Raw::One(ColumnSpec::Name(names)) => names
+                .split(',')
+                .map(|name| match name.parse() {
+                    Ok(spec) => spec,
+                    Err(never) => match never {},
+                })
+                .collect(),
+            Raw::One(index) => vec![index],
+        }),
+    )
+}
+
+/// Reads `cleared-markers`, a comma-separated string of markers such as `"*,!"`.
+fn marker_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<Status>>, D::Error> {
+    let Some(markers) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    markers
+        .split(',')
+        .map(|marker| {
+            Status::parse_marker(marker).ok_or_else(|| {
+                de::Error::custom(format!("{:?} is not a status marker; use * or !", marker))
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Reads a date key such as `since`, written as a `YYYY-MM-DD` string like
+/// the flag it mirrors.
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> {
+    let Some(date) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    date.parse()
+        .map(Some)
+        .map_err(|_| de::Error::custom(format!("{:?} is not a YYYY-MM-DD date", date)))
+}
+
+/// The file name searched for in the current directory and in `~/.config`.
+pub const CONFIG_FILE_NAME: &str = "ledger-reconcile.toml";
+
+/// Settings read from a config file; `None` means the key was not given.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Settings {
+    pub ledger: Option<String>,
+    pub syntax: Option<Syntax>,
+    pub format: Option<InputFormat>,
+    pub encoding: Option<Encoding>,
+    pub delimiter: Option<char>,
+    pub output: Option<String>,
+    pub in_place: Option<bool>,
+    pub date_col: Option<ColumnSpec>,
+    #[serde(default, deserialize_with = "column_list")]
+    pub desc_col: Option<Vec<ColumnSpec>>,
+    pub amount_col: Option<ColumnSpec>,
+    pub debit_col: Option<ColumnSpec>,
+    pub credit_col: Option<ColumnSpec>,
+    pub id_col: Option<ColumnSpec>,
+    pub debit_sign: Option<DebitSign>,
+    pub invert_amount: Option<bool>,
+    pub amount_tolerance: Option<Decimal>,
+    pub account: Option<String>,
+    #[serde(rename = "exclude-account")]
+    pub exclude_accounts: Option<Vec<String>>,
+    #[serde(rename = "bank-account-prefix")]
+    pub bank_account_prefixes: Option<Vec<String>>,
+    pub ledger_filter: Option<String>,
+    pub ignore_below: Option<Decimal>,
+    #[serde(rename = "ignore-desc")]
+    pub ignore_descs: Option<Vec<String>>,
+    pub commodity: Option<String>,
+    pub decimal_sep: Option<char>,
+    pub thousands_sep: Option<char>,
+    pub locale: Option<Locale>,
+    pub csv_date_format: Option<String>,
+    pub no_header: Option<bool>,
+    pub sort_csv: Option<CsvSort>,
+    pub reverse: Option<bool>,
+    pub date_window: Option<u32>,
+    #[serde(default, deserialize_with = "date")]
+    pub since: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "date")]
+    pub until: Option<NaiveDate>,
+    pub limit: Option<usize>,
+    pub auto: Option<bool>,
+    pub yes: Option<bool>,
+    pub mark: Option<Status>,
+    pub include_pending: Option<bool>,
+    #[serde(default, deserialize_with = "marker_list")]
+    pub cleared_markers: Option<Vec<Status>>,
+    pub posting_level: Option<bool>,
+    pub fuzzy: Option<bool>,
+    pub similarity: Option<u8>,
+    pub auto_accept_score: Option<u8>,
+    pub match_fields: Option<Vec<MatchField>>,
+    pub case_sensitive: Option<bool>,
+    pub ascii_fold: Option<bool>,
+    pub match_context: Option<usize>,
+    pub normalize: Option<Vec<Normalization>>,
+    pub rules: Option<String>,
+    pub payee_map: Option<String>,
+    pub template: Option<String>,
+    pub misc_account: Option<String>,
+    pub asset_account: Option<String>,
+    pub templates: Option<BTreeMap<String, String>>,
+    pub strict: Option<bool>,
+    pub strict_amount_format: Option<bool>,
+    pub dedupe: Option<bool>,
+    pub backup: Option<bool>,
+    pub sorted_insert: Option<bool>,
+    pub trim_trailing: Option<bool>,
+    pub dry_run: Option<bool>,
+    pub force: Option<bool>,
+    pub stamp_cleared: Option<bool>,
+    #[serde(default, deserialize_with = "date")]
+    pub stamp_date: Option<NaiveDate>,
+    pub checkpoint_every: Option<usize>,
+    pub report: Option<String>,
+    pub json_log: Option<String>,
+    pub rejects: Option<String>,
+    pub csv_out: Option<String>,
+    pub reverse_match: Option<bool>,
+    pub print_cleared: Option<String>,
+    #[serde(rename = "assert-balance")]
+    pub assert_balances: Option<Vec<String>>,
+    pub opening_balance: Option<String>,
+    pub closing_balance: Option<String>,
+    #[serde(rename = "action")]
+    pub actions: Option<Vec<MenuAction>>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+    pub no_color: Option<bool>,
+    pub annotate_header: Option<bool>,
+    pub group_by_day: Option<bool>,
+    pub interactive_edit_unmatched: Option<bool>,
+    pub quiet_matches: Option<bool>,
+    pub verify_with_hledger: Option<bool>,
+    pub threads: Option<usize>,
+}
+
+impl Settings {
+    /// Fills every key missing from `self` with the value from `fallback`.
+    ///
+    /// The amount columns are taken as a group, so a profile with a single
+    /// amount column replaces a debit/credit pair at the top level.
+    fn or(self, mut fallback: Settings) -> Settings {
+        if self.amount_col.is_some() || self.debit_col.is_some() || self.credit_col.is_some() {
+            fallback.amount_col = None;
+            fallback.debit_col = None;
+            fallback.credit_col = None;
+        }
+
+        macro_rules! merge {
+            ($($field:ident),* $(,)?) => {
+                Settings { $($field: self.$field.or(fallback.$field)),* }
+            };
+        }
+
+        merge!(
+            ledger,
+            syntax,
+            format,
+            encoding,
+            delimiter,
+            output,
+            in_place,
+            date_col,
+            desc_col,
+            amount_col,
+            debit_col,
+            credit_col,
+            id_col,
+            debit_sign,
+            invert_amount,
+            amount_tolerance,
+            account,
+            exclude_accounts,
+            bank_account_prefixes,
+            ledger_filter,
+            ignore_below,
+            ignore_descs,
+            commodity,
+            decimal_sep,
+            thousands_sep,
+            locale,
+            csv_date_format,
+            no_header,
+            sort_csv,
+            reverse,
+            date_window,
+            since,
+            until,
+            limit,
+            auto,
+            yes,
+            mark,
+            include_pending,
+            cleared_markers,
+            posting_level,
+            fuzzy,
+            similarity,
+            auto_accept_score,
+            match_fields,
+            case_sensitive,
+            ascii_fold,
+            match_context,
+            normalize,
+            rules,
+            payee_map,
+            template,
+            misc_account,
+            asset_account,
+            templates,
+            strict,
+            strict_amount_format,
+            dedupe,
+            backup,
+            sorted_insert,
+            trim_trailing,
+            dry_run,
+            force,
+            stamp_cleared,
+            stamp_date,
+            checkpoint_every,
+            report,
+            json_log,
+            rejects,
+            csv_out,
+            reverse_match,
+            print_cleared,
+            assert_balances,
+            opening_balance,
+            closing_balance,
+            actions,
+            verbose,
+            quiet,
+            no_color,
+            annotate_header,
+            group_by_day,
+            interactive_edit_unmatched,
+            quiet_matches,
+            verify_with_hledger,
+            threads,
+        )
+    }
+
+    /// Checks the constraints that clap enforces for the equivalent flags.
+    fn validate(&self) -> Result<(), String> {
+        if self.debit_col.is_some() != self.credit_col.is_some() {
+            return Err("debit-col and credit-col must be given together".to_string());
+        }
+        if self.amount_col.is_some() && self.debit_col.is_some() {
+            return Err("amount-col cannot be combined with debit-col/credit-col".to_string());
+        }
+        if self.locale.is_some() && (self.decimal_sep.is_some() || self.thousands_sep.is_some()) {
+            return Err("locale cannot be combined with decimal-sep/thousands-sep".to_string());
+        }
+        if self.verbose == Some(true) && self.quiet == Some(true) {
+            return Err("verbose and quiet cannot both be set".to_string());
+        }
+        if self.similarity.is_some_and(|similarity| similarity > 100) {
+            return Err("similarity must be between 0 and 100".to_string());
+        }
+        if self.auto_accept_score.is_some_and(|score| score > 100) {
+            return Err("auto-accept-score must be between 0 and 100".to_string());
+        }
+        if self.include_pending == Some(true) && self.cleared_markers.is_some() {
+            return Err("include-pending cannot be combined with cleared-markers".to_string());
+        }
+        if self.stamp_date.is_some() && self.stamp_cleared != Some(true) {
+            return Err("stamp-date requires stamp-cleared".to_string());
+        }
+        if self.match_fields.as_ref().is_some_and(Vec::is_empty) {
+            return Err("match-fields must name at least one field".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A parsed config file: top-level settings plus named profiles.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub settings: Settings,
+    pub profiles: Vec<(String, Settings)>,
+}
+
+impl Config {
+    /// Reads and parses a config file.
+    pub fn load(path: &str) -> io::Result<Config> {
+        let content = fs::read_to_string(path)?;
+        Config::parse(&content).map_err(|message| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message))
+        })
+    }
+
+    /// Parses config file text.
+    pub fn parse(content: &str) -> Result<Config, String> {
+        let mut table: toml::Table = content.parse().map_err(|error| format!("{}", error))?;
+
+        let mut profiles = Vec::new();
+        if let Some(value) = table.remove("profile") {
+            let toml::Value::Table(profile_tables) = value else {
+                return Err("`profile` must be a table of [profile.<name>] sections".to_string());
+            };
+            for (name, value) in profile_tables {
+                let settings = Settings::deserialize(value)
+                    .map_err(|error| format!("profile \"{}\": {}", name, error.message()))?;
+                profiles.push((name, settings));
+            }
+        }
+
+        let settings = Settings::deserialize(toml::Value::Table(table))
+            .map_err(|error| error.message().to_string())?;
+
+        Ok(Config { settings, profiles })
+    }
+
+    /// The settings for a run, with the named profile (if any) layered over the
+    /// top-level keys.
+    pub fn resolve(&self, profile: Option<&str>) -> Result<Settings, String> {
+        let resolved = match profile {
+            None => self.settings.clone(),
+            Some(name) => {
+                let (_, settings) = self
+                    .profiles
+                    .iter()
+                    .find(|(candidate, _)| candidate == name)
+                    .ok_or_else(|| {
+                        let known: Vec<&str> = self
+                            .profiles
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect();
+                        format!(
+                            "no profile named \"{}\" (known profiles: {})",
+                            name,
+                            if known.is_empty() {
+                                "none".to_string()
+                            } else {
+                                known.join(", ")
+                            }
+                        )
+                    })?;
+                settings.clone().or(self.settings.clone())
+            }
+        };
+
+        resolved.validate()?;
+        Ok(resolved)
+    }
+}
+
+/// The config file to use: `explicit` if given, otherwise the first of
+/// `./ledger-reconcile.toml` and `~/.config/ledger-reconcile.toml` that exists.
+pub fn find_config_file(explicit: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = explicit {
+        return Some(PathBuf::from(path));
+    }
+
+    let mut candidates = vec![PathBuf::from(CONFIG_FILE_NAME)];
+    if let Some(home) = env::var_os("HOME") {
+        candidates.push(PathBuf::from(home).join(".config").join(CONFIG_FILE_NAME));
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+ledger = "main.ledger"
+date-col = 0
+backup = true
+
+[profile.chase]
+date-col = "Posting Date"
+amount-col = "Amount"
+amount-tolerance = 0.01
+mark = "pending"
+
+[profile.amex]
+debit-col = 3
+credit-col = 4
+debit-sign = "positive"
+"#;
+
+    #[test]
+    fn test_profile_overrides_top_level_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let chase = config.resolve(Some("chase")).unwrap();
+        assert_eq!(chase.ledger.as_deref(), Some("main.ledger"));
+        assert_eq!(chase.backup, Some(true));
+        assert_eq!(
+            chase.date_col,
+            Some(ColumnSpec::Name("Posting Date".to_string()))
+        );
+        assert_eq!(chase.amount_tolerance, Decimal::from_str_exact("0.01").ok());
+        assert_eq!(chase.mark, Some(Status::Pending));
+
+        let amex = config.resolve(Some("amex")).unwrap();
+        assert_eq!(amex.date_col, Some(ColumnSpec::Index(0)));
+        assert_eq!(amex.debit_col, Some(ColumnSpec::Index(3)));
+        assert_eq!(amex.debit_sign, Some(DebitSign::Positive));
+
+        assert_eq!(config.resolve(None).unwrap().amount_col, None);
+    }
+
+    #[test]
+    fn test_reports_unknown_keys_and_profiles() {
+        let error = Config::parse("date-column = 1").unwrap_err();
+        assert!(error.contains("date-column"), "{}", error);
+
+        let config = Config::parse(CONFIG).unwrap();
+        let error = config.resolve(Some("wells")).unwrap_err();
+        assert!(error.contains("known profiles: amex, chase"), "{}", error);
+
+        let config = Config::parse("debit-col = 3").unwrap();
+        assert!(config.resolve(None).is_err());
+    }
+
+    #[test]
+    fn test_profile_amount_columns_replace_top_level_layout() {
+        let config =
+            Config::parse("debit-col = 3\ncredit-col = 4\n[profile.card]\namount-col = 2").unwrap();
+
+        let card = config.resolve(Some("card")).unwrap();
+
+        assert_eq!(card.amount_col, Some(ColumnSpec::Index(2)));
+        assert_eq!(card.debit_col, None);
+    }
+
+    #[test]
+    fn test_desc_col_may_list_several_columns() {
+        let name = |name: &str| ColumnSpec::Name(name.to_string());
+        for text in [
+            "desc-col = \"Name,Memo\"",
+            "desc-col = [\"Name\", \"Memo\"]",
+        ] {
+            let settings = Config::parse(text).unwrap().resolve(None).unwrap();
+            assert_eq!(settings.desc_col, Some(vec![name("Name"), name("Memo")]));
+        }
+        let settings = Config::parse("desc-col = 3")
+            .unwrap()
+            .resolve(None)
+            .unwrap();
+        assert_eq!(settings.desc_col, Some(vec![ColumnSpec::Index(3)]));
+        assert_eq!(Config::parse("").unwrap().settings.desc_col, None);
+    }
+
+    #[test]
+    fn test_cleared_markers_are_read_from_a_string() {
+        let settings = Config::parse("cleared-markers = \"*, !\"")
+            .unwrap()
+            .resolve(None)
+            .unwrap();
+        assert_eq!(
+            settings.cleared_markers,
+            Some(vec![Status::Cleared, Status::Pending])
+        );
+        assert!(Config::parse("cleared-markers = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_actions_are_read_from_action_tables() {
+        let config = Config::parse(
+            "[[action]]\nname = \"Transfer\"\naccount = \"Assets:Savings\"\nclear = true\n\n[[action]]\nname = \"Gift\"\naccount = \"Expenses:Gifts\"\n",
+        )
+        .unwrap();
+
+        let actions = config.resolve(None).unwrap().actions.unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].account, "Assets:Savings");
+        assert!(actions[0].clear);
+        assert!(!actions[1].clear);
+    }
+
+    #[test]
+    fn test_date_keys_are_read_from_strings() {
+        let settings = Config::parse("since = \"2025-01-01\"\nuntil = \"2025-01-31\"\nlimit = 5")
+            .unwrap()
+            .resolve(None)
+            .unwrap();
+        assert_eq!(settings.since, NaiveDate::from_ymd_opt(2025, 1, 1));
+        assert_eq!(settings.until, NaiveDate::from_ymd_opt(2025, 1, 31));
+        assert_eq!(settings.limit, Some(5));
+
+        let error = Config::parse("since = \"01/02/2025\"").unwrap_err();
+        assert!(error.contains("YYYY-MM-DD"), "{}", error);
+
+        let config = Config::parse("stamp-date = \"2025-02-01\"").unwrap();
+        assert!(config.resolve(None).is_err());
+    }
+}
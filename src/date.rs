@@ -0,0 +1,138 @@
+//! Parsing transaction dates.
+
+use chrono::NaiveDate;
+
+/// Parses a ledger-style date: `YYYY-MM-DD`, `YYYY/MM/DD` or `YYYY.MM.DD`.
+///
+/// A secondary date (`2025-01-01=2025-01-03`) is ignored.
+pub fn parse_date(raw: &str) -> Option<NaiveDate> {
+    let primary = raw.trim().split('=').next()?;
+
+    ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(primary, format).ok())
+}
+
+/// Formats tried, in order, when no statement date format is given.
+///
+/// Slash-separated dates are read month-first, as US banks export them.
+const STATEMENT_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%m/%d/%Y",
+    "%m/%d/%y",
+    "%d-%b-%Y",
+    "%d %b %Y",
+    "%b %d, %Y",
+    "%d.%m.%Y",
+    "%Y%m%d",
+];
+
+/// Parses a statement date using `format` (a chrono format string) if given,
+/// or else the first of a few common bank formats that fits.
+pub fn parse_statement_date(raw: &str, format: Option<&str>) -> Option<NaiveDate> {
+    let raw = raw.trim();
+    match format {
+        Some(format) => NaiveDate::parse_from_str(raw, format).ok(),
+        None => STATEMENT_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(raw, format).ok()),
+    }
+}
+
+/// The first common statement format that reads every sample.
+///
+/// Formats that reproduce the samples exactly are preferred, which tells
+/// `01/02/25` (`%m/%d/%y`) apart from `01/02/2025` (`%m/%d/%Y`).
+pub fn detect_statement_format(samples: &[&str]) -> Option<&'static str> {
+    let samples: Vec<&str> = samples
+        .iter()
+        .map(|sample| sample.trim())
+        .filter(|sample| !sample.is_empty())
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+
+    let reads = |format: &str, exact: bool| {
+        samples.iter().all(|sample| {
+            NaiveDate::parse_from_str(sample, format)
+                .is_ok_and(|date| !exact || date.format(format).to_string() == *sample)
+        })
+    };
+
+    STATEMENT_FORMATS
+        .iter()
+        .find(|format| reads(format, true))
+        .or_else(|| STATEMENT_FORMATS.iter().find(|format| reads(format, false)))
+        .copied()
+}
+
+/// How many days apart two dates are, whichever comes first.
+pub fn days_apart(a: NaiveDate, b: NaiveDate) -> u64 {
+    (a - b).num_days().unsigned_abs()
+}
+
+/// Whether two dates are at most `window` days apart.
+pub fn within_window(a: NaiveDate, b: NaiveDate, window: u32) -> bool {
+    days_apart(a, b) <= u64::from(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2025-01-02"), Some(ymd(2025, 1, 2)));
+        assert_eq!(parse_date("2025/01/02"), Some(ymd(2025, 1, 2)));
+        assert_eq!(parse_date("2025-01-02=2025-01-04"), Some(ymd(2025, 1, 2)));
+        assert_eq!(parse_date("Groceries"), None);
+    }
+
+    #[test]
+    fn test_statement_date_formats_detected() {
+        let expected = Some(ymd(2025, 1, 2));
+
+        assert_eq!(parse_statement_date("01/02/2025", None), expected);
+        assert_eq!(parse_statement_date("02-Jan-2025", None), expected);
+        assert_eq!(parse_statement_date("2025-01-02", None), expected);
+        assert_eq!(parse_statement_date("20250102", None), expected);
+    }
+
+    #[test]
+    fn test_statement_date_explicit_format() {
+        assert_eq!(
+            parse_statement_date("02/01/2025", Some("%d/%m/%Y")),
+            Some(ymd(2025, 1, 2))
+        );
+        assert_eq!(parse_statement_date("2025-01-02", Some("%d/%m/%Y")), None);
+    }
+
+    #[test]
+    fn test_within_window() {
+        let recorded = ymd(2025, 1, 30);
+
+        assert!(within_window(recorded, ymd(2025, 1, 30), 0));
+        assert!(!within_window(recorded, ymd(2025, 1, 31), 0));
+        assert!(within_window(recorded, ymd(2025, 1, 31), 1));
+        assert!(within_window(recorded, ymd(2025, 2, 2), 3));
+        assert!(!within_window(recorded, ymd(2025, 2, 2), 1));
+    }
+
+    #[test]
+    fn test_detect_statement_format() {
+        assert_eq!(
+            detect_statement_format(&["01/02/2025", "12/31/2024"]),
+            Some("%m/%d/%Y")
+        );
+        assert_eq!(detect_statement_format(&["01/02/25", ""]), Some("%m/%d/%y"));
+        assert_eq!(detect_statement_format(&["1/2/2025"]), Some("%m/%d/%Y"));
+        assert_eq!(detect_statement_format(&["Groceries"]), None);
+        assert_eq!(detect_statement_format(&[]), None);
+    }
+}
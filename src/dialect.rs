@@ -0,0 +1,52 @@
+//! The ledger syntaxes transactions are read from and written in.
+//!
+//! A [`LedgerDialect`] knows where a transaction begins, how its header
+//! carries a status, payee and code, and how a new entry is written. The
+//! block structure (a header and the indented lines under it) and the posting
+//! syntax are shared by every dialect; see [`ledger`](crate::ledger).
+
+use crate::ledger::{Status, Transaction};
+use std::borrow::Cow;
+
+/// One ledger syntax.
+pub trait LedgerDialect {
+    /// Whether `line` opens a transaction.
+    fn is_header(&self, line: &str) -> bool;
+
+    /// What a header line must look like, for error messages.
+    fn header_rule(&self) -> &'static str;
+
+    /// Groups ledger lines into transactions.
+    fn parse_transactions(&self, lines: &[String]) -> Vec<Transaction>;
+
+    /// The status a header line carries, if any.
+    fn status(&self, header: &str) -> Option<Status>;
+
+    /// The payee/description from a header line, without the date, status,
+    /// code or trailing comment.
+    fn description<'a>(&self, header: &'a str) -> Cow<'a, str>;
+
+    /// The transaction code on a header line, if the dialect has them.
+    fn code<'a>(&self, _header: &'a str) -> Option<&'a str> {
+        None
+    }
+
+    /// Whether an indented line under a header holds something other than a
+    /// posting.
+    fn is_metadata(&self, _line: &str) -> bool {
+        false
+    }
+
+    /// Rewrites a header line so it carries `status`, replacing any status it
+    /// already has. Applying it twice gives the same line as applying it once.
+    fn mark_cleared(&self, header: &str, status: Status) -> String;
+
+    /// How a new entry writes `commodity`.
+    fn commodity<'a>(&self, commodity: &'a str) -> &'a str {
+        commodity
+    }
+
+    /// A new, unmarked entry made of its header and the posting lines, which
+    /// are already laid out.
+    fn render(&self, date: &str, description: &str, postings: &[String]) -> String;
+}
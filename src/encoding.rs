@@ -0,0 +1,121 @@
+//! Decoding statements that banks export in something other than UTF-8.
+//!
+//! A byte-order mark settles the encoding and is dropped. Without one, a file
+//! is read as UTF-8 and, failing that, as Windows-1252, the usual encoding of
+//! exports from Windows banking software.
+
+use clap::ValueEnum;
+use log::warn;
+use serde::Deserialize;
+use std::io;
+
+/// The text encoding of a statement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Utf8,
+    Utf16le,
+    Utf16be,
+    /// Windows' Western European code page, a superset of Latin-1.
+    Windows1252,
+}
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+/// The characters Windows-1252 puts at 0x80 to 0x9F, where Latin-1 has
+/// control codes. The five bytes it leaves undefined keep their control code.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// The encoding a byte-order mark at the start of `bytes` names, with the
+/// mark's length.
+fn sniff(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&UTF8_BOM) {
+        Some((Encoding::Utf8, UTF8_BOM.len()))
+    } else if bytes.starts_with(&UTF16LE_BOM) {
+        Some((Encoding::Utf16le, UTF16LE_BOM.len()))
+    } else if bytes.starts_with(&UTF16BE_BOM) {
+        Some((Encoding::Utf16be, UTF16BE_BOM.len()))
+    } else {
+        None
+    }
+}
+
+/// Decodes `bytes` as `encoding`, or as the byte-order mark says, or as UTF-8
+/// falling back to Windows-1252. Only an explicit encoding the bytes don't
+/// fit is an error; a byte-order mark is never part of the text.
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> io::Result<String> {
+    let sniffed = sniff(bytes);
+    let body = match (sniffed, encoding) {
+        (Some((marked, length)), None) => return decode_as(&bytes[length..], marked),
+        (Some((marked, length)), Some(wanted)) if marked == wanted => &bytes[length..],
+        _ => bytes,
+    };
+    match encoding {
+        Some(encoding) => decode_as(body, encoding),
+        None => decode_as(body, Encoding::Utf8).or_else(|err| {
+            warn!("{}; reading it as Windows-1252 instead.", err);
+            decode_as(body, Encoding::Windows1252)
+        }),
+    }
+}
+
+/// Decodes `bytes`, which carry no byte-order mark, as `encoding`.
+fn decode_as(bytes: &[u8], encoding: Encoding) -> io::Result<String> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    match encoding {
+        Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+            .map_err(|err| invalid(format!("not valid UTF-8: {}", err))),
+        Encoding::Utf16le | Encoding::Utf16be => {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(invalid(
+                    "UTF-16 text has an odd number of bytes".to_string(),
+                ));
+            }
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| match encoding {
+                    Encoding::Utf16le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16(&units).map_err(|err| invalid(format!("not valid UTF-16: {}", err)))
+        }
+        Encoding::Windows1252 => Ok(bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+                _ => char::from(byte),
+            })
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bom_is_dropped_and_picks_the_encoding() {
+        let utf8 = [&UTF8_BOM[..], "Date,Café".as_bytes()].concat();
+        assert_eq!(decode(&utf8, None).unwrap(), "Date,Café");
+        assert_eq!(decode(&utf8, Some(Encoding::Utf8)).unwrap(), "Date,Café");
+
+        let utf16: Vec<u8> = UTF16LE_BOM
+            .into_iter()
+            .chain("Date,€5".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(decode(&utf16, None).unwrap(), "Date,€5");
+    }
+
+    #[test]
+    fn test_windows_1252_is_the_fallback() {
+        let bytes = b"Caf\xe9 \x80 4,50 \x93ok\x94";
+        assert_eq!(decode(bytes, None).unwrap(), "Café € 4,50 “ok”");
+        assert!(decode(bytes, Some(Encoding::Utf8)).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! The ways a reconciliation run can fail.
+
+use std::io;
+use thiserror::Error;
+
+/// Why a reconciliation run failed.
+#[derive(Debug, Error)]
+pub enum ReconcileError {
+    /// A file could not be read or written.
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A statement row could not be read as CSV, or is missing mapped columns.
+    #[error("{path}: CSV row {line} {message}")]
+    Csv {
+        path: String,
+        line: u64,
+        message: String,
+    },
+
+    /// A column given by name or index is not in the CSV header.
+    #[error("{path}: {message}")]
+    ColumnNotFound { path: String, message: String },
+
+    /// A statement amount could not be read, under `--strict`.
+    #[error("{path}: record {record}: {value:?} is not an amount")]
+    AmountParse {
+        path: String,
+        /// 1-based position of the record in its file.
+        record: usize,
+        value: String,
+    },
+
+    /// A statement date could not be read, under `--strict`.
+    #[error("{path}: record {record}: {value:?} is not a date")]
+    DateParse {
+        path: String,
+        /// 1-based position of the record in its file.
+        record: usize,
+        value: String,
+    },
+
+    /// A line of the rules file or payee map is malformed.
+    #[error("{path}: {message}")]
+    Rules { path: String, message: String },
+
+    /// An entry template is empty or uses an unknown placeholder.
+    #[error("{path}: {message}")]
+    Template { path: String, message: String },
+
+    /// The cleared balance of an account differs from the `--assert-balance`
+    /// value.
+    #[error("the cleared balance of {account} is {actual}, but {expected} was asserted")]
+    BalanceMismatch {
+        account: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// The `--opening-balance` plus this run's cleared postings to the account
+    /// differs from the `--closing-balance`.
+    #[error("{account} closes at {actual} after this run, but the statement closes at {expected}, {difference} apart")]
+    ClosingBalanceMismatch {
+        account: String,
+        expected: String,
+        actual: String,
+        difference: String,
+    },
+
+    /// The options, paths or checkpoint given cannot be used together.
+    #[error("{0}")]
+    InvalidOptions(String),
+}
+
+impl ReconcileError {
+    /// Wraps an IO error on `path`, for use with `map_err`.
+    pub(crate) fn io(path: &str) -> impl FnOnce(io::Error) -> ReconcileError + '_ {
+        move |source| ReconcileError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
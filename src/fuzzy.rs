@@ -0,0 +1,79 @@
+//! Fuzzy comparison of transaction descriptions.
+
+use std::collections::BTreeSet;
+
+/// A description's distinct lowercase alphanumeric tokens, split once so it
+/// can be compared against many others.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tokens(BTreeSet<String>);
+
+impl Tokens {
+    pub fn new(text: &str) -> Tokens {
+        Tokens(
+            text.split(|c: char| !c.is_alphanumeric())
+                .filter(|token| !token.is_empty())
+                .map(str::to_lowercase)
+                .collect(),
+        )
+    }
+
+    /// Token-set similarity of the descriptions these tokens were split
+    /// from, from 0 (unrelated) to 100 (identical).
+    ///
+    /// Word order and repeated words are ignored, and a description whose
+    /// words are all contained in the other scores 100. This suits bank
+    /// descriptions like `SQ *COFFEE SHOP 123`, which pad the payee with extra
+    /// noise.
+    pub fn ratio(&self, other: &Tokens) -> u8 {
+        let (a, b) = (&self.0, &other.0);
+        let join = |set: BTreeSet<&String>| {
+            set.into_iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let common = join(a.intersection(b).collect());
+        let only_a = join(a.difference(b).collect());
+        let only_b = join(b.difference(a).collect());
+
+        let combined_a = format!("{} {}", common, only_a).trim().to_string();
+        let combined_b = format!("{} {}", common, only_b).trim().to_string();
+
+        let best = ratio(&common, &combined_a)
+            .max(ratio(&common, &combined_b))
+            .max(ratio(&combined_a, &combined_b));
+
+        best.round() as u8
+    }
+}
+
+fn ratio(a: &str, b: &str) -> f64 {
+    strsim::normalized_levenshtein(a, b) * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_set_ratio(a: &str, b: &str) -> u8 {
+        Tokens::new(a).ratio(&Tokens::new(b))
+    }
+
+    #[test]
+    fn test_token_set_ratio() {
+        assert_eq!(token_set_ratio("Coffee Shop", "coffee shop"), 100);
+        assert_eq!(token_set_ratio("SQ *COFFEE SHOP 123", "Coffee Shop"), 100);
+        assert_eq!(token_set_ratio("shop coffee", "Coffee Shop"), 100);
+        assert!(token_set_ratio("Coffee Shops", "Coffee Shop") >= 90);
+        assert!(token_set_ratio("Rent", "Coffee Shop") < 30);
+    }
+
+    #[test]
+    fn test_tokens_ignore_order_case_and_repeats() {
+        assert_eq!(
+            Tokens::new("SQ *COFFEE SHOP 123"),
+            Tokens::new("shop 123 coffee sq coffee")
+        );
+        assert_eq!(Tokens::new(" - "), Tokens::default());
+    }
+}
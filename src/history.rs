@@ -0,0 +1,134 @@
+//! Undo support for changes made to the ledger during a run.
+
+use crate::report::Summary;
+
+/// A single change applied to the ledger lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Lines were rewritten to mark a transaction or some of its postings;
+    /// `previous` holds each line's index and old contents.
+    Marked { previous: Vec<(usize, String)> },
+    /// A new entry was pushed onto the end of the ledger.
+    Appended,
+}
+
+impl Change {
+    /// The indices of the lines this change rewrote.
+    pub fn lines(&self) -> Vec<usize> {
+        match self {
+            Change::Marked { previous } => previous.iter().map(|(line, _)| *line).collect(),
+            Change::Appended => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    record_index: usize,
+    change: Change,
+    summary: Summary,
+}
+
+/// What an undo reverted, so the caller can resume from the right place.
+#[derive(Clone, Debug)]
+pub struct Undone {
+    /// Index of the CSV record whose decision was undone.
+    pub record_index: usize,
+    /// The summary as it was before that decision.
+    pub summary: Summary,
+    /// A short human-readable description of the reverted change.
+    pub description: String,
+}
+
+/// A stack of applied changes, most recent last.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+}
+
+impl History {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records a change made while handling `record_index`, along with the
+    /// summary as it stood just before the change.
+    pub fn push(&mut self, record_index: usize, change: Change, summary: &Summary) {
+        self.entries.push(Entry {
+            record_index,
+            change,
+            summary: summary.clone(),
+        });
+    }
+
+    /// Reverts the most recent change in `ledger_lines`.
+    pub fn undo(&mut self, ledger_lines: &mut Vec<String>) -> Option<Undone> {
+        let entry = self.entries.pop()?;
+
+        let description = match entry.change {
+            Change::Marked { previous } => {
+                let restored: Vec<String> = previous
+                    .iter()
+                    .map(|(line, contents)| format!("line {} to \"{}\"", line + 1, contents))
+                    .collect();
+                for (line, contents) in previous {
+                    ledger_lines[line] = contents;
+                }
+                format!("restored {}", restored.join(", "))
+            }
+            Change::Appended => {
+                let removed = ledger_lines.pop().unwrap_or_default();
+                format!(
+                    "removed added entry \"{}\"",
+                    removed.lines().next().unwrap_or_default()
+                )
+            }
+        };
+
+        Some(Undone {
+            record_index: entry.record_index,
+            summary: entry.summary,
+            description,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_reverts_in_reverse_order() {
+        let mut lines: Vec<String> = vec!["2025-01-01 Rent".into(), "    Assets:Bank".into()];
+        let mut history = History::default();
+        let mut summary = Summary::default();
+
+        history.push(
+            0,
+            Change::Marked {
+                previous: vec![(0, lines[0].clone())],
+            },
+            &summary,
+        );
+        lines[0] = "* 2025-01-01 Rent".into();
+        summary.cleared += 1;
+
+        history.push(1, Change::Appended, &summary);
+        lines.push("2025-01-02 Coffee\n    Expenses:Food  $4".into());
+        summary.added += 1;
+
+        let undone = history.undo(&mut lines).unwrap();
+        assert_eq!(undone.record_index, 1);
+        assert_eq!(undone.summary.added, 0);
+        assert_eq!(undone.summary.cleared, 1);
+        assert_eq!(lines.len(), 2);
+
+        let undone = history.undo(&mut lines).unwrap();
+        assert_eq!(undone.record_index, 0);
+        assert_eq!(undone.summary, Summary::default());
+        assert_eq!(lines[0], "2025-01-01 Rent");
+
+        assert!(history.is_empty());
+        assert!(history.undo(&mut lines).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+//! hledger's transaction syntax, which ledger-cli reads as well.
+//!
+//! A header starts in column zero with its date, and may carry a status
+//! marker (`*` or `!`), before the date or after it as hledger prints them,
+//! and a `(code)` before the description:
+//!
+//! ```text
+//! * 2025-01-03 (1042) Blue Bottle  ; morning
+//!     Expenses:Coffee  $4.50
+//!     Assets:Checking
+//! ```
+
+use crate::dialect::LedgerDialect;
+use crate::ledger::{self, Status, Syntax, Transaction};
+use std::borrow::Cow;
+
+/// The hledger dialect.
+pub struct Hledger;
+
+/// The header after its date, with status markers on either side dropped.
+fn after_date(header: &str) -> Option<&str> {
+    let rest = header
+        .trim_start_matches(['*', '!'])
+        .trim_start()
+        .split_once(char::is_whitespace)?
+        .1;
+    Some(rest.trim_start_matches(['*', '!']).trim_start())
+}
+
+impl LedgerDialect for Hledger {
+    /// A header starts in column zero with a date, optionally preceded by a
+    /// status marker.
+    fn is_header(&self, line: &str) -> bool {
+        let rest = line
+            .strip_prefix('*')
+            .or_else(|| line.strip_prefix('!'))
+            .map(str::trim_start)
+            .unwrap_or(line);
+
+        rest.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn header_rule(&self) -> &'static str {
+        "the first line must start with the transaction date"
+    }
+
+    fn parse_transactions(&self, lines: &[String]) -> Vec<Transaction> {
+        ledger::blocks(lines, Syntax::Hledger, |line| self.is_header(line))
+    }
+
+    fn status(&self, header: &str) -> Option<Status> {
+        header
+            .chars()
+            .next()
+            .and_then(Status::from_marker)
+            .or_else(|| ledger::marker_after_date(header).map(|(_, status)| status))
+    }
+
+    fn description<'a>(&self, header: &'a str) -> Cow<'a, str> {
+        let header = header.split(';').next().unwrap_or_default();
+        let mut rest = after_date(header).unwrap_or_default();
+
+        if rest.starts_with('(') {
+            if let Some(close) = rest.find(')') {
+                rest = &rest[close + 1..];
+            }
+        }
+
+        Cow::Borrowed(rest.trim())
+    }
+
+    /// The code in parentheses, e.g. `1042` in `2025-01-01 * (1042) Coffee Shop`.
+    fn code<'a>(&self, header: &'a str) -> Option<&'a str> {
+        let rest = after_date(header)?.strip_prefix('(')?;
+        let code = rest[..rest.find(')')?].trim();
+        (!code.is_empty()).then_some(code)
+    }
+
+    fn mark_cleared(&self, header: &str, status: Status) -> String {
+        ledger::with_status(header, status)
+    }
+
+    fn render(&self, date: &str, description: &str, postings: &[String]) -> String {
+        let mut entry = format!("{} {}", date, description);
+        for posting in postings {
+            entry.push('\n');
+            entry.push_str(posting);
+        }
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn lines(content: &str) -> Vec<String> {
+        content.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_marking_and_rendering_are_byte_for_byte() {
+        let journal = lines(
+            "; opening balances
+2025-01-01 Opening
+    Assets:Checking  $100.00
+    Equity
+
+* 2025-01-02 (1042) Coffee Shop  ; morning
+    Expenses:Food  $4.00
+    Assets:Checking
+2025-01-03 ! Rent\t; tabbed
+    Expenses:Rent\t$1000.00
+    Assets:Checking",
+        );
+
+        let transactions = Hledger.parse_transactions(&journal);
+        let headers: Vec<&str> = transactions
+            .iter()
+            .map(|transaction| journal[transaction.start].as_str())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "2025-01-01 Opening",
+                "* 2025-01-02 (1042) Coffee Shop  ; morning",
+                "2025-01-03 ! Rent\t; tabbed"
+            ]
+        );
+        assert_eq!(
+            transactions.iter().map(|t| t.end).collect::<Vec<_>>(),
+            [4, 8, 11]
+        );
+
+        assert_eq!(Hledger.status(headers[1]), Some(Status::Cleared));
+        assert_eq!(Hledger.status(headers[2]), Some(Status::Pending));
+        assert_eq!(Hledger.code(headers[1]), Some("1042"));
+        assert_eq!(Hledger.description(headers[1]), "Coffee Shop");
+        assert_eq!(Hledger.description(headers[2]), "Rent");
+
+        assert_eq!(
+            Hledger.mark_cleared(headers[0], Status::Cleared),
+            "* 2025-01-01 Opening"
+        );
+        assert_eq!(
+            Hledger.mark_cleared(headers[1], Status::Pending),
+            "! 2025-01-02 (1042) Coffee Shop  ; morning"
+        );
+        assert_eq!(
+            Hledger.mark_cleared(headers[2], Status::Cleared),
+            "2025-01-03 * Rent\t; tabbed"
+        );
+        assert_eq!(
+            Hledger.mark_cleared(
+                &Hledger.mark_cleared(headers[0], Status::Cleared),
+                Status::Cleared
+            ),
+            "* 2025-01-01 Opening"
+        );
+
+        assert_eq!(
+            Hledger.render(
+                "2025-01-04",
+                "bakery",
+                &[
+                    "    Expenses:Food  $3.00".to_string(),
+                    "    Assets:Bank  -$3.00".to_string()
+                ]
+            ),
+            "2025-01-04 bakery\n    Expenses:Food  $3.00\n    Assets:Bank  -$3.00"
+        );
+    }
+
+    #[test]
+    fn test_status_is_read_from_column_zero_headers_only() {
+        let journal = lines(
+            "2025-01-01 Rent
+    Expenses:Rent  $900.00
+    * 2025-01-02 indented, so a posting of Rent
+    Assets:Bank
+*2025-01-03 Tight marker
+    Expenses:Food  $4.00
+    Assets:Bank
+  ! 2025-01-04 Indented after a posting
+    Assets:Bank
+! 2025-01-05 Pending
+    Expenses:Food  $3.00
+    Assets:Bank",
+        );
+
+        let transactions = Hledger.parse_transactions(&journal);
+        let statuses: Vec<(usize, Option<Status>)> = transactions
+            .iter()
+            .map(|transaction| (transaction.start, transaction.status(&journal)))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (0, None),
+                (4, Some(Status::Cleared)),
+                (9, Some(Status::Pending))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indented_header_after_a_blank_line_starts_a_transaction() {
+        let journal = lines(
+            "  2025-01-01 Indented
+    Expenses:Rent  $900.00
+    Assets:Bank
+
+  * 2025-01-02 Indented and cleared
+    Expenses:Food  $4.00
+    Assets:Bank
+
+\t2025-01-03 ! Tabbed
+    Expenses:Food  $3.00
+    Assets:Bank",
+        );
+
+        let transactions = Hledger.parse_transactions(&journal);
+        let found: Vec<(usize, usize, Option<Status>, String)> = transactions
+            .iter()
+            .map(|transaction| {
+                (
+                    transaction.start,
+                    transaction.end,
+                    transaction.status(&journal),
+                    transaction.description(&journal).into_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, 3, None, "Indented".to_string()),
+                (
+                    4,
+                    7,
+                    Some(Status::Cleared),
+                    "Indented and cleared".to_string()
+                ),
+                (8, 11, Some(Status::Pending), "Tabbed".to_string())
+            ]
+        );
+        assert_eq!(
+            transactions[0].date(&journal),
+            NaiveDate::from_ymd_opt(2025, 1, 1)
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Looking up ledger candidates by date and amount, or by transaction code,
+//! instead of scanning them all.
+
+use chrono::{Days, NaiveDate};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// What a candidate is indexed by.
+#[derive(Clone, Debug, Default)]
+pub struct Keys {
+    pub date: Option<NaiveDate>,
+    pub amounts: Vec<Decimal>,
+    /// The `(code)` from the transaction header.
+    pub code: Option<String>,
+}
+
+/// Candidate positions bucketed by `(date, absolute amount)` and by code.
+///
+/// Candidates without a parseable date or without any posting amount can only
+/// be matched textually, so they are kept aside and returned by every lookup.
+#[derive(Clone, Debug, Default)]
+pub struct CandidateIndex {
+    buckets: HashMap<(NaiveDate, Decimal), Vec<usize>>,
+    codes: HashMap<String, Vec<usize>>,
+    unindexed: Vec<usize>,
+    len: usize,
+}
+
+impl CandidateIndex {
+    /// Builds the index from each candidate's keys, in order.
+    pub fn build<I>(candidates: I) -> CandidateIndex
+    where
+        I: IntoIterator<Item = Keys>,
+    {
+        let mut index = CandidateIndex::default();
+
+        for (position, keys) in candidates.into_iter().enumerate() {
+            let Keys {
+                date,
+                amounts,
+                code,
+            } = keys;
+            index.len += 1;
+            if let Some(code) = code {
+                index.codes.entry(code).or_default().push(position);
+            }
+            match date {
+                Some(date) if !amounts.is_empty() => {
+                    for amount in amounts {
+                        let bucket = index.buckets.entry((date, key(amount))).or_default();
+                        if bucket.last() != Some(&position) {
+                            bucket.push(position);
+                        }
+                    }
+                }
+                _ => index.unindexed.push(position),
+            }
+        }
+
+        index
+    }
+
+    /// The positions of the candidates whose code is exactly `code`.
+    pub fn by_code(&self, code: &str) -> &[usize] {
+        self.codes.get(code).map_or(&[], Vec::as_slice)
+    }
+
+    /// The positions, in ascending order, of every candidate that could have
+    /// exactly `amount` on a date within `window` days of `date`.
+    ///
+    /// Returns `None` when the window spans more days than there are
+    /// candidates, since scanning them all is then cheaper.
+    pub fn lookup(&self, date: NaiveDate, amount: Decimal, window: u32) -> Option<Vec<usize>> {
+        if u64::from(window) * 2 + 1 > self.len as u64 {
+            return None;
+        }
+
+        let amount = key(amount);
+        let first = date
+            .checked_sub_days(Days::new(window.into()))
+            .unwrap_or(NaiveDate::MIN);
+        let mut positions = self.unindexed.clone();
+        for day in first.iter_days().take(window as usize * 2 + 1) {
+            if let Some(bucket) = self.buckets.get(&(day, amount)) {
+                positions.extend(bucket);
+            }
+        }
+
+        positions.sort_unstable();
+        positions.dedup();
+        Some(positions)
+    }
+}
+
+/// Amounts are matched by magnitude, and `50` and `50.00` must share a bucket.
+fn key(amount: Decimal) -> Decimal {
+    amount.abs().normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2025, 1, day)
+    }
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str_exact(value).unwrap()
+    }
+
+    fn keys(date: Option<NaiveDate>, amounts: &[&str]) -> Keys {
+        Keys {
+            date,
+            amounts: amounts.iter().map(|amount| dec(amount)).collect(),
+            code: None,
+        }
+    }
+
+    #[test]
+    fn test_lookup_spans_the_date_window() {
+        let index = CandidateIndex::build([
+            keys(ymd(1), &["-50.00", "50.00"]),
+            keys(ymd(3), &["50"]),
+            keys(ymd(3), &["12.00"]),
+            keys(None, &["50.00"]),
+            keys(ymd(9), &[]),
+            keys(ymd(20), &["50"]),
+        ]);
+
+        assert_eq!(
+            index.lookup(ymd(2).unwrap(), dec("50.0"), 0),
+            Some(vec![3, 4])
+        );
+        assert_eq!(
+            index.lookup(ymd(2).unwrap(), dec("-50"), 1),
+            Some(vec![0, 1, 3, 4])
+        );
+        assert_eq!(index.lookup(ymd(2).unwrap(), dec("50"), 3), None);
+    }
+
+    #[test]
+    fn test_by_code() {
+        let index = CandidateIndex::build([
+            Keys {
+                code: Some("1001".to_string()),
+                ..keys(ymd(1), &["5"])
+            },
+            keys(ymd(1), &["5"]),
+            Keys {
+                code: Some("1002".to_string()),
+                ..Keys::default()
+            },
+        ]);
+
+        assert_eq!(index.by_code("1002"), [2]);
+        assert!(index.by_code("1003").is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Describing an unfamiliar CSV export and suggesting a column mapping for it.
+
+use crate::{amount, columns, date};
+use std::fmt::Write;
+
+/// How many data rows are sampled when guessing column roles.
+const SAMPLE_ROWS: usize = 5;
+
+/// A CSV column with the values sampled from it.
+struct Column {
+    index: usize,
+    header: String,
+    samples: Vec<String>,
+}
+
+impl Column {
+    fn values(&self) -> impl Iterator<Item = &str> {
+        self.samples
+            .iter()
+            .map(|sample| sample.trim())
+            .filter(|sample| !sample.is_empty())
+    }
+
+    fn header_has(&self, words: &[&str]) -> bool {
+        let header = self.header.to_lowercase();
+        header
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| words.contains(&word))
+    }
+
+    fn is_date(&self) -> bool {
+        self.values().next().is_some()
+            && self
+                .values()
+                .all(|value| date::parse_statement_date(value, None).is_some())
+    }
+
+    /// Numeric, allowing blank cells as in split debit/credit exports.
+    ///
+    /// [`amount::parse_amount`] ignores letters, so the characters are checked
+    /// first to keep descriptions like `STORE 123` out.
+    fn is_amount(&self) -> bool {
+        self.values().all(|value| {
+            value
+                .chars()
+                .all(|c| c.is_ascii_digit() || " .,+-()$€£".contains(c))
+                && amount::parse_amount(value, amount::Separators::default()).is_some()
+        }) && !self.is_date()
+    }
+
+    fn average_length(&self) -> usize {
+        let lengths: Vec<usize> = self.values().map(str::len).collect();
+        lengths.iter().sum::<usize>() / lengths.len().max(1)
+    }
+}
+
+/// The role guessed for each column, by index.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Guess {
+    date: Option<usize>,
+    date_format: Option<&'static str>,
+    description: Option<usize>,
+    amount: Option<usize>,
+    debit_credit: Option<(usize, usize)>,
+    id: Option<usize>,
+}
+
+fn guess(columns: &[Column]) -> Guess {
+    let dates: Vec<&Column> = columns.iter().filter(|column| column.is_date()).collect();
+    let date = dates
+        .iter()
+        .find(|column| column.header_has(&["date", "posted"]))
+        .or(dates.first())
+        .copied();
+
+    let amounts: Vec<&Column> = columns
+        .iter()
+        .filter(|column| column.is_amount() && !column.header_has(&["balance"]))
+        .collect();
+    let named = |words: &[&str]| {
+        amounts
+            .iter()
+            .find(|column| column.header_has(words))
+            .map(|column| column.index)
+    };
+    let debit_credit = named(&["debit", "withdrawal", "withdrawals"])
+        .zip(named(&["credit", "deposit", "deposits"]));
+    let amount = match debit_credit {
+        Some(_) => None,
+        None => named(&["amount"]).or(amounts
+            .iter()
+            .find(|column| !column.header_has(&["id", "ref", "reference", "number"]))
+            .map(|column| column.index)),
+    };
+
+    let id = columns
+        .iter()
+        .find(|column| column.header_has(&["id", "fitid", "ref", "reference"]))
+        .map(|column| column.index);
+
+    let taken = |index: usize| {
+        Some(index) == date.map(|column| column.index)
+            || Some(index) == amount
+            || Some(index) == id
+            || debit_credit.is_some_and(|(debit, credit)| index == debit || index == credit)
+    };
+    let free: Vec<&Column> = columns
+        .iter()
+        .filter(|column| !taken(column.index) && !column.is_amount())
+        .collect();
+    let description = [
+        "description",
+        "desc",
+        "payee",
+        "name",
+        "memo",
+        "narrative",
+        "details",
+    ]
+    .iter()
+    .find_map(|word| free.iter().find(|column| column.header_has(&[word])))
+    .or(free.iter().max_by_key(|column| column.average_length()))
+    .map(|column| column.index);
+
+    Guess {
+        date: date.map(|column| column.index),
+        date_format: date.and_then(|column| {
+            let samples: Vec<&str> = column.values().collect();
+            date::detect_statement_format(&samples)
+        }),
+        description,
+        amount,
+        debit_credit,
+        id,
+    }
+}
+
+/// Describes the columns of a CSV export and suggests a config profile named
+/// `profile` for it.
+pub fn inspect(name: &str, profile: &str, content: &str) -> Result<String, String> {
+    let delimiter = columns::detect_delimiter(content);
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(|error| error.to_string())?.clone();
+
+    let mut columns: Vec<Column> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| Column {
+            index,
+            header: header.trim().to_string(),
+            samples: Vec::new(),
+        })
+        .collect();
+    for record in reader.records().take(SAMPLE_ROWS) {
+        let record = record.map_err(|error| error.to_string())?;
+        for column in &mut columns {
+            column
+                .samples
+                .push(record.get(column.index).unwrap_or_default().to_string());
+        }
+    }
+
+    let guess = guess(&columns);
+    let role = |index: usize| {
+        if Some(index) == guess.date {
+            "date"
+        } else if Some(index) == guess.description {
+            "description"
+        } else if Some(index) == guess.amount {
+            "amount"
+        } else if guess.debit_credit.is_some_and(|(debit, _)| debit == index) {
+            "debit"
+        } else if guess
+            .debit_credit
+            .is_some_and(|(_, credit)| credit == index)
+        {
+            "credit"
+        } else if Some(index) == guess.id {
+            "id"
+        } else {
+            ""
+        }
+    };
+
+    let header_width = columns
+        .iter()
+        .map(|column| column.header.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    writeln!(out, "Columns of {}:", name).unwrap();
+    for column in &columns {
+        let example = column.values().next().unwrap_or_default();
+        let role = role(column.index);
+        let line = format!(
+            "  {:>2}  {:<header_width$}  {:<20}{}",
+            column.index,
+            column.header,
+            example,
+            if role.is_empty() {
+                String::new()
+            } else {
+                format!("  <- {}", role)
+            },
+        );
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    let key = |index: usize| toml::Value::String(columns[index].header.clone()).to_string();
+    writeln!(
+        out,
+        "\nSuggested config (add to ledger-reconcile.toml and run with --profile {}):\n",
+        profile
+    )
+    .unwrap();
+    writeln!(out, "[profile.{}]", profile).unwrap();
+    if delimiter != b',' {
+        let delimiter = toml::Value::String(char::from(delimiter).to_string());
+        writeln!(out, "delimiter = {}", delimiter).unwrap();
+    }
+    match guess.date {
+        Some(index) => writeln!(out, "date-col = {}", key(index)).unwrap(),
+        None => writeln!(out, "# date-col = ?  (no column of dates found)").unwrap(),
+    }
+    if let Some(format) = guess.date_format {
+        writeln!(out, "csv-date-format = \"{}\"", format).unwrap();
+    }
+    match guess.description {
+        Some(index) => writeln!(out, "desc-col = {}", key(index)).unwrap(),
+        None => writeln!(out, "# desc-col = ?").unwrap(),
+    }
+    match (guess.amount, guess.debit_credit) {
+        (_, Some((debit, credit))) => {
+            writeln!(out, "debit-col = {}", key(debit)).unwrap();
+            writeln!(out, "credit-col = {}", key(credit)).unwrap();
+        }
+        (Some(index), None) => writeln!(out, "amount-col = {}", key(index)).unwrap(),
+        (None, None) => writeln!(out, "# amount-col = ?  (no column of amounts found)").unwrap(),
+    }
+    if let Some(index) = guess.id {
+        writeln!(out, "id-col = {}", key(index)).unwrap();
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_guesses_single_amount_layout() {
+        let content = "Details,Posting Date,Description,Amount,Type,Balance
+DEBIT,01/02/2025,AMAZON MKTP US 123,-25.00,ACH_DEBIT,975.00
+CREDIT,01/03/2025,PAYROLL 42,2000.00,ACH_CREDIT,2975.00
+";
+
+        let report = inspect("chase.csv", "chase", content).unwrap();
+
+        assert!(
+            report.contains("   1  Posting Date  01/02/2025            <- date"),
+            "{}",
+            report
+        );
+        assert!(report.contains("[profile.chase]\ndate-col = \"Posting Date\"\ncsv-date-format = \"%m/%d/%Y\"\ndesc-col = \"Description\"\namount-col = \"Amount\"\n"), "{}", report);
+    }
+
+    #[test]
+    fn test_inspect_guesses_debit_and_credit_columns() {
+        let content = "Transaction ID,Date,Payee,Debit,Credit
+A1,2025-01-02,Coffee,4.00,
+A2,2025-01-03,Refund,,12.50
+";
+
+        let report = inspect("card.csv", "card", content).unwrap();
+
+        assert!(
+            report.contains("debit-col = \"Debit\"\ncredit-col = \"Credit\"\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains("id-col = \"Transaction ID\""), "{}", report);
+        assert!(report.contains("desc-col = \"Payee\""), "{}", report);
+        assert!(!report.contains("amount-col"), "{}", report);
+    }
+}
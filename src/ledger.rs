@@ -0,0 +1,1303 @@
+//! Splitting a ledger file into transaction blocks.
+
+use crate::amount::{self, Separators};
+use crate::beancount::Beancount;
+use crate::date;
+use crate::dialect::LedgerDialect;
+use crate::hledger::Hledger;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// The syntax a ledger file is written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Syntax {
+    /// hledger journals, also read by ledger-cli.
+    #[default]
+    Hledger,
+    /// Beancount files, whose flag follows the date; see [`beancount`].
+    Beancount,
+}
+
+impl Syntax {
+    /// The dialect that reads and writes this syntax.
+    pub fn dialect(self) -> &'static dyn LedgerDialect {
+        match self {
+            Syntax::Hledger => &Hledger,
+            Syntax::Beancount => &Beancount,
+        }
+    }
+}
+
+/// The hledger status marked on a transaction header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// `*`: the transaction has cleared the bank.
+    #[default]
+    Cleared,
+    /// `!`: the transaction is pending.
+    Pending,
+}
+
+impl Status {
+    /// The marker character written before the header.
+    pub fn marker(self) -> char {
+        match self {
+            Status::Cleared => '*',
+            Status::Pending => '!',
+        }
+    }
+
+    /// A lowercase name for messages.
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Cleared => "cleared",
+            Status::Pending => "pending",
+        }
+    }
+
+    /// The status a marker given on the command line or in the config stands
+    /// for, `*` or `!`.
+    pub fn parse_marker(marker: &str) -> Option<Status> {
+        let mut chars = marker.trim().chars();
+        match (chars.next(), chars.next()) {
+            (Some(marker), None) => Status::from_marker(marker),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_marker(marker: char) -> Option<Status> {
+        match marker {
+            '*' => Some(Status::Cleared),
+            '!' => Some(Status::Pending),
+            _ => None,
+        }
+    }
+}
+
+/// A single posting line: an account and, unless it is left implicit, an amount.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Posting {
+    /// The posting's own status marker, which hledger allows apart from the
+    /// transaction's.
+    pub status: Option<Status>,
+    pub account: String,
+    pub amount: Option<Decimal>,
+    /// The commodity the amount is written in, if it names one.
+    pub commodity: Option<String>,
+}
+
+impl Posting {
+    /// Whether this posting is to `account` or one of its subaccounts,
+    /// compared case-insensitively.
+    pub fn is_in(&self, account: &str) -> bool {
+        let own = self.account.to_lowercase();
+        let wanted = account.to_lowercase();
+        own == wanted || own.starts_with(&format!("{}:", wanted))
+    }
+
+    /// Whether this posting's account name starts with `prefix`, compared
+    /// case-insensitively.
+    pub fn has_prefix(&self, prefix: &str) -> bool {
+        self.account
+            .to_lowercase()
+            .starts_with(&prefix.to_lowercase())
+    }
+}
+
+/// Parses a posting line like `    Assets:Bank   -$50.00 ; note`.
+///
+/// The account is separated from the amount by two or more spaces or a tab, as
+/// in hledger. Posting status markers and virtual-posting brackets are dropped
+/// from the account name. Comment lines yield `None`.
+pub fn parse_posting(line: &str, separators: Separators) -> Option<Posting> {
+    let body = line.trim().split(';').next()?.trim_end();
+    if body.is_empty() || is_comment(body) {
+        return None;
+    }
+
+    let (account, amount_text) = split_posting(body);
+    let status = account.chars().next().and_then(Status::from_marker);
+    let account = account
+        .trim_start_matches(['*', '!'])
+        .trim()
+        .trim_matches(['(', ')', '[', ']']);
+
+    let parsed = amount_text.and_then(|text| amount::parse(text, separators));
+    Some(Posting {
+        status,
+        account: account.to_string(),
+        amount: parsed.as_ref().map(|amount| amount.quantity),
+        commodity: parsed.and_then(|amount| amount.commodity),
+    })
+}
+
+/// Splits a posting body into its account and amount text.
+///
+/// The amount text stops before any lot annotation (`{...}`, `[...]`), price
+/// (`@`, `@@`) or balance assertion (`=`), whose figures aren't the amount that
+/// moved.
+fn split_posting(body: &str) -> (&str, Option<&str>) {
+    let separator = match (body.find("  "), body.find('\t')) {
+        (Some(spaces), Some(tab)) => Some(spaces.min(tab)),
+        (spaces, tab) => spaces.or(tab),
+    };
+    match separator {
+        Some(index) => {
+            let text = &body[index..];
+            let end = text.find(['{', '[', '@', '=']).unwrap_or(text.len());
+            (&body[..index], Some(text[..end].trim_end()))
+        }
+        None => (body, None),
+    }
+}
+
+/// How a ledger file separates its lines, so it can be written back the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Whether lines end in `\r\n` rather than `\n`.
+    pub crlf: bool,
+    /// Whether the last line is followed by a line ending.
+    pub trailing_newline: bool,
+}
+
+impl Layout {
+    /// Detects the dominant line ending of `content` and whether it ends with one.
+    pub fn detect(content: &str) -> Layout {
+        let crlf = content.matches("\r\n").count();
+        let lf = content.matches('\n').count() - crlf;
+
+        Layout {
+            crlf: crlf > lf,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    /// Joins lines back into file content. Lines may hold several lines of
+    /// their own (appended entries), whose `\n` separators are converted too.
+    pub fn render(&self, lines: &[String]) -> String {
+        let ending = if self.crlf { "\r\n" } else { "\n" };
+        let mut content = lines
+            .iter()
+            .map(|line| {
+                if self.crlf {
+                    line.replace('\n', ending)
+                } else {
+                    line.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(ending);
+
+        if self.trailing_newline && !lines.is_empty() {
+            content.push_str(ending);
+        }
+        content
+    }
+}
+
+/// Where the amounts of a ledger's postings line up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// A tab separates the account from the amount.
+    Tab,
+    /// Amounts start this many characters after the indentation.
+    Start(usize),
+    /// Amounts end this many characters after the indentation.
+    End(usize),
+}
+
+/// How a ledger lays out its posting lines, so appended entries can match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PostingStyle {
+    pub indent: String,
+    pub alignment: Alignment,
+}
+
+impl Default for PostingStyle {
+    fn default() -> PostingStyle {
+        PostingStyle {
+            indent: "    ".to_string(),
+            alignment: Alignment::Start(32),
+        }
+    }
+}
+
+impl PostingStyle {
+    /// Infers the most common indentation and amount alignment from the posting
+    /// lines of `lines`, falling back to the default for each when there is
+    /// none to go by or no clear majority.
+    pub fn detect(lines: &[String], syntax: Syntax) -> PostingStyle {
+        let postings: Vec<&str> = parse_transactions(lines, syntax)
+            .iter()
+            .flat_map(|transaction| &lines[transaction.start + 1..transaction.end])
+            .filter(|line| !syntax.dialect().is_metadata(line))
+            .map(|line| line.split(';').next().unwrap_or_default().trim_end())
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let default = PostingStyle::default();
+
+        let indents = postings
+            .iter()
+            .map(|line| &line[..line.len() - line.trim_start().len()]);
+        let indent = majority(indents, postings.len()).map_or(default.indent, String::from);
+
+        // Offsets from the end of the indentation, so a tab indent doesn't
+        // depend on the tab width.
+        let amounts: Vec<(bool, usize, usize)> = postings
+            .iter()
+            .filter_map(|line| {
+                let body = line.trim_start();
+                let separator = [body.find("  "), body.find('\t')]
+                    .into_iter()
+                    .flatten()
+                    .min()?;
+                let gap = &body[separator..];
+                let amount = gap.trim_start();
+                let start = body[..body.len() - amount.len()].chars().count();
+                Some((gap.starts_with('\t'), start, start + amount.chars().count()))
+            })
+            .collect();
+        let alignment = if majority(amounts.iter().map(|(tab, ..)| *tab), amounts.len())
+            == Some(true)
+        {
+            Alignment::Tab
+        } else if let Some(end) = majority(amounts.iter().map(|(_, _, end)| *end), amounts.len()) {
+            Alignment::End(end)
+        } else if let Some(start) =
+            majority(amounts.iter().map(|(_, start, _)| *start), amounts.len())
+        {
+            Alignment::Start(start)
+        } else {
+            default.alignment
+        };
+
+        PostingStyle { indent, alignment }
+    }
+
+    /// Lays out a posting of `amount` to `account`. At least two spaces are
+    /// kept between them, however long the account.
+    pub fn posting(&self, account: &str, amount: &str) -> String {
+        let width = account.chars().count();
+        let gap = match self.alignment {
+            Alignment::Tab => return format!("{}{}\t{}", self.indent, account, amount),
+            Alignment::Start(start) => start.saturating_sub(width),
+            Alignment::End(end) => end.saturating_sub(width + amount.chars().count()),
+        };
+        format!(
+            "{}{}{}{}",
+            self.indent,
+            account,
+            " ".repeat(gap.max(2)),
+            amount
+        )
+    }
+}
+
+/// The value shared by more than half of `total` items, if there is one.
+fn majority<T: PartialEq>(values: impl Iterator<Item = T>, total: usize) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .find(|(_, count)| count * 2 > total)
+        .map(|(value, _)| value)
+}
+
+/// Why a transaction's postings don't balance, if they don't.
+///
+/// Each commodity must sum to zero on its own. One posting may leave its amount
+/// out, as hledger fills it in, and amounts without a commodity count towards
+/// the only one named. As in hledger, two commodities that don't balance but
+/// have opposite signs are taken as a conversion between them.
+pub fn balance_problem(postings: &[Posting]) -> Option<String> {
+    let elided = postings
+        .iter()
+        .filter(|posting| posting.amount.is_none())
+        .count();
+    match elided {
+        0 => {}
+        1 => return None,
+        _ => {
+            return Some(format!(
+                "{} postings leave their amount out, but at most one may",
+                elided
+            ))
+        }
+    }
+
+    let mut named: Vec<&str> = postings
+        .iter()
+        .filter_map(|posting| posting.commodity.as_deref())
+        .map(amount::canonical)
+        .collect();
+    named.sort_unstable();
+    named.dedup();
+    let mut sums: Vec<amount::Amount> = Vec::new();
+    for posting in postings {
+        let commodity = match (&posting.commodity, named.as_slice()) {
+            (None, [only]) => Some(only.to_string()),
+            (commodity, _) => commodity.clone(),
+        };
+        let key = commodity.as_deref().map(amount::canonical);
+        match sums
+            .iter_mut()
+            .find(|sum| sum.commodity.as_deref().map(amount::canonical) == key)
+        {
+            Some(sum) => sum.quantity += posting.amount.unwrap_or_default(),
+            None => sums.push(amount::Amount {
+                quantity: posting.amount.unwrap_or_default(),
+                commodity,
+            }),
+        }
+    }
+
+    sums.retain(|sum| !sum.quantity.is_zero());
+    match sums.as_slice() {
+        [] => None,
+        [a, b] if a.quantity.is_sign_negative() != b.quantity.is_sign_negative() => None,
+        _ => Some(format!(
+            "the postings sum to {} instead of zero",
+            sums.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" and ")
+        )),
+    }
+}
+
+/// The amount of each posting, with a lone elided amount filled in as what
+/// balances the others. It is left out (`None`) when the others are in more
+/// than one commodity, or when more than one amount is elided.
+pub fn posting_amounts(postings: &[Posting]) -> Vec<Option<amount::Amount>> {
+    let elided = postings
+        .iter()
+        .filter(|posting| posting.amount.is_none())
+        .count();
+    let mut named: Vec<&str> = postings
+        .iter()
+        .filter_map(|posting| posting.commodity.as_deref())
+        .map(amount::canonical)
+        .collect();
+    named.sort_unstable();
+    named.dedup();
+    let filled = match (elided, named.as_slice()) {
+        (1, [] | [_]) => Some(amount::Amount {
+            quantity: -postings
+                .iter()
+                .filter_map(|posting| posting.amount)
+                .sum::<Decimal>(),
+            commodity: postings
+                .iter()
+                .find_map(|posting| posting.commodity.clone()),
+        }),
+        _ => None,
+    };
+
+    postings
+        .iter()
+        .map(|posting| match posting.amount {
+            Some(quantity) => Some(amount::Amount {
+                quantity,
+                commodity: posting.commodity.clone(),
+            }),
+            None => filled.clone(),
+        })
+        .collect()
+}
+
+/// Checks that `entry` is a single well-formed transaction: a dated header
+/// followed by at least two indented postings that balance.
+pub fn check_entry(entry: &str, separators: Separators, syntax: Syntax) -> Result<(), String> {
+    let dialect = syntax.dialect();
+    let mut lines = entry.trim_end().lines();
+    let header = lines.next().unwrap_or_default();
+    if !dialect.is_header(header) {
+        return Err(dialect.header_rule().to_string());
+    }
+    let token = header
+        .trim_start_matches(['*', '!'])
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    if date::parse_date(token).is_none() {
+        return Err(format!("\"{}\" is not a valid date", token));
+    }
+
+    let mut postings = Vec::new();
+    for line in lines {
+        if !line.starts_with(char::is_whitespace) || line.trim().is_empty() {
+            return Err(format!("posting line \"{}\" must be indented", line));
+        }
+        if !dialect.is_metadata(line) {
+            postings.extend(parse_posting(line, separators));
+        }
+    }
+    if postings.len() < 2 {
+        return Err("a transaction needs at least two postings".to_string());
+    }
+
+    balance_problem(&postings).map_or(Ok(()), Err)
+}
+
+/// Rewrites a posting line so it carries `status`, keeping its indentation and
+/// replacing any existing marker.
+pub fn posting_with_status(line: &str, status: Status) -> String {
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    format!("{}{}", indent, with_leading_status(body, status))
+}
+
+/// Where a header's status marker is written after its date, as hledger
+/// prints them (`2025-01-03 * Rent`), and the status it stands for.
+pub(crate) fn marker_after_date(header: &str) -> Option<(usize, Status)> {
+    if !header.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let date_end = header.find(char::is_whitespace)?;
+    let rest = header[date_end..].trim_start();
+    let at = header.len() - rest.len();
+    let status = rest.chars().next().and_then(Status::from_marker)?;
+    Some((at, status))
+}
+
+/// Rewrites a header line so it carries `status`, replacing any existing marker:
+/// one after the date is replaced where it stands, and otherwise the marker
+/// goes before the date.
+///
+/// Applying it twice gives the same line as applying it once.
+pub fn with_status(header: &str, status: Status) -> String {
+    if let Some((at, _)) = marker_after_date(header) {
+        let mut marked = header.to_string();
+        marked.replace_range(at..at + 1, status.marker().encode_utf8(&mut [0; 4]));
+        return marked;
+    }
+    with_leading_status(header, status)
+}
+
+/// `line` with `status`'s marker at its start, in place of any already there.
+fn with_leading_status(line: &str, status: Status) -> String {
+    let trimmed = line.trim_start();
+    let unmarked = match trimmed.chars().next().and_then(Status::from_marker) {
+        Some(_) => trimmed[1..].trim_start(),
+        None => trimmed,
+    };
+
+    format!("{} {}", status.marker(), unmarked)
+}
+
+/// Appends a `; name: value` metadata tag to a header line, unless its comment
+/// already has a `name:` tag, so tagging twice gives the same line as once.
+pub fn with_tag(header: &str, name: &str, value: &str) -> String {
+    let key = format!("{}:", name.to_lowercase());
+    let tagged = header
+        .split_once(';')
+        .is_some_and(|(_, comment)| comment.to_lowercase().contains(&key));
+    if tagged {
+        return header.to_string();
+    }
+
+    format!("{}  ; {}: {}", header.trim_end(), name, value)
+}
+
+/// A transaction block within the ledger, as a half-open range of line indices.
+///
+/// `start` is the header line (the one carrying the date) and `end` is one past
+/// the last posting line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub start: usize,
+    pub end: usize,
+    pub syntax: Syntax,
+}
+
+impl Transaction {
+    /// The lines of this transaction joined back into a single block of text.
+    pub fn text(&self, lines: &[String]) -> String {
+        lines[self.start..self.end].join("\n")
+    }
+
+    /// Like [`Transaction::text`], but without comment lines or the inline
+    /// `; ...` comments and tags, so that only the header and postings are
+    /// matched against. Postings keep their account and amount, leaving out
+    /// prices, lot annotations and balance assertions.
+    pub fn match_text(&self, lines: &[String]) -> String {
+        lines[self.start..self.end]
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !is_comment(line))
+            .map(|(offset, line)| {
+                let body = line.split(';').next().unwrap_or_default().trim_end();
+                match split_posting(body) {
+                    (account, Some(amount)) if offset > 0 => format!("{}{}", account, amount),
+                    _ => body.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The postings of this transaction in order, each with the index of its line.
+    pub fn postings(&self, lines: &[String], separators: Separators) -> Vec<(usize, Posting)> {
+        (self.start + 1..self.end)
+            .filter(|&index| !self.syntax.dialect().is_metadata(&lines[index]))
+            .filter_map(|index| Some((index, parse_posting(&lines[index], separators)?)))
+            .collect()
+    }
+
+    /// The postings as [`Transaction::postings`] reads them, with a lone
+    /// elided amount filled in as what balances the others, the way hledger
+    /// infers it, so the bank's leg of `Assets:Bank` alone can be matched on
+    /// amount too.
+    pub fn balanced_postings(
+        &self,
+        lines: &[String],
+        separators: Separators,
+    ) -> Vec<(usize, Posting)> {
+        let (indices, mut postings): (Vec<usize>, Vec<Posting>) =
+            self.postings(lines, separators).into_iter().unzip();
+        let amounts = posting_amounts(&postings);
+        for (posting, amount) in postings.iter_mut().zip(amounts) {
+            if let (None, Some(amount)) = (posting.amount, amount) {
+                posting.amount = Some(amount.quantity);
+                posting.commodity = posting.commodity.take().or(amount.commodity);
+            }
+        }
+        indices.into_iter().zip(postings).collect()
+    }
+
+    /// The header line, without the indentation of an indented header.
+    pub fn header<'a>(&self, lines: &'a [String]) -> &'a str {
+        lines[self.start].trim_start()
+    }
+
+    /// The primary date from the header line.
+    pub fn date(&self, lines: &[String]) -> Option<NaiveDate> {
+        let token = self
+            .header(lines)
+            .trim_start_matches(['*', '!'])
+            .split_whitespace()
+            .next()?;
+        date::parse_date(token)
+    }
+
+    /// The payee/description from the header line, without the date, status
+    /// markers, transaction code or trailing comment.
+    pub fn description<'a>(&self, lines: &'a [String]) -> Cow<'a, str> {
+        self.syntax.dialect().description(self.header(lines))
+    }
+
+    /// The transaction code from the header line, e.g. `1042` in
+    /// `2025-01-01 * (1042) Coffee Shop`.
+    pub fn code<'a>(&self, lines: &'a [String]) -> Option<&'a str> {
+        self.syntax.dialect().code(self.header(lines))
+    }
+
+    /// The status marker on the header line, if any.
+    pub fn status(&self, lines: &[String]) -> Option<Status> {
+        self.syntax.dialect().status(self.header(lines))
+    }
+}
+
+/// Returns true if the line is a comment, such as an indented `; note` or
+/// `; tag: value` line within a transaction.
+pub fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with([';', '#'])
+}
+
+/// The file an `include` directive names, as hledger (`include path`),
+/// ledger-cli (`!include path`) and Beancount (`include "path"`) write it.
+pub fn include_path(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix("include")
+        .or_else(|| line.strip_prefix("!include"))?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let path = rest
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches('"');
+    (!path.is_empty()).then_some(path)
+}
+
+/// Groups ledger lines into transactions, as `syntax` reads them.
+pub fn parse_transactions(lines: &[String], syntax: Syntax) -> Vec<Transaction> {
+    syntax.dialect().parse_transactions(lines)
+}
+
+/// Groups ledger lines into transactions of `syntax`, whose headers are the
+/// lines `is_header` accepts.
+///
+/// A transaction begins at a header line and extends over the indented lines
+/// that follow it. It ends at the first blank line, or at the next line that
+/// starts in column zero (another transaction, a directive, or a comment).
+/// A header may itself be indented when it opens the file or follows a blank
+/// line, where it can't belong to the transaction above.
+pub fn blocks(
+    lines: &[String],
+    syntax: Syntax,
+    is_header: impl Fn(&str) -> bool,
+) -> Vec<Transaction> {
+    let mut transactions = Vec::new();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let after_break = index == 0 || lines[index - 1].trim().is_empty();
+        let header =
+            is_header(&lines[index]) || (after_break && is_header(lines[index].trim_start()));
+        if !header {
+            index += 1;
+            continue;
+        }
+
+        let start = index;
+        index += 1;
+        while index < lines.len()
+            && lines[index].starts_with(char::is_whitespace)
+            && !lines[index].trim().is_empty()
+        {
+            index += 1;
+        }
+
+        transactions.push(Transaction {
+            start,
+            end: index,
+            syntax,
+        });
+    }
+
+    transactions
+}
+
+/// `lines` with the entries from index `original` on, which were appended
+/// during a run, moved among the first `original` lines by date.
+///
+/// Each entry goes before the first transaction dated after it, and before
+/// any comment lines directly above that transaction; entries dated after
+/// every transaction stay at the end. A blank line follows an inserted entry
+/// when the ledger separates its transactions with blank lines. Also returns
+/// where each moved entry now starts, as 1-based line numbers before and after.
+pub fn merge_by_date(
+    lines: &[String],
+    original: usize,
+    syntax: Syntax,
+) -> (Vec<String>, Vec<(usize, usize)>) {
+    let existing = &lines[..original];
+    let transactions = parse_transactions(existing, syntax);
+    // Where a transaction begins once the comment lines above it are counted.
+    let first_line = |transaction: &Transaction| {
+        let mut start = transaction.start;
+        while start > 0
+            && !existing[start - 1].starts_with(char::is_whitespace)
+            && is_comment(&existing[start - 1])
+        {
+            start -= 1;
+        }
+        start
+    };
+    let separated = transactions
+        .iter()
+        .skip(1)
+        .any(|transaction| existing[first_line(transaction) - 1].trim().is_empty());
+    let entry_date = |entry: &str| {
+        let entry_lines: Vec<String> = entry.lines().map(String::from).collect();
+        parse_transactions(&entry_lines, syntax)
+            .first()
+            .and_then(|transaction| transaction.date(&entry_lines))
+    };
+
+    let mut slots: Vec<Vec<usize>> = vec![Vec::new(); original + 1];
+    for (index, entry) in lines.iter().enumerate().skip(original) {
+        let slot = entry_date(entry)
+            .and_then(|date| {
+                transactions.iter().find(|transaction| {
+                    transaction
+                        .date(existing)
+                        .is_some_and(|existing_date| existing_date > date)
+                })
+            })
+            .map_or(original, first_line);
+        slots[slot].push(index);
+    }
+    for slot in &mut slots[..original] {
+        slot.sort_by_key(|&index| entry_date(&lines[index]));
+    }
+
+    let line_count = |line: &String| line.lines().count().max(1);
+    let old_starts: Vec<usize> = lines
+        .iter()
+        .scan(1, |next, line| {
+            let start = *next;
+            *next += line_count(line);
+            Some(start)
+        })
+        .collect();
+
+    let mut merged = Vec::new();
+    let mut moved = Vec::new();
+    let mut next_line = 1;
+    for (position, slot) in slots.iter().enumerate() {
+        for &index in slot {
+            moved.push((old_starts[index], next_line));
+            merged.push(lines[index].clone());
+            next_line += line_count(&lines[index]);
+            if separated && position < original {
+                merged.push(String::new());
+                next_line += 1;
+            }
+        }
+        if let Some(line) = existing.get(position) {
+            merged.push(line.clone());
+            next_line += line_count(line);
+        }
+    }
+
+    (merged, moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(content: &str) -> Vec<String> {
+        content.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_merge_by_date_inserts_between_neighbours() {
+        let mut ledger = lines(
+            "2025-01-10 Rent
+    Expenses:Rent  $1000
+    Assets:Bank
+
+; summer
+2025-07-01 Rent
+    Expenses:Rent  $1000
+    Assets:Bank",
+        );
+        let original = ledger.len();
+        ledger.push("2025-12-01 Gift\n    Expenses:Gifts  $20\n    Assets:Bank".to_string());
+        ledger.push("2025-06-15 Bakery\n    Expenses:Food  $3\n    Assets:Bank".to_string());
+
+        let (merged, moved) = merge_by_date(&ledger, original, Syntax::Hledger);
+
+        assert_eq!(
+            merged.join("\n"),
+            "2025-01-10 Rent
+    Expenses:Rent  $1000
+    Assets:Bank
+
+2025-06-15 Bakery
+    Expenses:Food  $3
+    Assets:Bank
+
+; summer
+2025-07-01 Rent
+    Expenses:Rent  $1000
+    Assets:Bank
+2025-12-01 Gift
+    Expenses:Gifts  $20
+    Assets:Bank"
+        );
+        assert_eq!(moved, [(12, 5), (9, 13)]);
+    }
+
+    #[test]
+    fn test_groups_varying_posting_counts() {
+        let ledger = lines(
+            "2025-01-01 Groceries
+    Expenses:Food          $50.00
+    Assets:Bank           -$50.00
+
+2025-01-02 Dinner
+    Expenses:Food          $40.00
+    Expenses:Tips           $8.00
+    Assets:Bank           -$48.00
+
+2025-01-03 Paycheck
+    Assets:Bank          $2000.00
+    Expenses:Tax          $500.00
+    Expenses:Insurance    $100.00
+    Income:Salary       -$2600.00
+",
+        );
+
+        let transactions = parse_transactions(&ledger, Syntax::Hledger);
+
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    start: 0,
+                    end: 3,
+                    syntax: Syntax::Hledger,
+                },
+                Transaction {
+                    start: 4,
+                    end: 8,
+                    syntax: Syntax::Hledger,
+                },
+                Transaction {
+                    start: 9,
+                    end: 14,
+                    syntax: Syntax::Hledger,
+                },
+            ]
+        );
+        assert_eq!(transactions[1].text(&ledger).lines().count(), 4);
+        assert!(transactions[2].text(&ledger).contains("Income:Salary"));
+        assert_eq!(
+            transactions[2]
+                .postings(&ledger, Separators::default())
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_splits_adjacent_transactions_without_blank_lines() {
+        let ledger = lines(
+            "; opening comment
+account Assets:Bank
+* 2025-01-01 Groceries
+    Expenses:Food          $50.00
+    Assets:Bank
+2025-01-02 Rent
+    Expenses:Rent         $1000.00
+    Assets:Bank          -$1000.00",
+        );
+
+        let transactions = parse_transactions(&ledger, Syntax::Hledger);
+
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    start: 2,
+                    end: 5,
+                    syntax: Syntax::Hledger,
+                },
+                Transaction {
+                    start: 5,
+                    end: 8,
+                    syntax: Syntax::Hledger,
+                },
+            ]
+        );
+        assert_eq!(transactions[0].status(&ledger), Some(Status::Cleared));
+        assert_eq!(transactions[1].status(&ledger), None);
+        assert_eq!(transactions[0].description(&ledger), "Groceries");
+        assert_eq!(transactions[0].code(&ledger), None);
+        assert_eq!(
+            transactions[1].date(&ledger),
+            NaiveDate::from_ymd_opt(2025, 1, 2)
+        );
+    }
+
+    #[test]
+    fn test_description_strips_markers_and_code() {
+        let ledger = lines("2025-01-01 * (1042) Coffee Shop  ; morning\n    Assets:Bank");
+
+        let transactions = parse_transactions(&ledger, Syntax::Hledger);
+
+        assert_eq!(transactions[0].description(&ledger), "Coffee Shop");
+        assert_eq!(transactions[0].code(&ledger), Some("1042"));
+    }
+
+    #[test]
+    fn test_parse_posting() {
+        assert_eq!(
+            parse_posting("    Assets:Bank           -$50.00", Separators::default()),
+            Some(Posting {
+                status: None,
+                account: "Assets:Bank".to_string(),
+                amount: Decimal::from_str_exact("-50.00").ok(),
+                commodity: Some("$".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_posting("\t* Expenses:Food\t$50 ; lunch", Separators::default()),
+            Some(Posting {
+                status: Some(Status::Cleared),
+                account: "Expenses:Food".to_string(),
+                amount: Decimal::from_str_exact("50").ok(),
+                commodity: Some("$".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_posting("    Assets:Bank", Separators::default()),
+            Some(Posting {
+                status: None,
+                account: "Assets:Bank".to_string(),
+                amount: None,
+                commodity: None,
+            })
+        );
+        assert_eq!(
+            parse_posting("    ; just a note", Separators::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_posting_amount_ignores_prices_lots_and_assertions() {
+        let amount = |line: &str| parse_posting(line, Separators::default()).unwrap().amount;
+
+        assert_eq!(
+            amount("    Assets:Bank  $-50.00 = $1234.56"),
+            Decimal::from_str_exact("-50.00").ok()
+        );
+        assert_eq!(
+            amount("    Assets:Broker  10 AAPL {$150.00} [2024-03-01] @ $155.00"),
+            Decimal::from_str_exact("10").ok()
+        );
+        assert_eq!(
+            amount("    Assets:Cash  100 EUR @@ $108.00"),
+            Decimal::from_str_exact("100").ok()
+        );
+        assert_eq!(amount("    [Assets:Budget]  = $500"), None);
+
+        let lines = lines(
+            "2025-01-01 Coffee\n    Expenses:Coffee  $4.00\n    Assets:Bank  $-4.00 = $96.00",
+        );
+        assert_eq!(
+            parse_transactions(&lines, Syntax::Hledger)[0].match_text(&lines),
+            "2025-01-01 Coffee\n    Expenses:Coffee  $4.00\n    Assets:Bank  $-4.00"
+        );
+    }
+
+    #[test]
+    fn test_posting_is_in_account() {
+        let posting = parse_posting("    Assets:Bank:Checking  $5", Separators::default()).unwrap();
+
+        assert!(posting.is_in("assets:bank"));
+        assert!(posting.is_in("Assets:Bank:Checking"));
+        assert!(!posting.is_in("Assets:Ban"));
+    }
+
+    #[test]
+    fn test_layout_round_trips_line_endings() {
+        for content in ["a\r\nb\r\n", "a\nb", "a\nb\n", ""] {
+            let layout = Layout::detect(content);
+            assert_eq!(layout.render(&lines(content)), content);
+        }
+
+        let layout = Layout::detect("a\r\n");
+        assert_eq!(
+            layout.render(&["a".to_string(), "b\n    c".to_string()]),
+            "a\r\nb\r\n    c\r\n"
+        );
+    }
+
+    #[test]
+    fn test_check_entry() {
+        assert_eq!(
+            check_entry(
+                "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank  -$4.00\n",
+                Separators::default(),
+                Syntax::Hledger
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_entry(
+                "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank",
+                Separators::default(),
+                Syntax::Hledger
+            ),
+            Ok(())
+        );
+        assert!(check_entry(
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank  -$5",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .unwrap_err()
+        .contains("sum to -$1"));
+        assert_eq!(
+            check_entry(
+                "2025-01-01 Lunch\n    Expenses:Food  €12,50\n    Assets:Bank  -12.50 EUR",
+                Separators::default(),
+                Syntax::Hledger
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_entry(
+                "2025-01-01 Exchange\n    Assets:Euro  10 EUR\n    Assets:Bank  -$11",
+                Separators::default(),
+                Syntax::Hledger
+            ),
+            Ok(())
+        );
+        assert!(check_entry(
+            "2025-01-01 Lunch\n    Expenses:Food  €12\n    Assets:Bank  -$12\n    Assets:Cash  -£1",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .unwrap_err()
+        .contains("sum to €12 and -$12 and -£1"));
+        assert!(check_entry(
+            "2025-01-01 Coffee\n    Expenses:Food\n    Assets:Bank",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .is_err());
+        assert!(check_entry(
+            "Coffee\n    Expenses:Food  $4\n    Assets:Bank",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .is_err());
+        assert!(check_entry(
+            "2025-13-01 Coffee\n    Expenses:Food  $4\n    Assets:Bank",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .is_err());
+        assert!(check_entry(
+            "2025-01-01 Coffee\n    Expenses:Food  $4",
+            Separators::default(),
+            Syntax::Hledger
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_check_entry_with_decimal_commas() {
+        let eu = amount::Locale::Eu.separators();
+
+        assert_eq!(
+            check_entry(
+                "2025-01-01 Rent\n    Expenses:Rent  €1.234,56\n    Assets:Bank  -1234,56 EUR",
+                eu,
+                Syntax::Hledger
+            ),
+            Ok(())
+        );
+        assert!(check_entry(
+            "2025-01-01 Rent\n    Expenses:Rent  €1.234\n    Assets:Bank  -€1,234",
+            eu,
+            Syntax::Hledger
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_posting_style_detects_alignment() {
+        let right = lines(
+            "2025-01-01 Rent
+    Expenses:Rent          $1000.00
+    Assets:Bank           -$1000.00
+2025-01-02 Coffee
+    Expenses:Food             $4.00
+    Assets:Bank",
+        );
+        let style = PostingStyle::detect(&right, Syntax::Hledger);
+        assert_eq!(style.alignment, Alignment::End(31));
+        assert_eq!(
+            style.posting("Expenses:Miscellaneous", "$12.50"),
+            "    Expenses:Miscellaneous   $12.50"
+        );
+        assert_eq!(
+            style.posting("Assets:Bank", "-$12.50"),
+            "    Assets:Bank             -$12.50"
+        );
+        assert_eq!(
+            style.posting("Expenses:Some:Very:Long:Account", "$1"),
+            "    Expenses:Some:Very:Long:Account  $1"
+        );
+
+        let tabs = lines("2025-01-01 Rent\n\tExpenses:Rent\t$1000.00\n\tAssets:Bank");
+        let style = PostingStyle::detect(&tabs, Syntax::Hledger);
+        assert_eq!(style.indent, "\t");
+        assert_eq!(style.alignment, Alignment::Tab);
+        assert_eq!(style.posting("Assets:Bank", "-$5"), "\tAssets:Bank\t-$5");
+
+        let left = lines(
+            "2025-01-01 Rent
+  Expenses:Rent   $1000.00
+  Assets:Bank     -$1000.00",
+        );
+        let style = PostingStyle::detect(&left, Syntax::Hledger);
+        assert_eq!(style.indent, "  ");
+        assert_eq!(style.alignment, Alignment::Start(16));
+
+        assert_eq!(
+            PostingStyle::detect(&[], Syntax::Hledger),
+            PostingStyle::default()
+        );
+    }
+
+    #[test]
+    fn test_with_tag_is_added_once() {
+        let tagged = with_tag("* 2025-01-01 Coffee", "reconciled", "2025-06-01");
+        assert_eq!(tagged, "* 2025-01-01 Coffee  ; reconciled: 2025-06-01");
+        assert_eq!(with_tag(&tagged, "reconciled", "2025-07-01"), tagged);
+
+        assert_eq!(
+            with_tag("2025-01-01 Coffee ; :work:", "reconciled", "2025-06-01"),
+            "2025-01-01 Coffee ; :work:  ; reconciled: 2025-06-01"
+        );
+    }
+
+    #[test]
+    fn test_match_text_leaves_out_comments() {
+        let lines = lines(
+            "2025-01-01 Coffee  ; receipt: 50.00
+    ; paid for the team
+    Expenses:Coffee  $4.00  ; project: offsite
+    # 2025-01-02
+    Assets:Bank
+",
+        );
+        let transaction = &parse_transactions(&lines, Syntax::Hledger)[0];
+
+        assert_eq!(transaction.end, 5);
+        assert_eq!(
+            transaction.match_text(&lines),
+            "2025-01-01 Coffee\n    Expenses:Coffee  $4.00\n    Assets:Bank"
+        );
+        assert_eq!(transaction.postings(&lines, Separators::default()).len(), 2);
+    }
+
+    #[test]
+    fn test_posting_amounts_fill_in_the_elided_one() {
+        let postings = |lines: &[&str]| -> Vec<Posting> {
+            lines
+                .iter()
+                .map(|line| parse_posting(line, Separators::default()).unwrap())
+                .collect()
+        };
+        let quantities = |postings: &[Posting]| -> Vec<Option<String>> {
+            posting_amounts(postings)
+                .into_iter()
+                .map(|amount| amount.map(|amount| amount.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            quantities(&postings(&["    Expenses:Food  $4.00", "    Assets:Bank"])),
+            vec![Some("$4.00".to_string()), Some("-$4.00".to_string())]
+        );
+        assert_eq!(
+            quantities(&postings(&[
+                "    Expenses:Food  4 EUR",
+                "    Expenses:Fees  $1",
+                "    Assets:Bank"
+            ])),
+            vec![Some("4 EUR".to_string()), Some("$1".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn test_balanced_postings_carry_the_inferred_amount() {
+        let lines: Vec<String> = [
+            "2025-01-03 Groceries",
+            "    Expenses:Food  $50.00",
+            "    ; receipt: 1042",
+            "    Assets:Bank",
+        ]
+        .map(String::from)
+        .to_vec();
+        let transaction = &parse_transactions(&lines, Syntax::Hledger)[0];
+
+        let balanced: Vec<(usize, Option<String>)> = transaction
+            .balanced_postings(&lines, Separators::default())
+            .into_iter()
+            .map(|(index, posting)| {
+                let amount = posting.amount.map(|quantity| amount::Amount {
+                    quantity,
+                    commodity: posting.commodity,
+                });
+                (index, amount.map(|amount| amount.to_string()))
+            })
+            .collect();
+        assert_eq!(
+            balanced,
+            [
+                (1, Some("$50.00".to_string())),
+                (3, Some("-$50.00".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_posting_with_status_keeps_indentation() {
+        assert_eq!(
+            posting_with_status("    Assets:Bank  -$5", Status::Cleared),
+            "    * Assets:Bank  -$5"
+        );
+        assert_eq!(
+            posting_with_status("\t! Assets:Bank  -$5", Status::Cleared),
+            "\t* Assets:Bank  -$5"
+        );
+    }
+
+    #[test]
+    fn test_with_status_replaces_existing_marker() {
+        assert_eq!(
+            with_status("2025-01-01 Rent", Status::Pending),
+            "! 2025-01-01 Rent"
+        );
+        assert_eq!(
+            with_status("! 2025-01-01 Rent", Status::Cleared),
+            "* 2025-01-01 Rent"
+        );
+        assert_eq!(
+            with_status("* 2025-01-01 Rent", Status::Cleared),
+            "* 2025-01-01 Rent"
+        );
+        assert_eq!(
+            with_status("*2025-01-01 Rent", Status::Cleared),
+            "* 2025-01-01 Rent"
+        );
+        assert_eq!(
+            with_status("2025-01-01 ! Rent  ; due", Status::Cleared),
+            "2025-01-01 * Rent  ; due"
+        );
+        assert_eq!(
+            with_status("2025-01-01=2025-01-03 * (42) Rent", Status::Cleared),
+            "2025-01-01=2025-01-03 * (42) Rent"
+        );
+        assert_eq!(
+            with_status("2025-01-01 Rent * 2", Status::Cleared),
+            "* 2025-01-01 Rent * 2"
+        );
+    }
+
+    #[test]
+    fn test_markers_are_parsed_alone() {
+        assert_eq!(Status::parse_marker(" * "), Some(Status::Cleared));
+        assert_eq!(Status::parse_marker("!"), Some(Status::Pending));
+        assert_eq!(Status::parse_marker("**"), None);
+        assert_eq!(Status::parse_marker("cleared"), None);
+    }
+
+    #[test]
+    fn test_include_path_reads_every_syntax() {
+        assert_eq!(include_path("include 2024.journal"), Some("2024.journal"));
+        assert_eq!(
+            include_path("!include sub/old.ledger ; archive"),
+            Some("sub/old.ledger")
+        );
+        assert_eq!(
+            include_path("include \"accounts.beancount\""),
+            Some("accounts.beancount")
+        );
+        assert_eq!(include_path("included 2024.journal"), None);
+        assert_eq!(include_path("    include x"), None);
+    }
+}
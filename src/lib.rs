@@ -0,0 +1,8020 @@
+//! Matching bank statements against an hledger journal and marking the
+//! transactions they confirm as cleared.
+//!
+//! [`reconcile`] runs the same interactive session as the `ledger-reconcile`
+//! command, and [`reconcile_with`] lets another [`Prompter`] answer its
+//! questions:
+//!
+//! ```no_run
+//! use ledger_reconcile::{reconcile, Options, ReconcileConfig};
+//!
+//! let report = reconcile(&ReconcileConfig {
+//!     ledger: "main.ledger".to_string(),
+//!     statements: vec!["bank.csv".to_string()],
+//!     output: "updated.ledger".to_string(),
+//!     options: Options {
+//!         auto: true,
+//!         ..Options::default()
+//!     },
+//! })?;
+//! println!("cleared {} transaction(s)", report.cleared);
+//! # Ok::<(), ledger_reconcile::ReconcileError>(())
+//! ```
+
+mod add;
+mod amount;
+mod beancount;
+mod checkpoint;
+mod color;
+mod columns;
+mod date;
+mod dialect;
+mod encoding;
+mod error;
+mod fuzzy;
+mod history;
+mod hledger;
+mod index;
+mod inspect;
+mod ledger;
+mod normalize;
+mod ofx;
+mod payees;
+mod progress;
+mod prompt;
+mod qif;
+mod report;
+mod rules;
+mod template;
+mod verify;
+
+pub use add::{add_entry, add_entry_with};
+pub use amount::{Locale, Separators};
+pub use color::disable_colors;
+pub use columns::{AmountColumns, ColumnMapping, ColumnSpec, DebitSign};
+pub use encoding::Encoding;
+pub use error::ReconcileError;
+pub use inspect::inspect;
+pub use ledger::{Status, Syntax};
+pub use normalize::{Normalization, StatementField};
+pub use progress::LogWriter;
+pub use prompt::{Prompter, Validator};
+
+use checkpoint::Checkpoint;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use history::{Change, History};
+use index::CandidateIndex;
+use ledger::{Posting, PostingStyle};
+use log::{debug, info, warn, LevelFilter};
+use normalize::Normalizer;
+use payees::PayeeMap;
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use report::{
+    Action, CandidateRef, Decision, Preflight, StatementRecord, Summary, UnbalancedTransaction,
+};
+use rules::Rules;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use template::Template;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Set when the updated ledger goes to stdout, so the summary moves to stderr.
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Print run results (the summary and dry-run preview) to stdout, or to stderr
+/// while stdout carries the ledger. Progress goes through `log` instead.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if MESSAGES_TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Settings that control how statements are read and matched.
+///
+/// Most fields mirror the command-line flag of the same name; `columns` and
+/// `separators` gather the column and number notation flags.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub syntax: Syntax,
+    pub format: Option<InputFormat>,
+    pub encoding: Option<Encoding>,
+    pub delimiter: Option<char>,
+    pub columns: ColumnMapping,
+    pub invert_amount: bool,
+    pub amount_tolerance: Decimal,
+    pub csv_date_format: Option<String>,
+    pub no_header: bool,
+    pub sort_csv: Option<CsvSort>,
+    pub reverse: bool,
+    pub date_window: u32,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub ignore_below: Option<Decimal>,
+    pub ignore_descs: Vec<String>,
+    pub account: Option<String>,
+    pub exclude_accounts: Vec<String>,
+    pub bank_account_prefixes: Vec<String>,
+    pub ledger_filter: Option<String>,
+    pub commodity: Option<String>,
+    pub separators: Separators,
+    pub auto: bool,
+    pub yes: bool,
+    pub mark: Status,
+    pub include_pending: bool,
+    pub cleared_markers: Vec<Status>,
+    pub posting_level: bool,
+    pub fuzzy: bool,
+    pub similarity: u8,
+    pub match_fields: Vec<MatchField>,
+    pub auto_accept_score: Option<u8>,
+    pub case_sensitive: bool,
+    pub ascii_fold: bool,
+    pub match_context: usize,
+    pub normalize: Vec<Normalization>,
+    pub rules: Option<String>,
+    pub payee_map: Option<String>,
+    pub template: Option<String>,
+    pub misc_account: Option<String>,
+    pub asset_account: Option<String>,
+    pub templates: BTreeMap<String, String>,
+    pub strict: bool,
+    pub strict_amount_format: bool,
+    pub dedupe: bool,
+    pub backup: bool,
+    pub dry_run: bool,
+    pub count: bool,
+    pub limit: Option<usize>,
+    pub sorted_insert: bool,
+    pub trim_trailing: bool,
+    pub checkpoint_every: usize,
+    pub resume: bool,
+    pub report: Option<String>,
+    pub json_log: Option<String>,
+    pub rejects: Option<String>,
+    pub print_cleared: Option<String>,
+    pub csv_out: Option<String>,
+    pub reverse_match: bool,
+    pub stamp_cleared: Option<NaiveDate>,
+    pub annotate_header: Option<NaiveDate>,
+    pub explain: Option<String>,
+    pub group_by_day: bool,
+    pub interactive_edit_unmatched: bool,
+    pub quiet_matches: bool,
+    pub verify_with_hledger: bool,
+    pub threads: Option<usize>,
+    pub assert_balances: Vec<String>,
+    pub opening_balance: Option<String>,
+    pub closing_balance: Option<String>,
+    pub actions: Vec<MenuAction>,
+}
+
+/// An extra choice for records that match nothing: it drafts a new entry
+/// booked to `account`, from the named `template` if given, to be edited, and
+/// marks it with `--mark` if `clear`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MenuAction {
+    pub name: String,
+    pub account: String,
+    #[serde(default)]
+    pub clear: bool,
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+// Choices offered by the prompts.
+const IGNORE: &str = "Ignore";
+const IGNORE_THIS_LINE: &str = "Ignore this line";
+const ADD_NEW: &str = "Add new transaction";
+const CREATE_AND_CLEAR: &str = "Create and clear";
+const RELAX_AND_RETRY: &str = "Relax and retry (wider date window and amount tolerance)";
+const SPLIT: &str = "Clear several transactions that add up to it";
+const SKIP_ALL_REMAINING: &str = "Skip all remaining (saves progress so far)";
+const EXIT: &str = "Exit (saves progress so far)";
+const UNDO: &str = "Undo last action";
+const REVIEW_EACH: &str = "Go through them one by one";
+const IGNORE_ALL: &str = "Ignore them all";
+const IGNORE_SOME: &str = "Choose which to ignore, then go through the rest";
+const ACCEPT_BEST: &str = "Accept it";
+const SHOW_ALL_MATCHES: &str = "Show all matches";
+const DROP_DUPLICATES: &str = "Drop them";
+const KEEP_DUPLICATES: &str = "Keep them";
+const ASK_AGAIN: &str = "No, ask again";
+const CONFIRM_SKIP: &str = "Yes, skip it";
+const NEW_ACCOUNT: &str = "Yes, open a new account";
+const PICK_ACCOUNT: &str = "No, pick an existing account";
+
+/// The account a new entry is booked to when no rule or action names one,
+/// unless `--misc-account` is given.
+const MISC_ACCOUNT: &str = "Expenses:Miscellaneous";
+/// The account balancing a new entry drafted without a template, unless
+/// `--asset-account` is given.
+const ASSET_ACCOUNT: &str = "Assets:Bank";
+
+/// An uncleared ledger transaction that CSV records are matched against.
+struct Candidate {
+    header_line: usize,
+    text: String,
+    /// `text` without its comments, made [`comparable`] and with `$` removed,
+    /// for substring comparisons.
+    normalized: String,
+    /// The description made [`comparable`] and split for `--fuzzy`, once
+    /// rather than for every record it is compared with.
+    tokens: fuzzy::Tokens,
+    date: Option<NaiveDate>,
+    code: Option<String>,
+    /// The postings, with a lone elided amount filled in as hledger infers it.
+    postings: Vec<Posting>,
+    /// The ledger line of each posting.
+    posting_lines: Vec<usize>,
+    /// Where the transaction starts, as `line 42`, or `2024.journal:42` in an
+    /// included file.
+    location: String,
+    /// The `--match-context` lines around the transaction.
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+/// Whether a statement amount is compared against `posting`: any posting is,
+/// unless `--account` or `--bank-account-prefix` narrow them down.
+fn is_compared(posting: &Posting, options: &Options) -> bool {
+    options
+        .account
+        .as_deref()
+        .is_none_or(|account| posting.is_in(account))
+        && (options.bank_account_prefixes.is_empty()
+            || options
+                .bank_account_prefixes
+                .iter()
+                .any(|prefix| posting.has_prefix(prefix)))
+}
+
+/// Whether the compared postings are the statement account's own legs, named
+/// by `--account` or `--bank-account-prefix`, so a statement amount must go
+/// the same way as the posting it matches: a `-50.00` debit matches the
+/// bank's `-$50.00`, but not a `$50.00` deposit.
+fn compares_sign(options: &Options) -> bool {
+    options.account.is_some() || !options.bank_account_prefixes.is_empty()
+}
+
+impl Candidate {
+    /// Postings to compare against.
+    fn compared_postings<'a>(
+        &'a self,
+        options: &'a Options,
+    ) -> impl Iterator<Item = &'a Posting> + 'a {
+        self.postings
+            .iter()
+            .filter(move |posting| is_compared(posting, options))
+    }
+
+    /// Posting amounts to compare against.
+    fn amounts<'a>(&'a self, options: &'a Options) -> impl Iterator<Item = Decimal> + 'a {
+        self.compared_postings(options)
+            .filter_map(|posting| posting.amount)
+    }
+}
+
+/// The fields of a CSV record that candidates are compared against.
+struct StatementLine<'a> {
+    date: &'a str,
+    parsed_date: Option<NaiveDate>,
+    /// Description made [`comparable`].
+    description: &'a str,
+    tokens: &'a fuzzy::Tokens,
+    amount: &'a str,
+    parsed_amount: Option<Decimal>,
+    /// The amount's commodity, or `--commodity` if the statement doesn't say.
+    commodity: Option<&'a str>,
+    /// The bank's transaction ID, or empty if the statement has none.
+    id: &'a str,
+}
+
+/// The owned values a [`StatementLine`] borrows from.
+struct RecordFields {
+    /// The date as `YYYY-MM-DD` if it could be read, else as written.
+    date: String,
+    parsed_date: Option<NaiveDate>,
+    description: String,
+    tokens: fuzzy::Tokens,
+    /// The amount as written, without `$`.
+    amount: String,
+    parsed: Option<amount::Amount>,
+}
+
+impl RecordFields {
+    fn new(record: &[String], options: &Options) -> RecordFields {
+        let parsed_date =
+            date::parse_statement_date(&record[0], options.csv_date_format.as_deref());
+        let description = comparable(record[1].trim(), options);
+        RecordFields {
+            date: parsed_date.map_or_else(
+                || record[0].trim().to_string(),
+                |parsed| parsed.format("%Y-%m-%d").to_string(),
+            ),
+            parsed_date,
+            tokens: fuzzy::Tokens::new(&description),
+            description,
+            amount: record[2].trim().replace('$', ""),
+            parsed: amount::parse(&record[2], options.separators),
+        }
+    }
+
+    fn line<'a>(&'a self, record: &'a [String], options: &'a Options) -> StatementLine<'a> {
+        StatementLine {
+            date: &self.date,
+            parsed_date: self.parsed_date,
+            description: &self.description,
+            tokens: &self.tokens,
+            amount: &self.amount,
+            parsed_amount: self.parsed.as_ref().map(|parsed| parsed.quantity),
+            commodity: self
+                .parsed
+                .as_ref()
+                .and_then(|parsed| parsed.commodity.as_deref())
+                .or(options.commodity.as_deref()),
+            id: record[3].trim(),
+        }
+    }
+}
+
+/// A candidate accepted for a CSV record, with its description similarity under `--fuzzy`.
+struct Match {
+    header_line: usize,
+    text: String,
+    location: String,
+    before: Vec<String>,
+    after: Vec<String>,
+    score: Option<u8>,
+    /// Under `--posting-level`, the lines of the postings that may be marked.
+    postings: Vec<usize>,
+    /// The subset of `postings` whose amount agrees with the record.
+    suggested: Vec<usize>,
+}
+
+impl Match {
+    fn new(
+        candidate: &Candidate,
+        line: &StatementLine,
+        score: Option<u8>,
+        options: &Options,
+    ) -> Match {
+        let mut matched = Match {
+            header_line: candidate.header_line,
+            text: candidate.text.clone(),
+            location: candidate.location.clone(),
+            before: candidate.before.clone(),
+            after: candidate.after.clone(),
+            score,
+            postings: Vec::new(),
+            suggested: Vec::new(),
+        };
+        if !options.posting_level {
+            return matched;
+        }
+
+        let markable: Vec<(usize, &Posting)> = candidate
+            .posting_lines
+            .iter()
+            .copied()
+            .zip(&candidate.postings)
+            .filter(|(_, posting)| {
+                is_compared(posting, options) && reconcilable(posting.status, options)
+            })
+            .collect();
+        let agreeing: Vec<(usize, Decimal)> = markable
+            .iter()
+            .filter(|(_, posting)| {
+                amount::same_commodity(line.commodity, posting.commodity.as_deref())
+            })
+            .filter_map(|&(index, posting)| {
+                let ledger_amount = posting.amount?;
+                let csv_amount = line.parsed_amount?;
+                amount::amounts_match(csv_amount, ledger_amount, options.amount_tolerance)
+                    .then_some((index, ledger_amount))
+            })
+            .collect();
+        // Both legs of a simple transaction agree in magnitude, so the one with
+        // the statement's sign, usually the bank's own, is preferred.
+        let same_sign: Vec<usize> = agreeing
+            .iter()
+            .filter(|(_, ledger_amount)| {
+                line.parsed_amount
+                    .is_some_and(|csv| csv.is_sign_negative() == ledger_amount.is_sign_negative())
+            })
+            .map(|&(index, _)| index)
+            .collect();
+
+        matched.postings = markable.into_iter().map(|(index, _)| index).collect();
+        matched.suggested = if same_sign.is_empty() {
+            agreeing.into_iter().map(|(index, _)| index).collect()
+        } else {
+            same_sign
+        };
+        matched
+    }
+
+    /// The text shown for this match in the selection list, fitted to the
+    /// terminal's width.
+    fn label(&self) -> String {
+        let width = console::Term::stdout()
+            .size_checked()
+            .map(|(_, columns)| usize::from(columns));
+        self.label_within(width)
+    }
+
+    /// The transaction with its location after the header and any context
+    /// lines around it. Lines wider than `width` are cut short with an
+    /// ellipsis, the header before its location, so the payee is what gives.
+    fn label_within(&self, width: Option<usize>) -> String {
+        // Room for the cursor `inquire` draws in front of the option.
+        let width = width.map(|width| width.saturating_sub(2));
+        let fit = |line: &str, room: Option<usize>| match room {
+            Some(room) => console::truncate_str(line, room, "…").into_owned(),
+            None => line.to_string(),
+        };
+        let mut lines = self.text.lines();
+        let header = match self.score {
+            Some(score) => format!("[{:>3}%] {}", score, lines.next().unwrap_or_default()),
+            None => lines.next().unwrap_or_default().to_string(),
+        };
+        let location = format!("  ({})", self.location);
+        let room = width.map(|width| {
+            width
+                .saturating_sub(console::measure_text_width(&location))
+                .max(12)
+        });
+
+        let mut label: Vec<String> = self
+            .before
+            .iter()
+            .map(|line| fit(&format!("  ┆ {}", line), width))
+            .collect();
+        label.push(format!("{}{}", fit(&header, room), location));
+        label.extend(lines.map(|line| fit(line, width)));
+        label.extend(
+            self.after
+                .iter()
+                .map(|line| fit(&format!("  ┆ {}", line), width)),
+        );
+        label.join("\n")
+    }
+}
+
+/// `text` as descriptions are compared: lowercased unless `--case-sensitive`,
+/// and under `--ascii-fold` with accents stripped, so `Café` compares as `cafe`.
+fn comparable(text: &str, options: &Options) -> String {
+    let folded = if options.ascii_fold {
+        text.nfd().filter(|&c| !is_combining_mark(c)).collect()
+    } else {
+        text.to_string()
+    };
+    if options.case_sensitive {
+        folded
+    } else {
+        folded.to_lowercase()
+    }
+}
+
+/// Whether something with `status` is still to be reconciled: it is unmarked,
+/// or its marker isn't one of `--cleared-markers`. Without those, only pending
+/// ("!") ones are, and only when they were asked for.
+fn reconcilable(status: Option<Status>, options: &Options) -> bool {
+    match status {
+        None => true,
+        Some(status) if !options.cleared_markers.is_empty() => {
+            !options.cleared_markers.contains(&status)
+        }
+        Some(Status::Pending) => options.include_pending,
+        Some(Status::Cleared) => false,
+    }
+}
+
+/// Group the ledger into transactions and keep the ones still to be reconciled,
+/// and of those only the ones matching `filter` (the compiled
+/// `--ledger-filter`) and touching `--account` if they were given, but none of
+/// the `--exclude-account`s. Under `--posting-level` a transaction also needs a
+/// posting still to be reconciled. Payees are compared as `normalizer` rewrites
+/// them.
+fn load_candidates(
+    ledger_lines: &[String],
+    filter: Option<&Regex>,
+    normalizer: &Normalizer,
+    options: &Options,
+) -> Vec<Candidate> {
+    ledger::parse_transactions(ledger_lines, options.syntax)
+        .into_iter()
+        .filter(|transaction| reconcilable(transaction.status(ledger_lines), options))
+        .filter(|transaction| {
+            filter.is_none_or(|filter| filter.is_match(&transaction.text(ledger_lines)))
+        })
+        .map(|transaction| {
+            let text = transaction.text(ledger_lines);
+            let match_text = transaction.match_text(ledger_lines);
+            // The bank's leg is usually the one left without an amount, and
+            // its sign is what the statement's sign should be compared with.
+            let (posting_lines, postings): (Vec<usize>, Vec<Posting>) = transaction
+                .balanced_postings(ledger_lines, options.separators)
+                .into_iter()
+                .unzip();
+            let description = normalizer.ledger(&transaction.description(ledger_lines));
+            Candidate {
+                header_line: transaction.start,
+                normalized: comparable(&match_text, options).replace('$', ""),
+                text,
+                tokens: fuzzy::Tokens::new(&comparable(&description, options)),
+                date: transaction.date(ledger_lines),
+                code: transaction.code(ledger_lines).map(String::from),
+                postings,
+                posting_lines,
+                location: format!("line {}", transaction.start + 1),
+                before: ledger_lines
+                    [transaction.start.saturating_sub(options.match_context)..transaction.start]
+                    .to_vec(),
+                after: ledger_lines[transaction.end
+                    ..(transaction.end + options.match_context).min(ledger_lines.len())]
+                    .to_vec(),
+            }
+        })
+        .filter(|candidate| match &options.account {
+            Some(account) => candidate
+                .postings
+                .iter()
+                .any(|posting| posting.is_in(account)),
+            None => true,
+        })
+        .filter(|candidate| {
+            !candidate.postings.iter().any(|posting| {
+                options
+                    .exclude_accounts
+                    .iter()
+                    .any(|excluded| posting.is_in(excluded))
+            })
+        })
+        .filter(|candidate| {
+            !options.posting_level
+                || candidate
+                    .compared_postings(options)
+                    .any(|posting| reconcilable(posting.status, options))
+        })
+        .collect()
+}
+
+/// Index candidates by date and the amounts they would be compared on.
+fn index_candidates(candidates: &[Candidate], options: &Options) -> CandidateIndex {
+    CandidateIndex::build(candidates.iter().map(|candidate| index::Keys {
+        date: candidate.date,
+        amounts: candidate.amounts(options).collect(),
+        code: candidate.code.clone(),
+    }))
+}
+
+/// A statement field that `--match-fields` can compare or leave out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchField {
+    Date,
+    Description,
+    Amount,
+}
+
+/// Whether candidates are filtered on `field`; no `--match-fields` means all
+/// three are.
+fn compares(options: &Options, field: MatchField) -> bool {
+    options.match_fields.is_empty() || options.match_fields.contains(&field)
+}
+
+/// How far a candidate is from a record, in days and then in amount, for
+/// ordering matches when descriptions aren't compared. Whatever can't be
+/// measured sorts last.
+fn closeness(candidate: &Candidate, line: &StatementLine, options: &Options) -> (u64, Decimal) {
+    let days = match (line.parsed_date, candidate.date) {
+        (Some(csv_date), Some(ledger_date)) => date::days_apart(csv_date, ledger_date),
+        _ => u64::MAX,
+    };
+    let difference = line
+        .parsed_amount
+        .and_then(|csv_amount| {
+            candidate
+                .amounts(options)
+                .map(|ledger_amount| amount::difference(csv_amount, ledger_amount))
+                .min()
+        })
+        .unwrap_or(Decimal::MAX);
+    (days, difference)
+}
+
+/// How a candidate fared against each field of a CSV record.
+struct Checks {
+    date: bool,
+    amount: bool,
+    description: bool,
+    /// The description similarity under `--fuzzy`.
+    score: Option<u8>,
+}
+
+impl Checks {
+    fn passed(&self) -> bool {
+        self.date && self.amount && self.description
+    }
+}
+
+/// Whether `amount` appears in `text` as a whole figure rather than as part of
+/// a longer one, so `5.00` is not found in `15.00` or `5.001`.
+fn contains_amount(text: &str, amount: &str) -> bool {
+    if amount.is_empty() {
+        return true;
+    }
+    let digit_at = |part: &str, from_end: bool| {
+        let mut chars = part.chars();
+        let next = if from_end {
+            chars.next_back()
+        } else {
+            chars.next()
+        };
+        match next {
+            Some(c) if c.is_ascii_digit() => true,
+            // A separator only continues the figure when a digit is past it.
+            Some('.' | ',') => {
+                let after = if from_end {
+                    chars.next_back()
+                } else {
+                    chars.next()
+                };
+                after.is_some_and(|c| c.is_ascii_digit())
+            }
+            _ => false,
+        }
+    };
+    text.match_indices(amount).any(|(start, _)| {
+        !digit_at(&text[..start], true) && !digit_at(&text[start + amount.len()..], false)
+    })
+}
+
+/// Compares a candidate with a CSV record field by field.
+///
+/// Parsed dates and amounts are compared within `--date-window` and
+/// `--amount-tolerance`, and amounts must be in the same commodity when both
+/// sides name one, and go the same way when the statement account's legs are
+/// known (see [`compares_sign`]); when either side doesn't parse, the raw CSV
+/// text must appear in the transaction instead. A field left out of
+/// `--match-fields` always agrees.
+fn check_candidate(candidate: &Candidate, line: &StatementLine, options: &Options) -> Checks {
+    let signed = compares_sign(options);
+    let amount = !compares(options, MatchField::Amount)
+        || match line.parsed_amount {
+            Some(csv_amount) => candidate.compared_postings(options).any(|posting| {
+                posting.amount.is_some_and(|ledger_amount| {
+                    amount::amounts_match(csv_amount, ledger_amount, options.amount_tolerance)
+                        && (!signed || amount::signs_agree(csv_amount, ledger_amount))
+                }) && amount::same_commodity(line.commodity, posting.commodity.as_deref())
+            }),
+            None => contains_amount(
+                &candidate.normalized,
+                line.amount.trim_start_matches(['-', '+']),
+            ),
+        };
+
+    let date = !compares(options, MatchField::Date)
+        || match (line.parsed_date, candidate.date) {
+            (Some(csv_date), Some(ledger_date)) => {
+                date::within_window(csv_date, ledger_date, options.date_window)
+            }
+            _ => candidate.normalized.contains(line.date),
+        };
+    let compares_description = compares(options, MatchField::Description);
+    let score =
+        (options.fuzzy && compares_description).then(|| line.tokens.ratio(&candidate.tokens));
+    let description = !compares_description
+        || candidate.normalized.contains(line.description)
+        || score.is_some_and(|score| score >= options.similarity);
+
+    Checks {
+        date,
+        amount,
+        description,
+        score,
+    }
+}
+
+/// Compare one candidate against a CSV record, accepting it when every field
+/// agrees.
+fn match_candidate(
+    candidate: &Candidate,
+    line: &StatementLine,
+    options: &Options,
+) -> Option<Match> {
+    let checks = check_candidate(candidate, line, options);
+
+    let hit = |matched: bool| if matched { "hit" } else { "miss" };
+    debug!(
+        "{} {}: date {}, amount {}, description {}{}",
+        if checks.passed() {
+            "Accepted"
+        } else {
+            "Rejected"
+        },
+        candidate.text.lines().next().unwrap_or_default(),
+        hit(checks.date),
+        hit(checks.amount),
+        hit(checks.description),
+        checks
+            .score
+            .map_or(String::new(), |score| format!(" ({}% similar)", score)),
+    );
+
+    checks
+        .passed()
+        .then(|| Match::new(candidate, line, checks.score, options))
+}
+
+/// The record `--explain` picks: its 1-based position among the records being
+/// reconciled, or else the first whose description contains the text.
+fn explained_record(records: &[Vec<String>], wanted: &str) -> Result<usize, ReconcileError> {
+    let position = match wanted.trim().parse::<usize>() {
+        Ok(number) => (1..=records.len()).contains(&number).then(|| number - 1),
+        Err(_) => {
+            let wanted = wanted.trim().to_lowercase();
+            records
+                .iter()
+                .position(|record| record[1].to_lowercase().contains(&wanted))
+        }
+    };
+    position.ok_or_else(|| {
+        ReconcileError::InvalidOptions(format!(
+            "--explain {:?} names none of the {} statement record(s)",
+            wanted,
+            records.len()
+        ))
+    })
+}
+
+/// Spells out, for each candidate, which fields of a CSV record it agreed on
+/// and the normalized values compared, for `--explain`.
+fn explain(candidates: &[Candidate], line: &StatementLine, options: &Options) -> String {
+    let verdict = |passed: bool| if passed { "pass" } else { "fail" };
+    let mut explained = format!(
+        "Record: date {:?}, description {:?}, amount {:?}\n",
+        line.date, line.description, line.amount
+    );
+    if candidates.is_empty() {
+        explained.push_str("The ledger has no transactions left to reconcile.\n");
+    }
+    let by_code = !line.id.is_empty()
+        && candidates
+            .iter()
+            .any(|candidate| candidate.code.as_deref() == Some(line.id));
+    if by_code {
+        explained.push_str(&format!(
+            "The record's ID {:?} is a transaction code, so only the transactions with that code match.\n",
+            line.id
+        ));
+    }
+
+    for candidate in candidates {
+        let checks = check_candidate(candidate, line, options);
+        let header = candidate.text.lines().next().unwrap_or_default();
+        explained.push_str(&format!(
+            "\nline {}: {}\n",
+            candidate.header_line + 1,
+            header.trim()
+        ));
+        let coded = by_code && candidate.code.as_deref() == Some(line.id);
+        if by_code {
+            explained.push_str(&format!("  code: {}\n", verdict(coded)));
+        }
+
+        let date = if !compares(options, MatchField::Date) {
+            "not compared".to_string()
+        } else if let (Some(csv_date), Some(ledger_date)) = (line.parsed_date, candidate.date) {
+            format!(
+                "{} against {}, within {} day(s)",
+                csv_date, ledger_date, options.date_window
+            )
+        } else {
+            format!("{:?} looked for in {:?}", line.date, candidate.normalized)
+        };
+        explained.push_str(&format!("  date: {} ({})\n", verdict(checks.date), date));
+
+        let amount = if !compares(options, MatchField::Amount) {
+            "not compared".to_string()
+        } else if let Some(csv_amount) = line.parsed_amount {
+            let written = |quantity: Decimal, commodity: Option<&str>| {
+                amount::Amount {
+                    quantity,
+                    commodity: commodity.map(String::from),
+                }
+                .format(options.separators)
+            };
+            let compared: Vec<String> = candidate
+                .compared_postings(options)
+                .map(|posting| match posting.amount {
+                    Some(quantity) => format!(
+                        "{} in {}",
+                        written(quantity, posting.commodity.as_deref()),
+                        posting.account
+                    ),
+                    None => format!("no amount in {}", posting.account),
+                })
+                .collect();
+            format!(
+                "{} against {}, within {}",
+                written(csv_amount, line.commodity),
+                if compared.is_empty() {
+                    "no compared postings".to_string()
+                } else {
+                    compared.join(", ")
+                },
+                options.amount_tolerance
+            )
+        } else {
+            format!(
+                "{:?} looked for in {:?}",
+                line.amount.trim_start_matches(['-', '+']),
+                candidate.normalized
+            )
+        };
+        explained.push_str(&format!(
+            "  amount: {} ({})\n",
+            verdict(checks.amount),
+            amount
+        ));
+
+        let description = if !compares(options, MatchField::Description) {
+            "not compared".to_string()
+        } else {
+            let similarity = checks.score.map_or(String::new(), |score| {
+                format!(
+                    ", {}% similar where {}% is needed",
+                    score, options.similarity
+                )
+            });
+            format!(
+                "{:?} looked for in {:?}{}",
+                line.description, candidate.normalized, similarity
+            )
+        };
+        explained.push_str(&format!(
+            "  description: {} ({})\n",
+            verdict(checks.description),
+            description
+        ));
+        explained.push_str(if coded || (!by_code && checks.passed()) {
+            "  => matches\n"
+        } else {
+            "  => does not match\n"
+        });
+    }
+    explained
+}
+
+/// Every candidate matching a CSV record, best description score first, or
+/// nearest in date and amount when descriptions aren't compared.
+///
+/// A record whose ID equals a transaction's code matches only the transactions
+/// with that code, regardless of date, amount or description. Otherwise, when
+/// the record has a date and an exact amount is wanted, only the candidates in
+/// the index buckets around that date are compared; failing that, all of them
+/// are scanned.
+fn find_matches(
+    candidates: &[Candidate],
+    index: &CandidateIndex,
+    line: &StatementLine,
+    options: &Options,
+) -> Vec<Match> {
+    let coded = index.by_code(line.id);
+    if !line.id.is_empty() && !coded.is_empty() {
+        debug!(
+            "Matched {} candidate(s) by transaction code {}",
+            coded.len(),
+            line.id
+        );
+        return coded
+            .iter()
+            .map(|&position| Match::new(&candidates[position], line, None, options))
+            .collect();
+    }
+
+    let indexed = options.amount_tolerance.is_zero()
+        && compares(options, MatchField::Date)
+        && compares(options, MatchField::Amount);
+    let positions = match (line.parsed_date, line.parsed_amount) {
+        (Some(date), Some(amount)) if indexed => index.lookup(date, amount, options.date_window),
+        _ => None,
+    };
+
+    debug!(
+        "Comparing {} of {} candidate(s)",
+        positions.as_ref().map_or(candidates.len(), Vec::len),
+        candidates.len()
+    );
+    let compared: Vec<&Candidate> = match positions {
+        Some(positions) => positions
+            .into_iter()
+            .map(|position| &candidates[position])
+            .collect(),
+        None => candidates.iter().collect(),
+    };
+    let mut matches: Vec<(&Candidate, Match)> = compared
+        .into_iter()
+        .filter_map(|candidate| {
+            match_candidate(candidate, line, options).map(|matched| (candidate, matched))
+        })
+        .collect();
+    if compares(options, MatchField::Description) {
+        matches.sort_by_key(|(_, matched)| Reverse(matched.score));
+    } else {
+        matches.sort_by_cached_key(|(candidate, _)| closeness(candidate, line, options));
+    }
+    matches.into_iter().map(|(_, matched)| matched).collect()
+}
+
+/// The matches of every record in `records`, in order, as [`find_matches`]
+/// finds them under `options`. With `threads`, the records are shared out
+/// among that many threads, or one per CPU if it is 0; the search only reads
+/// the ledger as it was loaded, so the results are the same either way.
+fn search_all(
+    records: &[Vec<String>],
+    candidates: &[Candidate],
+    index: &CandidateIndex,
+    options: &Options,
+    threads: Option<usize>,
+) -> Result<Vec<Vec<Match>>, ReconcileError> {
+    let search = |record: &Vec<String>| {
+        let fields = RecordFields::new(record, options);
+        find_matches(candidates, index, &fields.line(record, options), options)
+    };
+    let Some(threads) = threads else {
+        return Ok(records.iter().map(search).collect());
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| ReconcileError::InvalidOptions(format!("--threads: {}", err)))?;
+    Ok(pool.install(|| records.par_iter().map(search).collect()))
+}
+
+/// How many more days either side of the statement date a relaxed retry accepts.
+const RELAXED_DAYS: u32 = 7;
+
+/// `options` for retrying a record that matched nothing: the date window is
+/// widened by [`RELAXED_DAYS`] and the amount tolerance by 1% of `amount`, or
+/// by 1 if that is more. Whatever is found is offered, never cleared outright.
+fn relaxed(options: &Options, amount: Option<Decimal>) -> Options {
+    let widening = amount.map_or(Decimal::ONE, |amount| {
+        (amount.abs() / Decimal::ONE_HUNDRED).max(Decimal::ONE)
+    });
+    Options {
+        date_window: options.date_window + RELAXED_DAYS,
+        amount_tolerance: options.amount_tolerance + widening,
+        auto: false,
+        ..options.clone()
+    }
+}
+
+/// `options` for the first pass of an `--auto` run, which only takes exact
+/// matches: the same day, the same amount and the description found in the
+/// transaction as it is.
+fn exact(options: &Options) -> Options {
+    Options {
+        date_window: 0,
+        amount_tolerance: Decimal::ZERO,
+        fuzzy: false,
+        ..options.clone()
+    }
+}
+
+/// Supported bank statement formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    Csv,
+    Ofx,
+    Qfx,
+    Qif,
+}
+
+impl InputFormat {
+    /// Guess the format from the file extension, falling back to sniffing the contents.
+    fn detect(path: &str, content: &str) -> InputFormat {
+        let extension = Path::new(path.strip_suffix(".gz").unwrap_or(path))
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") => InputFormat::Csv,
+            Some("ofx") => InputFormat::Ofx,
+            Some("qfx") => InputFormat::Qfx,
+            Some("qif") => InputFormat::Qif,
+            _ if ofx::looks_like_ofx(content) => InputFormat::Ofx,
+            _ if qif::looks_like_qif(content) => InputFormat::Qif,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// The fewest columns a CSV statement can have: a date, a description and an
+/// amount.
+const STATEMENT_COLUMNS: usize = 3;
+
+/// The header written above records from statements that aren't CSV.
+const NORMALIZED_HEADER: [&str; 4] = ["Date", "Description", "Amount", "ID"];
+
+/// The records read from one statement file, with the rows they came from.
+#[derive(Debug)]
+struct Statement {
+    /// The CSV header (empty under `--no-header`), or [`NORMALIZED_HEADER`]
+    /// for other formats.
+    header: Vec<String>,
+    /// `[date, description, amount, id]` for each record.
+    records: Vec<Vec<String>>,
+    /// The fields of each record as they appear in the file.
+    rows: Vec<Vec<String>>,
+}
+
+/// Parse statement content into `[date, description, amount, id]` records.
+///
+/// CSV rows that cannot be read or are missing a mapped column are skipped with
+/// a warning, or abort the run when `options.strict` is set, as does any record
+/// whose date or amount can't be read.
+fn parse_statement(
+    path: &str,
+    content: &str,
+    format: InputFormat,
+    options: &Options,
+) -> Result<Statement, ReconcileError> {
+    let statement = match format {
+        InputFormat::Csv => {
+            if let Some(name) = options.columns.named().filter(|_| options.no_header) {
+                return Err(ReconcileError::ColumnNotFound {
+                    path: path.to_string(),
+                    message: format!(
+                        "column \"{}\" is given by name, but --no-header files have no header to find it in; give its index instead",
+                        name
+                    ),
+                });
+            }
+            let given = options
+                .delimiter
+                .and_then(|delimiter| u8::try_from(delimiter).ok());
+            let delimiter = given.unwrap_or_else(|| columns::detect_delimiter(content));
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .has_headers(!options.no_header)
+                .flexible(true)
+                .delimiter(delimiter)
+                .from_reader(content.as_bytes());
+
+            // Without a header the first row is only used to check the column
+            // indices against; it is read again as a record.
+            let headers = csv_reader.headers().map_err(|error| ReconcileError::Csv {
+                path: path.to_string(),
+                line: error.position().map_or(1, |position| position.line()),
+                message: format!("could not be read: {}", error),
+            })?;
+            if given.is_none() && headers.len() < STATEMENT_COLUMNS {
+                warn!(
+                    "{} splits into only {} column(s) on {:?}; pass --delimiter if its fields are separated by something else.",
+                    path,
+                    headers.len(),
+                    char::from(delimiter)
+                );
+            }
+            if !options.no_header
+                && headers.iter().any(|cell| {
+                    date::parse_statement_date(cell, options.csv_date_format.as_deref()).is_some()
+                })
+            {
+                warn!(
+                    "the first row of {} holds a date, so it looks like a transaction rather than a header; pass --no-header if the file has none.",
+                    path
+                );
+            }
+            let resolved = options.columns.resolve(headers).map_err(|message| {
+                ReconcileError::ColumnNotFound {
+                    path: path.to_string(),
+                    message,
+                }
+            })?;
+
+            let header = if options.no_header {
+                Vec::new()
+            } else {
+                headers.iter().map(String::from).collect()
+            };
+            let mut records = Vec::new();
+            let mut rows = Vec::new();
+            let mut dropped = 0;
+            for result in csv_reader.records() {
+                let (line, message) = match result {
+                    Ok(record) if record.len() >= resolved.width() => {
+                        records.push(resolved.extract(&record, options.separators));
+                        rows.push(record.iter().map(String::from).collect());
+                        continue;
+                    }
+                    Ok(record) => (
+                        record.position().map_or(0, |position| position.line()),
+                        format!(
+                            "has {} field(s) but at least {} are needed",
+                            record.len(),
+                            resolved.width()
+                        ),
+                    ),
+                    Err(error) => (
+                        error.position().map_or(0, |position| position.line()),
+                        format!("could not be read: {}", error),
+                    ),
+                };
+
+                if options.strict {
+                    return Err(ReconcileError::Csv {
+                        path: path.to_string(),
+                        line,
+                        message,
+                    });
+                }
+                warn!("CSV row {} {}; skipping it.", line, message);
+                dropped += 1;
+            }
+
+            if dropped > 0 {
+                warn!("dropped {} malformed CSV row(s).", dropped);
+            }
+
+            Statement {
+                header,
+                records,
+                rows,
+            }
+        }
+        // OFX amounts always use a decimal point, so they are rewritten in the
+        // notation the rest of the run reads.
+        InputFormat::Ofx | InputFormat::Qfx => normalized(
+            ofx::parse_transactions(content)
+                .into_iter()
+                .map(|mut record| {
+                    if let Some(value) = amount::parse_amount(&record[2], Separators::default()) {
+                        record[2] = options.separators.format(value);
+                    }
+                    record
+                })
+                .collect(),
+        ),
+        // QIF has no fixed notation; amounts are read like CSV ones.
+        InputFormat::Qif => normalized(qif::parse_transactions(content)),
+    };
+
+    if options.strict {
+        for (index, record) in statement.records.iter().enumerate() {
+            if date::parse_statement_date(&record[0], options.csv_date_format.as_deref()).is_none()
+            {
+                return Err(ReconcileError::DateParse {
+                    path: path.to_string(),
+                    record: index + 1,
+                    value: record[0].clone(),
+                });
+            }
+            if amount::parse(&record[2], options.separators).is_none() {
+                return Err(ReconcileError::AmountParse {
+                    path: path.to_string(),
+                    record: index + 1,
+                    value: record[2].clone(),
+                });
+            }
+        }
+    }
+
+    Ok(statement)
+}
+
+/// A statement whose records are also its rows, under [`NORMALIZED_HEADER`].
+fn normalized(records: Vec<Vec<String>>) -> Statement {
+    Statement {
+        header: NORMALIZED_HEADER
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        rows: records.clone(),
+        records,
+    }
+}
+
+/// The transactions the run cleared, as they now read, in ledger order and
+/// separated by blank lines, so `hledger print` reads them back unchanged.
+/// Each part of a split has a decision of its own, so all of them are here.
+fn cleared_entries(
+    ledger_lines: &[String],
+    original: usize,
+    decisions: &[Decision],
+    syntax: Syntax,
+) -> String {
+    let cleared: HashSet<usize> = decisions
+        .iter()
+        .filter(|decision| decision.action == Action::Cleared)
+        .filter_map(|decision| decision.line)
+        .collect();
+    ledger::parse_transactions(&ledger_lines[..original], syntax)
+        .iter()
+        .filter(|transaction| cleared.contains(&(transaction.start + 1)))
+        .map(|transaction| format!("{}\n", transaction.text(ledger_lines)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The `(file index, row)`s given as CSV, under the header of the file the
+/// first row came from (or of the first file, if there are no rows), with
+/// `columns` added to the header for values the rows carry past the file's.
+///
+/// Rows from a file with a different header are written under it all the same,
+/// with a warning, since the same column mapping read them.
+fn statement_csv<R: AsRef<[String]>>(
+    file_headers: &[Vec<String>],
+    csv_paths: &[impl AsRef<str>],
+    columns: &[&str],
+    rows: &[(usize, R)],
+) -> io::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    let first = rows.first().map_or(0, |(file_index, _)| *file_index);
+    if let Some(header) = file_headers.get(first).filter(|header| !header.is_empty()) {
+        writer.write_record(
+            header
+                .iter()
+                .map(String::as_str)
+                .chain(columns.iter().copied()),
+        )?;
+    }
+
+    let mut warned = HashSet::new();
+    for (file_index, row) in rows {
+        if file_headers[*file_index] != file_headers[first] && warned.insert(*file_index) {
+            warn!(
+                "{} has a different header than {}; its rows are written under the latter's.",
+                csv_paths[*file_index].as_ref(),
+                csv_paths[first].as_ref()
+            );
+        }
+        writer.write_record(row.as_ref())?;
+    }
+    writer.into_inner().map_err(|err| err.into_error())
+}
+
+/// The `--csv-out` status of a record and the ledger transaction behind it:
+/// the one cleared (as it read before) or added, or the first it matched.
+fn record_status(decision: Option<&Decision>, ledger_lines: &[String]) -> [String; 2] {
+    let Some(decision) = decision else {
+        return ["undecided".to_string(), String::new()];
+    };
+    let status = match decision.action {
+        Action::Cleared => "cleared",
+        Action::Added => "added",
+        Action::Ignored => "matched",
+        Action::Unmatched => "unmatched",
+    };
+    let candidate = |candidate: &CandidateRef| (candidate.line, candidate.header.clone());
+    let transaction = match (decision.action, decision.line) {
+        (Action::Added, Some(line)) => ledger_lines
+            .get(line - 1)
+            .map(|header| (line, header.clone())),
+        (Action::Cleared, Some(line)) => decision
+            .candidates
+            .iter()
+            .find(|matched| matched.line == line)
+            .map(candidate),
+        _ => decision.candidates.first().map(candidate),
+    };
+    let transaction = transaction.map(|(line, header)| format!("line {}: {}", line, header.trim()));
+    [status.to_string(), transaction.unwrap_or_default()]
+}
+
+/// Whether a record falls within `--since`/`--until`, or `None` if its date
+/// can't be read.
+fn in_date_range(record: &[String], options: &Options) -> Option<bool> {
+    let day = date::parse_statement_date(&record[0], options.csv_date_format.as_deref())?;
+    Some(
+        options.since.is_none_or(|since| day >= since)
+            && options.until.is_none_or(|until| day <= until),
+    )
+}
+
+/// The order `--sort-csv` puts statement records in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvSort {
+    /// By statement date, oldest first.
+    Date,
+}
+
+/// Compares two records' statement dates, oldest first or under `reverse`
+/// newest first, with undated records after every dated one either way.
+fn by_date(a: &[String], b: &[String], options: &Options) -> std::cmp::Ordering {
+    let date = |record: &[String]| {
+        date::parse_statement_date(&record[0], options.csv_date_format.as_deref())
+    };
+    match (date(a), date(b)) {
+        (Some(a), Some(b)) if options.reverse => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// What a cancelled record prompt comes to.
+enum Cancelled {
+    AskAgain,
+    Skip,
+    Exit,
+}
+
+/// Asks whether a record whose prompt was cancelled should really be skipped,
+/// so a stray Esc doesn't lose it. Cancelling this question as well exits,
+/// saving the progress so far.
+fn confirm_cancel(prompter: &mut dyn Prompter) -> Cancelled {
+    match prompter.select(
+        "Skip this record?",
+        vec![ASK_AGAIN.to_string(), CONFIRM_SKIP.to_string()],
+    ) {
+        Some(1) => Cancelled::Skip,
+        Some(_) => Cancelled::AskAgain,
+        None => Cancelled::Exit,
+    }
+}
+
+/// Asks about each account in a new entry that the ledger doesn't use yet, so
+/// a typo doesn't open an account by accident. An account can be kept, which
+/// adds it to `known`, or swapped for one picked from `known`. Returns `None`
+/// if a question was cancelled.
+fn confirm_accounts(
+    prompter: &mut dyn Prompter,
+    entry: &str,
+    known: &mut BTreeSet<String>,
+    separators: Separators,
+) -> Option<String> {
+    let mut lines: Vec<String> = entry.lines().map(String::from).collect();
+    for line in lines.iter_mut().skip(1) {
+        while let Some(posting) = ledger::parse_posting(line, separators) {
+            if known.contains(&posting.account) {
+                break;
+            }
+            let message = format!("{} isn't in the ledger yet. Use it?", posting.account);
+            let choices = vec![NEW_ACCOUNT.to_string(), PICK_ACCOUNT.to_string()];
+            if prompter.select(&message, choices)? == 0 {
+                known.insert(posting.account);
+                break;
+            }
+            let accounts: Vec<String> = known.iter().cloned().collect();
+            let picked = prompter.account("Account:", &posting.account, &accounts)?;
+            if !picked.trim().is_empty() {
+                *line = line.replacen(&posting.account, picked.trim(), 1);
+            }
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+/// The accounts a new entry may post to without being confirmed: those the
+/// ledger already uses, and the ones the options name.
+fn known_accounts(
+    ledger_lines: &[String],
+    drafter: &Drafter,
+    options: &Options,
+) -> BTreeSet<String> {
+    let misc_account = options.misc_account.as_deref().unwrap_or(MISC_ACCOUNT);
+    ledger::parse_transactions(ledger_lines, options.syntax)
+        .iter()
+        .flat_map(|transaction| transaction.postings(ledger_lines, options.separators))
+        .map(|(_, posting)| posting.account)
+        .chain([misc_account.to_string(), drafter.asset_account.clone()])
+        .chain(options.actions.iter().map(|custom| custom.account.clone()))
+        .collect()
+}
+
+/// `--group-by-day`: shows each day's records next to the transactions still
+/// open within `--date-window` of it, and marks the ones picked in one go.
+///
+/// A picked transaction is credited to the first record of the day it
+/// matches, or failing that to the first record not yet credited; picks
+/// beyond the day's records are left open. Returns true if the user chose to
+/// exit, leaving the remaining days undecided.
+fn review_by_day(
+    records: &[Vec<String>],
+    candidates: &[Candidate],
+    ledger_lines: &mut [String],
+    original_lines: &[String],
+    summary: &mut Summary,
+    options: &Options,
+    prompter: &mut dyn Prompter,
+) -> bool {
+    let mut days: BTreeMap<Option<NaiveDate>, Vec<usize>> = BTreeMap::new();
+    for (index, record) in records.iter().enumerate() {
+        let day = date::parse_statement_date(&record[0], options.csv_date_format.as_deref());
+        days.entry(day).or_default().push(index);
+    }
+
+    for (day, indices) in days {
+        let fields: Vec<RecordFields> = indices
+            .iter()
+            .map(|&index| RecordFields::new(&records[index], options))
+            .collect();
+        let lines: Vec<StatementLine> = indices
+            .iter()
+            .zip(&fields)
+            .map(|(&index, fields)| fields.line(&records[index], options))
+            .collect();
+        let statement = |position: usize| StatementRecord {
+            date: fields[position].date.clone(),
+            description: records[indices[position]][1].trim().to_string(),
+            amount: fields[position].amount.clone(),
+        };
+
+        let Some(day) = day else {
+            info!(
+                "{}",
+                color::skipped(format_args!(
+                    "{} record(s) have no date to group them by.",
+                    indices.len()
+                ))
+            );
+            for (position, &record_index) in indices.iter().enumerate() {
+                summary.record(Decision {
+                    record_index,
+                    record: statement(position),
+                    candidates: Vec::new(),
+                    action: Action::Unmatched,
+                    line: None,
+                });
+            }
+            continue;
+        };
+
+        let mut open: Vec<(&Candidate, Match)> = candidates
+            .iter()
+            .filter(|candidate| {
+                candidate
+                    .date
+                    .is_some_and(|date| date::within_window(day, date, options.date_window))
+            })
+            .map(|candidate| (candidate, Match::new(candidate, &lines[0], None, options)))
+            .collect();
+        open.retain_mut(|(_, matched)| still_open(matched, ledger_lines, original_lines, options));
+        let refs: Vec<CandidateRef> = open
+            .iter()
+            .map(|(candidate, _)| CandidateRef {
+                line: candidate.header_line + 1,
+                header: ledger_lines[candidate.header_line].clone(),
+            })
+            .collect();
+
+        info!("\n=== {} ===", day);
+        for line in &lines {
+            info!("  {}  {}  {}", line.date, line.description, line.amount);
+        }
+
+        let picked = if open.is_empty() {
+            info!(
+                "{}",
+                color::skipped("No open transactions around this day.")
+            );
+            Vec::new()
+        } else {
+            let defaults: Vec<usize> = (0..open.len())
+                .filter(|&index| {
+                    lines
+                        .iter()
+                        .any(|line| check_candidate(open[index].0, line, options).passed())
+                })
+                .collect();
+            let choices: Vec<String> = open.iter().map(|(_, matched)| matched.label()).collect();
+            loop {
+                match prompter.multi_select(
+                    &format!("Clear which transactions for {}?", day),
+                    choices.clone(),
+                    &defaults,
+                ) {
+                    Some(picked) => break picked,
+                    None => match confirm_cancel(prompter) {
+                        Cancelled::AskAgain => continue,
+                        Cancelled::Skip => break Vec::new(),
+                        Cancelled::Exit => {
+                            info!("Exiting program.");
+                            return true;
+                        }
+                    },
+                }
+            }
+        };
+
+        let mut credited: Vec<Option<usize>> = vec![None; lines.len()];
+        let mut uncredited = Vec::new();
+        for &pick in &picked {
+            let agreeing = (0..lines.len()).find(|&position| {
+                credited[position].is_none()
+                    && check_candidate(open[pick].0, &lines[position], options).passed()
+            });
+            match agreeing {
+                Some(position) => credited[position] = Some(pick),
+                None => uncredited.push(pick),
+            }
+        }
+        for pick in uncredited {
+            match credited.iter().position(Option::is_none) {
+                Some(position) => credited[position] = Some(pick),
+                None => warn!(
+                    "{} has only {} record(s); left line {} open.",
+                    day,
+                    lines.len(),
+                    open[pick].0.header_line + 1
+                ),
+            }
+        }
+
+        for (position, &record_index) in indices.iter().enumerate() {
+            let marked = credited[position].and_then(|pick| {
+                let matched = Match::new(open[pick].0, &lines[position], None, options);
+                mark_match(ledger_lines, &matched, options, prompter).map(|change| (pick, change))
+            });
+            let (action, line) = match marked {
+                Some((pick, change)) => {
+                    for line in change.lines() {
+                        info!(
+                            "{}",
+                            color::cleared(format_args!(
+                                "Marked as {}: {}",
+                                options.mark.label(),
+                                ledger_lines[line].trim()
+                            ))
+                        );
+                    }
+                    (Action::Cleared, Some(open[pick].0.header_line + 1))
+                }
+                None if open.iter().any(|(candidate, _)| {
+                    check_candidate(candidate, &lines[position], options).passed()
+                }) =>
+                {
+                    (Action::Ignored, None)
+                }
+                None => (Action::Unmatched, None),
+            };
+            summary.record(Decision {
+                record_index,
+                record: statement(position),
+                candidates: refs.clone(),
+                action,
+                line,
+            });
+        }
+    }
+    false
+}
+
+/// Indices of records that repeat an earlier record's date, description and
+/// amount. Other fields, such as the bank's ID, are not compared, since an
+/// overlapping download may number the same transaction differently.
+fn find_duplicates(records: &[Vec<String>]) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| {
+            let key: Vec<&str> = record[..3].iter().map(|field| field.trim()).collect();
+            !seen.insert(key)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Mark a transaction header with `status` as `syntax` writes it, keeping the
+/// indentation of an indented header, and return the change made.
+fn mark_transaction(
+    ledger_lines: &mut [String],
+    header_line: usize,
+    status: Status,
+    syntax: Syntax,
+) -> Change {
+    let header = &ledger_lines[header_line];
+    let body = header.trim_start();
+    let marked = format!(
+        "{}{}",
+        &header[..header.len() - body.len()],
+        syntax.dialect().mark_cleared(body, status)
+    );
+    let previous = std::mem::replace(&mut ledger_lines[header_line], marked);
+    Change::Marked {
+        previous: vec![(header_line, previous)],
+    }
+}
+
+/// Prefix the posting lines `lines` with `status`.
+fn mark_postings(ledger_lines: &mut [String], lines: &[usize], status: Status) -> Change {
+    let previous = lines
+        .iter()
+        .map(|&line| {
+            let marked = ledger::posting_with_status(&ledger_lines[line], status);
+            (line, std::mem::replace(&mut ledger_lines[line], marked))
+        })
+        .collect();
+    Change::Marked { previous }
+}
+
+/// The postings of `matched` to mark under `--posting-level`: the only one
+/// there is, the one suggested under `--auto`, all the suggested ones (or all
+/// of them if none is) under `--yes`, or else the ones the user picks.
+fn choose_postings(
+    ledger_lines: &[String],
+    matched: &Match,
+    options: &Options,
+    prompter: &mut dyn Prompter,
+) -> Vec<usize> {
+    if matched.postings.len() == 1 {
+        return matched.postings.clone();
+    }
+    if options.auto && matched.suggested.len() == 1 {
+        return matched.suggested.clone();
+    }
+    if options.yes {
+        return if matched.suggested.is_empty() {
+            matched.postings.clone()
+        } else {
+            matched.suggested.clone()
+        };
+    }
+
+    let choices = matched
+        .postings
+        .iter()
+        .map(|&line| ledger_lines[line].trim().to_string())
+        .collect();
+    let defaults: Vec<usize> = matched
+        .postings
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| matched.suggested.contains(line))
+        .map(|(position, _)| position)
+        .collect();
+    prompter
+        .multi_select("Postings to mark:", choices, &defaults)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|position| matched.postings[position])
+        .collect()
+}
+
+/// Whether `matched` can still be marked. A header that no longer reads as it
+/// did on load was marked earlier in the run, possibly from another file, and
+/// must not be offered again. Comparing against the original instead of
+/// keeping a set of cleared blocks means an undo makes the transaction
+/// available again for free. Under `--posting-level` the same goes for each
+/// posting, and a transaction stays on offer while any of its postings is
+/// unmarked.
+fn still_open(
+    matched: &mut Match,
+    ledger_lines: &[String],
+    original_lines: &[String],
+    options: &Options,
+) -> bool {
+    let unchanged = |line: &usize| ledger_lines[*line] == original_lines[*line];
+    if options.posting_level {
+        matched.postings.retain(unchanged);
+        matched.suggested.retain(unchanged);
+        !matched.postings.is_empty()
+    } else {
+        unchanged(&matched.header_line)
+    }
+}
+
+/// The transactions a record's amount may be split across: those still open
+/// and dated within `--date-window` of it, whatever their description and
+/// amount, nearest first. Each comes with the amount it moves, the largest of
+/// its compared postings.
+fn split_parts(
+    candidates: &[Candidate],
+    line: &StatementLine,
+    options: &Options,
+    ledger_lines: &[String],
+    original_lines: &[String],
+) -> Vec<(Match, Decimal)> {
+    let by_date = Options {
+        match_fields: vec![MatchField::Date],
+        fuzzy: false,
+        ..options.clone()
+    };
+    let mut parts: Vec<(&Candidate, Match, Decimal)> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            let amount = candidate
+                .amounts(options)
+                .map(|amount| amount.abs())
+                .max()?;
+            let mut matched = match_candidate(candidate, line, &by_date)?;
+            still_open(&mut matched, ledger_lines, original_lines, options)
+                .then_some((candidate, matched, amount))
+        })
+        .collect();
+    parts.sort_by_cached_key(|(candidate, _, _)| closeness(candidate, line, options));
+    parts
+        .into_iter()
+        .map(|(_, matched, amount)| (matched, amount))
+        .collect()
+}
+
+/// Mark `matched` with `--mark`: its header, or under `--posting-level` the
+/// postings chosen for it, tagging the header under `--stamp-cleared`.
+/// Returns `None` if no posting was chosen.
+fn mark_match(
+    ledger_lines: &mut [String],
+    matched: &Match,
+    options: &Options,
+    prompter: &mut dyn Prompter,
+) -> Option<Change> {
+    let mut change = if options.posting_level {
+        let lines = choose_postings(ledger_lines, matched, options, prompter);
+        if lines.is_empty() {
+            return None;
+        }
+        mark_postings(ledger_lines, &lines, options.mark)
+    } else {
+        mark_transaction(
+            ledger_lines,
+            matched.header_line,
+            options.mark,
+            options.syntax,
+        )
+    };
+
+    if let (Some(date), Change::Marked { previous }) = (options.stamp_cleared, &mut change) {
+        let header = &mut ledger_lines[matched.header_line];
+        let stamped = ledger::with_tag(header, "reconciled", &date.format("%Y-%m-%d").to_string());
+        let unstamped = std::mem::replace(header, stamped);
+        if !previous
+            .iter()
+            .any(|(line, _)| *line == matched.header_line)
+        {
+            previous.push((matched.header_line, unstamped));
+        }
+    }
+    Some(change)
+}
+
+/// The amounts of a drafted entry's two legs for a CSV amount in `commodity`,
+/// written in the notation of `separators`: the chosen account's, then the
+/// bank's. The bank's leg keeps the statement's sign, so a withdrawal of
+/// `-4.50` books `$4.50` to the account and a deposit of `2000.00` books
+/// `-$2000.00` to it.
+fn entry_amounts(amount: &str, commodity: &str, separators: Separators) -> (String, String) {
+    match amount::parse_amount(amount, separators) {
+        Some(value) => {
+            let posting = |quantity| {
+                amount::Amount {
+                    quantity,
+                    commodity: Some(commodity.to_string()),
+                }
+                .format(separators)
+            };
+            (posting(-value), posting(value))
+        }
+        None => {
+            let (magnitude, withdrawal) = match amount.strip_prefix('-') {
+                Some(magnitude) => (magnitude, true),
+                None => (amount, false),
+            };
+            let (into, out) = if commodity.chars().all(char::is_alphabetic) {
+                (
+                    format!("{} {}", magnitude, commodity),
+                    format!("-{} {}", magnitude, commodity),
+                )
+            } else {
+                (format!("${}", magnitude), format!("-${}", magnitude))
+            };
+            if withdrawal {
+                (into, out)
+            } else {
+                (out, into)
+            }
+        }
+    }
+}
+
+/// How new entries are drafted: from `--template` or a named template where
+/// one applies, or else as two postings between the chosen account and the
+/// bank, which moves the record's amount with its sign, laid out in `style`.
+struct Drafter {
+    style: PostingStyle,
+    separators: Separators,
+    syntax: Syntax,
+    asset_account: String,
+    default: Option<Template>,
+    named: BTreeMap<String, Template>,
+}
+
+impl Drafter {
+    /// Loads `--template` and the named templates, making sure each renders
+    /// a balanced entry and that every name `rules` refers to exists.
+    fn load(
+        options: &Options,
+        rules: &Rules,
+        style: PostingStyle,
+    ) -> Result<Drafter, ReconcileError> {
+        let check = |path: &str| -> Result<Template, ReconcileError> {
+            let template = Template::load(path)?;
+            let (amount, negated) = entry_amounts(
+                "1",
+                options.syntax.dialect().commodity("$"),
+                options.separators,
+            );
+            let sample = template.render(&template::Fields {
+                date: "2025-01-01",
+                description: "sample",
+                amount: &amount,
+                negated: &negated,
+                account: options.misc_account.as_deref().unwrap_or(MISC_ACCOUNT),
+            });
+            ledger::check_entry(&sample, options.separators, options.syntax).map_err(
+                |message| ReconcileError::Template {
+                    path: path.to_string(),
+                    message: format!("the drafted entry is not valid: {}", message),
+                },
+            )?;
+            Ok(template)
+        };
+
+        let default = options.template.as_deref().map(check).transpose()?;
+        let named = options
+            .templates
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), check(path)?)))
+            .collect::<Result<BTreeMap<_, _>, ReconcileError>>()?;
+
+        let referenced = rules.templates().chain(
+            options
+                .actions
+                .iter()
+                .filter_map(|custom| custom.template.as_deref()),
+        );
+        for name in referenced {
+            if !named.contains_key(name) {
+                return Err(ReconcileError::InvalidOptions(format!(
+                    "no template named \"{}\" is configured",
+                    name
+                )));
+            }
+        }
+
+        Ok(Drafter {
+            style,
+            separators: options.separators,
+            syntax: options.syntax,
+            asset_account: options
+                .asset_account
+                .clone()
+                .unwrap_or_else(|| ASSET_ACCOUNT.to_string()),
+            default,
+            named,
+        })
+    }
+
+    /// The entry for a CSV record booked to `account`, from the template
+    /// named `template` if given.
+    fn draft(
+        &self,
+        template: Option<&str>,
+        date: &str,
+        description: &str,
+        amount: &str,
+        commodity: &str,
+        account: &str,
+    ) -> String {
+        let chosen = match template {
+            Some(name) => self.named.get(name),
+            None => self.default.as_ref(),
+        };
+        let commodity = self.syntax.dialect().commodity(commodity);
+        let (amount, negated) = entry_amounts(amount, commodity, self.separators);
+        let Some(chosen) = chosen else {
+            return self.syntax.dialect().render(
+                date,
+                description,
+                &[
+                    self.style.posting(account, &amount),
+                    self.style.posting(&self.asset_account, &negated),
+                ],
+            );
+        };
+
+        chosen.render(&template::Fields {
+            date,
+            description,
+            amount: &amount,
+            negated: &negated,
+            account,
+        })
+    }
+}
+
+/// Copy the ledger to `<ledger>.bak`, or to a timestamped name if that already exists.
+fn backup_ledger(ledger_path: &str) -> io::Result<String> {
+    let mut backup_path = format!("{}.bak", ledger_path);
+    if Path::new(&backup_path).exists() {
+        let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+        backup_path = format!("{}.{}.bak", ledger_path, timestamp);
+    }
+
+    fs::copy(ledger_path, &backup_path)?;
+    Ok(backup_path)
+}
+
+/// Strips trailing whitespace from the lines the run wrote, leaving every line
+/// that still reads as it was loaded alone. Returns how many lines changed.
+fn trim_touched(ledger_lines: &mut [String], original_lines: &[String]) -> usize {
+    let mut trimmed = 0;
+    for (index, line) in ledger_lines.iter_mut().enumerate() {
+        if original_lines.get(index) == Some(line) {
+            continue;
+        }
+        // An added entry is pushed as one element holding all of its lines.
+        let untrimmed = line
+            .split('\n')
+            .filter(|part| part.trim_end() != *part)
+            .count();
+        if untrimmed > 0 {
+            trimmed += untrimmed;
+            *line = line
+                .split('\n')
+                .map(str::trim_end)
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+    }
+    trimmed
+}
+
+/// The 1-based line number that an entry pushed onto `ledger_lines` would start at.
+fn next_line_number(ledger_lines: &[String]) -> usize {
+    ledger_lines
+        .iter()
+        .map(|line| line.lines().count().max(1))
+        .sum::<usize>()
+        + 1
+}
+
+/// Revert the latest change and roll the summary back to match.
+///
+/// Returns the index of the CSV record to resume from, which is the record
+/// whose decision was undone.
+fn undo_last(
+    history: &mut History,
+    ledger_lines: &mut Vec<String>,
+    summary: &mut Summary,
+) -> Option<usize> {
+    let undone = history.undo(ledger_lines)?;
+    info!("Undid last action: {}", undone.description);
+    *summary = undone.summary;
+    Some(undone.record_index)
+}
+
+/// Indices of the records without a decision, in statement order.
+fn undecided(summary: &Summary, records: usize) -> Vec<usize> {
+    let decided: BTreeSet<usize> = summary
+        .decisions
+        .iter()
+        .map(|decision| decision.record_index)
+        .collect();
+    (0..records)
+        .filter(|index| !decided.contains(index))
+        .collect()
+}
+
+/// Lists the records `--interactive-edit-unmatched` passed over and asks what
+/// to do with them. Returns the ones to ignore; the others get the usual menu
+/// one by one.
+fn review_unmatched(
+    csv_records: &[Vec<String>],
+    pending: &[usize],
+    prompter: &mut dyn Prompter,
+) -> BTreeSet<usize> {
+    let labels: Vec<String> = pending
+        .iter()
+        .map(|&index| {
+            let record = &csv_records[index];
+            format!(
+                "[{}] {}  {}  {}",
+                index + 1,
+                record[0].trim(),
+                record[1].trim(),
+                record[2].trim()
+            )
+        })
+        .collect();
+    info!("\n--- {} record(s) without a match ---", pending.len());
+    for label in &labels {
+        info!("{}", label);
+    }
+    info!("-------------------------------");
+
+    let actions = [REVIEW_EACH, IGNORE_ALL, IGNORE_SOME];
+    let choices = actions.iter().map(ToString::to_string).collect();
+    match prompter.select("What would you like to do with them?", choices) {
+        Some(1) => pending.iter().copied().collect(),
+        Some(2) => prompter
+            .multi_select("Ignore which records?", labels, &[])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|chosen| pending[chosen])
+            .collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+/// A `--assert-balance` check: the cleared balance an account should have.
+struct BalanceAssertion {
+    account: String,
+    expected: amount::Amount,
+}
+
+impl BalanceAssertion {
+    /// Parses `ACCOUNT=AMOUNT`, splitting at the last `=`.
+    fn parse(raw: &str, separators: Separators) -> Result<BalanceAssertion, ReconcileError> {
+        raw.rsplit_once('=')
+            .map(|(account, expected)| (account.trim(), expected))
+            .filter(|(account, _)| !account.is_empty())
+            .and_then(|(account, expected)| {
+                Some(BalanceAssertion {
+                    account: account.to_string(),
+                    expected: amount::parse(expected, separators)?,
+                })
+            })
+            .ok_or_else(|| {
+                ReconcileError::InvalidOptions(format!(
+                    "--assert-balance {:?} is not of the form ACCOUNT=AMOUNT",
+                    raw
+                ))
+            })
+    }
+}
+
+/// The `--opening-balance` and `--closing-balance` of the statement period
+/// being reconciled for `--account`.
+struct StatementPeriod {
+    account: String,
+    opening: amount::Amount,
+    closing: amount::Amount,
+}
+
+impl StatementPeriod {
+    /// Reads the period from `options`, or `None` if no balances were given.
+    fn from_options(options: &Options) -> Result<Option<StatementPeriod>, ReconcileError> {
+        let (opening, closing, account) = match (
+            &options.opening_balance,
+            &options.closing_balance,
+            &options.account,
+        ) {
+            (None, None, _) => return Ok(None),
+            (Some(opening), Some(closing), Some(account)) => (opening, closing, account),
+            _ => return Err(ReconcileError::InvalidOptions(
+                "--opening-balance and --closing-balance must be given together, with --account"
+                    .to_string(),
+            )),
+        };
+        let parse = |flag: &str, raw: &str| {
+            amount::parse(raw, options.separators).ok_or_else(|| {
+                ReconcileError::InvalidOptions(format!("{} {:?} is not an amount", flag, raw))
+            })
+        };
+        let opening = parse("--opening-balance", opening)?;
+        let closing = parse("--closing-balance", closing)?;
+        if !amount::same_commodity(opening.commodity.as_deref(), closing.commodity.as_deref()) {
+            return Err(ReconcileError::InvalidOptions(format!(
+                "--opening-balance {} and --closing-balance {} are in different commodities",
+                opening.format(options.separators),
+                closing.format(options.separators)
+            )));
+        }
+        Ok(Some(StatementPeriod {
+            account: account.clone(),
+            opening,
+            closing,
+        }))
+    }
+}
+
+/// The sum of the cleared postings to `account` or its subaccounts, counting
+/// only amounts in `commodity` when it is given.
+///
+/// A posting without a marker of its own takes its transaction's status, and
+/// an elided amount counts as what balances the rest of its transaction.
+fn cleared_balance(
+    ledger: &str,
+    account: &str,
+    commodity: Option<&str>,
+    separators: Separators,
+    syntax: Syntax,
+) -> Decimal {
+    let lines: Vec<String> = ledger.lines().map(String::from).collect();
+    let mut balance = Decimal::ZERO;
+    for transaction in ledger::parse_transactions(&lines, syntax) {
+        let status = transaction.status(&lines);
+        for (_, posting) in transaction.balanced_postings(&lines, separators) {
+            let Some(quantity) = posting.amount else {
+                continue;
+            };
+            if posting.status.or(status) == Some(Status::Cleared)
+                && posting.is_in(account)
+                && amount::same_commodity(posting.commodity.as_deref(), commodity)
+            {
+                balance += quantity;
+            }
+        }
+    }
+    balance
+}
+
+/// Compare the cleared balance of each asserted account in `ledger` with the
+/// asserted amount, failing on the first that differs by more than
+/// `--amount-tolerance`.
+fn check_balances(
+    ledger: &str,
+    assertions: &[BalanceAssertion],
+    options: &Options,
+) -> Result<(), ReconcileError> {
+    for assertion in assertions {
+        let expected = &assertion.expected;
+        let actual = amount::Amount {
+            quantity: cleared_balance(
+                ledger,
+                &assertion.account,
+                expected.commodity.as_deref(),
+                options.separators,
+                options.syntax,
+            ),
+            commodity: expected.commodity.clone(),
+        };
+        if (actual.quantity - expected.quantity).abs() > options.amount_tolerance {
+            return Err(ReconcileError::BalanceMismatch {
+                account: assertion.account.clone(),
+                expected: expected.format(options.separators),
+                actual: actual.format(options.separators),
+            });
+        }
+        info!(
+            "Cleared balance of {} is {}, as asserted.",
+            assertion.account,
+            actual.format(options.separators)
+        );
+    }
+    Ok(())
+}
+
+/// Check that the opening balance plus what this run cleared to the period's
+/// account, the difference between its cleared balance in `before` and in
+/// `after`, comes to the closing balance within `--amount-tolerance`.
+fn check_period(
+    before: &str,
+    after: &str,
+    period: Option<&StatementPeriod>,
+    options: &Options,
+) -> Result<(), ReconcileError> {
+    let Some(period) = period else {
+        return Ok(());
+    };
+    let commodity = period
+        .closing
+        .commodity
+        .as_deref()
+        .or(period.opening.commodity.as_deref());
+    let cleared = |ledger: &str| {
+        cleared_balance(
+            ledger,
+            &period.account,
+            commodity,
+            options.separators,
+            options.syntax,
+        )
+    };
+    let amount = |quantity| {
+        amount::Amount {
+            quantity,
+            commodity: commodity.map(String::from),
+        }
+        .format(options.separators)
+    };
+    let this_run = cleared(after) - cleared(before);
+    let actual = period.opening.quantity + this_run;
+    let difference = actual - period.closing.quantity;
+    if difference.abs() > options.amount_tolerance {
+        return Err(ReconcileError::ClosingBalanceMismatch {
+            account: period.account.clone(),
+            expected: amount(period.closing.quantity),
+            actual: amount(actual),
+            difference: amount(difference),
+        });
+    }
+    info!(
+        "Opening balance {} plus {} cleared this run closes {} at {}, as the statement does.",
+        amount(period.opening.quantity),
+        amount(this_run),
+        period.account,
+        amount(actual)
+    );
+    Ok(())
+}
+
+/// The first bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read a file, or all of stdin when the path is `-`, decompressing it if it
+/// is gzipped.
+pub fn read_input(path: &str) -> io::Result<String> {
+    String::from_utf8(read_bytes(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Read a statement like [`read_input`], decoding it from `encoding`, or
+/// detecting the encoding when that is `None`; see [`Encoding`].
+pub fn read_statement(path: &str, encoding: Option<Encoding>) -> io::Result<String> {
+    encoding::decode(&read_bytes(path)?, encoding)
+}
+
+/// The bytes of a file, or of stdin when the path is `-`, uncompressed if
+/// they are gzipped.
+fn read_bytes(path: &str) -> io::Result<Vec<u8>> {
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(path)?
+    };
+
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut content = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut content)?;
+        Ok(content)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// A file the ledger pulls in with an `include` directive.
+struct Included {
+    path: String,
+    /// The lines of the combined ledger read from this file.
+    lines: Range<usize>,
+    layout: ledger::Layout,
+}
+
+/// Appends the lines of every file that `lines[scan]` includes, and of the
+/// files those include in turn, to `lines`. Paths are relative to the file
+/// holding the directive, `dir`; a file already read is not read again, so
+/// an include cycle ends.
+fn read_includes(
+    lines: &mut Vec<String>,
+    scan: Range<usize>,
+    dir: &Path,
+    seen: &mut HashSet<PathBuf>,
+    included: &mut Vec<Included>,
+) -> Result<(), ReconcileError> {
+    for index in scan {
+        let Some(name) = ledger::include_path(&lines[index]) else {
+            continue;
+        };
+        let path = dir.join(name);
+        let display = path.to_string_lossy().into_owned();
+        let canonical = fs::canonicalize(&path).map_err(ReconcileError::io(&display))?;
+        if !seen.insert(canonical) {
+            warn!(
+                "{} is included more than once; reading it only the first time.",
+                display
+            );
+            continue;
+        }
+
+        let content = read_input(&display).map_err(ReconcileError::io(&display))?;
+        let start = lines.len();
+        lines.extend(content.lines().map(String::from));
+        let end = lines.len();
+        debug!("Read {} line(s) included from {}", end - start, display);
+        included.push(Included {
+            path: display,
+            lines: start..end,
+            layout: ledger::Layout::detect(&content),
+        });
+        let child_dir = path.parent().unwrap_or(dir).to_path_buf();
+        read_includes(lines, start..end, &child_dir, seen, included)?;
+    }
+    Ok(())
+}
+
+/// Write a file, or to stdout when the path is `-`, gzipped if the path ends
+/// in `.gz`.
+fn write_output(path: &str, content: &str) -> io::Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(content.as_bytes())?;
+        stdout.flush()
+    } else if path.ends_with(".gz") {
+        let mut encoder = GzEncoder::new(fs::File::create(path)?, Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    } else {
+        fs::write(path, content)
+    }
+}
+
+/// Whether `a` and `b` name the same existing file; stdin and stdout never do.
+pub fn same_file(a: &str, b: &str) -> bool {
+    if a == "-" || b == "-" {
+        return false;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Reject path combinations that can't work with stdin/stdout.
+fn check_stdio_paths(
+    ledger_path: &str,
+    csv_paths: &[impl AsRef<str>],
+    output_path: &str,
+    options: &Options,
+) -> Result<(), ReconcileError> {
+    let stdin_inputs = std::iter::once(ledger_path)
+        .chain(csv_paths.iter().map(AsRef::as_ref))
+        .filter(|path| *path == "-")
+        .count();
+
+    let problem = if stdin_inputs > 1 {
+        Some("only one of the ledger and CSV files can be read from stdin")
+    } else if ledger_path == "-" && options.backup {
+        Some("a ledger read from stdin cannot be backed up")
+    } else if output_path == "-" && !options.yes {
+        Some("writing the ledger to stdout requires --yes, since prompts are drawn on stdout")
+    } else if output_path == "-" && options.resume {
+        Some("--resume needs an output file, since the checkpoint is kept next to it")
+    } else {
+        None
+    };
+
+    match problem {
+        Some(message) => Err(ReconcileError::InvalidOptions(message.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// What to reconcile, and how.
+#[derive(Clone, Debug, Default)]
+pub struct ReconcileConfig {
+    /// Path to the ledger file, or `-` to read it from stdin.
+    pub ledger: String,
+    /// Paths to the statement files, worked through in order; one may be `-`.
+    pub statements: Vec<String>,
+    /// Where the updated ledger is written, or `-` for stdout.
+    pub output: String,
+    pub options: Options,
+}
+
+/// The outcome of a reconciliation run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReconcileReport {
+    /// Ledger transactions marked as cleared.
+    pub cleared: usize,
+    /// Statement records that had matches but were skipped.
+    pub ignored: usize,
+    /// New transactions appended to the ledger.
+    pub added: usize,
+    /// Statement records with no ledger match that were not added.
+    pub unmatched: usize,
+    /// Whether the user stopped the run early, with "Exit" or "Skip all
+    /// remaining", leaving some records undecided.
+    pub aborted: bool,
+    /// The updated ledger text, as written to the output (or as it would have
+    /// been, under `dry_run`).
+    pub ledger: String,
+}
+
+/// Reconciles the statements in `config` against its ledger, asking on the
+/// terminal whenever a decision is needed.
+pub fn reconcile(config: &ReconcileConfig) -> Result<ReconcileReport, ReconcileError> {
+    process_files(
+        &config.ledger,
+        &config.statements,
+        &config.output,
+        &config.options,
+    )
+}
+
+/// Like [`reconcile`], but asking `prompter` instead of the terminal.
+pub fn reconcile_with(
+    config: &ReconcileConfig,
+    prompter: &mut dyn Prompter,
+) -> Result<ReconcileReport, ReconcileError> {
+    process_files_with(
+        &config.ledger,
+        &config.statements,
+        &config.output,
+        &config.options,
+        prompter,
+    )
+}
+
+/// Read and process the ledger and CSV files, then match transactions interactively.
+fn process_files(
+    ledger_path: &str,
+    csv_paths: &[impl AsRef<str>],
+    output_path: &str,
+    options: &Options,
+) -> Result<ReconcileReport, ReconcileError> {
+    process_files_with(
+        ledger_path,
+        csv_paths,
+        output_path,
+        options,
+        &mut prompt::Terminal,
+    )
+}
+
+/// Like [`process_files`], but asking `prompter` instead of the terminal.
+///
+/// The CSV files are worked through in order against the same ledger lines, and
+/// the output is written once at the end.
+fn process_files_with(
+    ledger_path: &str,
+    csv_paths: &[impl AsRef<str>],
+    output_path: &str,
+    options: &Options,
+    prompter: &mut dyn Prompter,
+) -> Result<ReconcileReport, ReconcileError> {
+    check_stdio_paths(ledger_path, csv_paths, output_path, options)?;
+    if options.annotate_header.is_some() && same_file(ledger_path, output_path) {
+        return Err(ReconcileError::InvalidOptions(
+            "--annotate-header writes a separate copy and leaves the ledger untouched, so --output must be another file"
+                .to_string(),
+        ));
+    }
+    if let (Some(since), Some(until)) = (options.since, options.until) {
+        if since > until {
+            return Err(ReconcileError::InvalidOptions(format!(
+                "--since {} is after --until {}",
+                since, until
+            )));
+        }
+    }
+    if options.group_by_day
+        && (options.auto || options.count || options.resume || options.explain.is_some())
+    {
+        return Err(ReconcileError::InvalidOptions(
+            "--group-by-day reviews every day interactively, so it can't be combined with --auto, --count, --resume or --explain"
+                .to_string(),
+        ));
+    }
+    if options.reverse_match && options.csv_out.is_none() {
+        return Err(ReconcileError::InvalidOptions(
+            "--reverse-match writes nothing but the annotated statement, so it needs --csv-out"
+                .to_string(),
+        ));
+    }
+    if options.include_pending && !options.cleared_markers.is_empty() {
+        return Err(ReconcileError::InvalidOptions(
+            "--include-pending is --cleared-markers '*'; give one or the other".to_string(),
+        ));
+    }
+    if options.threads.is_some() && !options.auto {
+        return Err(ReconcileError::InvalidOptions(
+            "--threads only applies to --auto runs; interactive runs search one record at a time"
+                .to_string(),
+        ));
+    }
+    if options.group_by_day && options.interactive_edit_unmatched {
+        return Err(ReconcileError::InvalidOptions(
+            "--group-by-day and --interactive-edit-unmatched each reorder the review; pick one"
+                .to_string(),
+        ));
+    }
+    if let Some(delimiter) = options.delimiter.filter(|delimiter| !delimiter.is_ascii()) {
+        return Err(ReconcileError::InvalidOptions(format!(
+            "--delimiter {:?} must be a single ASCII character",
+            delimiter
+        )));
+    }
+    if let Separators {
+        decimal: Some(decimal),
+        thousands: Some(thousands),
+    } = options.separators
+    {
+        if decimal == thousands {
+            return Err(ReconcileError::InvalidOptions(format!(
+                "--decimal-sep and --thousands-sep are both {:?}",
+                decimal
+            )));
+        }
+    }
+    let assertions = options
+        .assert_balances
+        .iter()
+        .map(|raw| BalanceAssertion::parse(raw, options.separators))
+        .collect::<Result<Vec<_>, _>>()?;
+    let period = StatementPeriod::from_options(options)?;
+    let built_in = [
+        IGNORE,
+        ADD_NEW,
+        CREATE_AND_CLEAR,
+        RELAX_AND_RETRY,
+        SPLIT,
+        SKIP_ALL_REMAINING,
+        EXIT,
+        UNDO,
+    ];
+    for (index, custom) in options.actions.iter().enumerate() {
+        if built_in.contains(&custom.name.as_str())
+            || options.actions[..index]
+                .iter()
+                .any(|earlier| earlier.name == custom.name)
+        {
+            return Err(ReconcileError::InvalidOptions(format!(
+                "the action \"{}\" is defined more than once",
+                custom.name
+            )));
+        }
+    }
+    for (flag, account) in [
+        ("--misc-account", &options.misc_account),
+        ("--asset-account", &options.asset_account),
+    ] {
+        if account
+            .as_deref()
+            .is_some_and(|account| account.trim().is_empty())
+        {
+            return Err(ReconcileError::InvalidOptions(format!(
+                "{} must name an account",
+                flag
+            )));
+        }
+    }
+    if output_path == "-" {
+        MESSAGES_TO_STDERR.store(true, Ordering::Relaxed);
+    }
+
+    let ledger_content = read_input(ledger_path).map_err(ReconcileError::io(ledger_path))?;
+    let layout = ledger::Layout::detect(&ledger_content);
+    let mut ledger_lines: Vec<String> = ledger_content.lines().map(String::from).collect();
+    // Included files follow the ledger's own lines, so a transaction keeps
+    // its line index whichever file it came from.
+    let own_lines = ledger_lines.len();
+    let mut included = Vec::new();
+    let ledger_dir = Path::new(ledger_path)
+        .parent()
+        .filter(|_| ledger_path != "-")
+        .unwrap_or(Path::new(""));
+    let mut seen: HashSet<PathBuf> = fs::canonicalize(ledger_path).into_iter().collect();
+    read_includes(
+        &mut ledger_lines,
+        0..own_lines,
+        ledger_dir,
+        &mut seen,
+        &mut included,
+    )?;
+    if options.annotate_header.is_some() && !included.is_empty() {
+        return Err(ReconcileError::InvalidOptions(
+            "--annotate-header cannot leave a ledger untouched when it includes other files, \
+             since they are updated in place"
+                .to_string(),
+        ));
+    }
+    // Clears in included files can only be written back to those files, so
+    // they are only touched when the ledger itself is.
+    let writes =
+        !options.dry_run && !options.count && !options.reverse_match && options.explain.is_none();
+    if writes && !included.is_empty() && !same_file(ledger_path, output_path) {
+        return Err(ReconcileError::InvalidOptions(format!(
+            "{} includes other files, and what is cleared in them is written back to them; \
+             pass --in-place to update the ledger and its included files, or --dry-run to preview",
+            ledger_path
+        )));
+    }
+    let original_lines = ledger_lines.clone();
+    let mut summary = Summary::default();
+
+    let ledger_filter = options
+        .ledger_filter
+        .as_deref()
+        .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+        .transpose()
+        .map_err(|err| ReconcileError::InvalidOptions(format!("--ledger-filter: {}", err)))?;
+    let normalizer = Normalizer::new(&options.normalize).map_err(ReconcileError::InvalidOptions)?;
+    let ignore_descs = options
+        .ignore_descs
+        .iter()
+        .map(|raw| {
+            rules::Pattern::parse(raw)
+                .map(|pattern| (raw.as_str(), pattern))
+                .map_err(|err| {
+                    ReconcileError::InvalidOptions(format!("--ignore-desc {:?}: {}", raw, err))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut uncleared_transactions =
+        load_candidates(&ledger_lines, ledger_filter.as_ref(), &normalizer, options);
+    for candidate in &mut uncleared_transactions {
+        let Some(file) = included
+            .iter()
+            .find(|file| file.lines.contains(&candidate.header_line))
+        else {
+            continue;
+        };
+        let name = Path::new(&file.path)
+            .file_name()
+            .map_or(file.path.as_str(), |name| {
+                name.to_str().unwrap_or(&file.path)
+            });
+        candidate.location = format!("{}:{}", name, candidate.header_line - file.lines.start + 1);
+    }
+    for candidate in &uncleared_transactions {
+        if let Some(problem) = ledger::balance_problem(&candidate.postings) {
+            let header = ledger_lines[candidate.header_line].clone();
+            warn!(
+                "line {}: \"{}\" does not balance: {}",
+                candidate.header_line + 1,
+                header,
+                problem
+            );
+            summary.unbalanced.push(UnbalancedTransaction {
+                line: candidate.header_line + 1,
+                header,
+                problem,
+            });
+        }
+    }
+    if options.strict && !summary.unbalanced.is_empty() {
+        warn!("--strict: unbalanced transactions will not be cleared.");
+        uncleared_transactions
+            .retain(|candidate| ledger::balance_problem(&candidate.postings).is_none());
+    }
+    let candidate_index = index_candidates(&uncleared_transactions, options);
+
+    // Read every file up front so a bad one is reported before any prompting.
+    let mut csv_records = Vec::new();
+    let mut record_files = Vec::new();
+    let mut record_rows = Vec::new();
+    let mut file_headers = Vec::new();
+    for (file_index, csv_path) in csv_paths.iter().enumerate() {
+        let csv_path = csv_path.as_ref();
+        let csv_content =
+            read_statement(csv_path, options.encoding).map_err(ReconcileError::io(csv_path))?;
+        let format = options
+            .format
+            .unwrap_or_else(|| InputFormat::detect(csv_path, &csv_content));
+        let statement = parse_statement(csv_path, &csv_content, format, options)?;
+        record_files.extend(std::iter::repeat_n(file_index, statement.records.len()));
+        csv_records.extend(statement.records);
+        record_rows.extend(statement.rows);
+        file_headers.push(statement.header);
+    }
+    if !options.normalize.is_empty() {
+        for record in &mut csv_records {
+            record[1] = normalizer.statement(StatementField::Description, &record[1]);
+            record[2] = normalizer.statement(StatementField::Amount, &record[2]);
+        }
+    }
+    if let Some(path) = &options.payee_map {
+        let payees = PayeeMap::load(path)?;
+        for record in &mut csv_records {
+            if let Some(payee) = payees.payee_for(&record[1]) {
+                debug!("Read {:?} as payee {:?}", record[1], payee);
+                record[1] = payee.to_string();
+                summary.remapped += 1;
+            }
+        }
+    }
+
+    // An amount with stray separators may still parse, as the wrong number,
+    // so it is counted in any case and dropped under --strict-amount-format.
+    let malformed = |record: &Vec<String>| !amount::well_formed(&record[2], options.separators);
+    summary.malformed_amounts = csv_records
+        .iter()
+        .filter(|record| malformed(record))
+        .count();
+    if summary.malformed_amounts > 0 && options.strict_amount_format {
+        let mut in_file = vec![0; csv_paths.len()];
+        let mut keep = Vec::new();
+        for (record, &file) in csv_records.iter().zip(&record_files) {
+            in_file[file] += 1;
+            keep.push(!malformed(record));
+            if !malformed(record) {
+                continue;
+            }
+            let path = csv_paths[file].as_ref();
+            if options.strict {
+                return Err(ReconcileError::AmountParse {
+                    path: path.to_string(),
+                    record: in_file[file],
+                    value: record[2].clone(),
+                });
+            }
+            warn!(
+                "{}: record {}: {:?} is not a well-formed amount; skipping it.",
+                path, in_file[file], record[2]
+            );
+        }
+        ((csv_records, record_files), record_rows) = csv_records
+            .into_iter()
+            .zip(record_files)
+            .zip(record_rows)
+            .zip(keep)
+            .filter_map(|(kept, keep)| keep.then_some(kept))
+            .unzip();
+    } else if summary.malformed_amounts > 0 {
+        warn!(
+            "{} CSV record(s) have amounts that aren't well-formed numbers; pass --strict-amount-format to skip them.",
+            summary.malformed_amounts
+        );
+    }
+    if options.invert_amount {
+        for record in &mut csv_records {
+            if let Some(parsed) = amount::parse(&record[2], options.separators) {
+                if !parsed.quantity.is_zero() {
+                    record[2] = amount::Amount {
+                        quantity: -parsed.quantity,
+                        ..parsed
+                    }
+                    .format(options.separators);
+                }
+            }
+        }
+    }
+
+    if options.since.is_some() || options.until.is_some() {
+        let before = csv_records.len();
+        let undated = csv_records
+            .iter()
+            .filter(|record| in_date_range(record, options).is_none())
+            .count();
+        ((csv_records, record_files), record_rows) = csv_records
+            .into_iter()
+            .zip(record_files)
+            .zip(record_rows)
+            .filter(|((record, _), _)| in_date_range(record, options) == Some(true))
+            .unzip();
+
+        if undated > 0 {
+            warn!(
+                "{} CSV record(s) have no readable date and were left out by --since/--until.",
+                undated
+            );
+        }
+        summary.filtered_out = before - csv_records.len();
+        info!(
+            "Filtered out {} CSV record(s) outside the date range.",
+            summary.filtered_out
+        );
+    }
+
+    if options.ignore_below.is_some() || !ignore_descs.is_empty() {
+        let noise = |record: &Vec<String>| {
+            if let Some(pattern) = ignore_descs
+                .iter()
+                .find(|(_, pattern)| pattern.matches(&record[1]))
+            {
+                return Some(format!(
+                    "its description matches --ignore-desc {:?}",
+                    pattern.0
+                ));
+            }
+            let threshold = options.ignore_below?;
+            amount::parse_amount(&record[2], options.separators)
+                .filter(|amount| amount.abs() < threshold)
+                .map(|_| format!("it is below --ignore-below {}", threshold))
+        };
+        let before = csv_records.len();
+        ((csv_records, record_files), record_rows) = csv_records
+            .into_iter()
+            .zip(record_files)
+            .zip(record_rows)
+            .filter(|((record, _), _)| match noise(record) {
+                Some(reason) => {
+                    info!(
+                        "Skipping {}  {}  {}: {}.",
+                        record[0].trim(),
+                        record[1].trim(),
+                        record[2].trim(),
+                        reason
+                    );
+                    false
+                }
+                None => true,
+            })
+            .unzip();
+        summary.noise = before - csv_records.len();
+    }
+
+    // Overlapping downloads repeat records, which would otherwise be left
+    // unmatched once their ledger entry had been cleared by the first copy.
+    let duplicates = find_duplicates(&csv_records);
+    if !duplicates.is_empty() {
+        warn!(
+            "{} CSV record(s) repeat an earlier record's date, description and amount.",
+            duplicates.len()
+        );
+        let drop = options.dedupe
+            || (!options.yes
+                && !options.count
+                && options.explain.is_none()
+                && prompter.select(
+                    "Drop the duplicate records?",
+                    vec![DROP_DUPLICATES.to_string(), KEEP_DUPLICATES.to_string()],
+                ) == Some(0));
+
+        if drop {
+            for &index in duplicates.iter().rev() {
+                csv_records.remove(index);
+                record_files.remove(index);
+                record_rows.remove(index);
+            }
+            summary.duplicates_removed = duplicates.len();
+            info!("Dropped {} duplicate record(s).", duplicates.len());
+        }
+    }
+
+    if options.sort_csv == Some(CsvSort::Date) {
+        let undated = csv_records
+            .iter()
+            .filter(|record| {
+                date::parse_statement_date(&record[0], options.csv_date_format.as_deref()).is_none()
+            })
+            .count();
+        if undated > 0 {
+            warn!(
+                "{} CSV record(s) have no readable date and were sorted to the end.",
+                undated
+            );
+        }
+        let mut sorted: Vec<_> = csv_records
+            .into_iter()
+            .zip(record_files)
+            .zip(record_rows)
+            .collect();
+        sorted.sort_by(|((a, _), _), ((b, _), _)| by_date(a, b, options));
+        ((csv_records, record_files), record_rows) = sorted.into_iter().unzip();
+    }
+
+    if let Some(limit) = options.limit.filter(|&limit| limit < csv_records.len()) {
+        summary.beyond_limit = csv_records.len() - limit;
+        info!(
+            "--limit: processing the first {} of {} CSV record(s).",
+            limit,
+            csv_records.len()
+        );
+        csv_records.truncate(limit);
+        record_files.truncate(limit);
+        record_rows.truncate(limit);
+    }
+
+    // --explain looks into a single record and writes nothing.
+    let explained = options
+        .explain
+        .as_deref()
+        .map(|wanted| explained_record(&csv_records, wanted))
+        .transpose()?;
+
+    let rules = match &options.rules {
+        Some(path) => Rules::load(path)?,
+        None => Rules::default(),
+    };
+    let drafter = Drafter::load(
+        options,
+        &rules,
+        PostingStyle::detect(&ledger_lines, options.syntax),
+    )?;
+    let misc_account = options.misc_account.as_deref().unwrap_or(MISC_ACCOUNT);
+    let mut known_accounts = known_accounts(&ledger_lines, &drafter, options);
+
+    // The checkpoint is kept next to the output; progress is saved to it every
+    // few decisions and it is deleted once the output has been written.
+    let checkpoint_path =
+        (output_path != "-" && explained.is_none()).then(|| checkpoint::path_for(output_path));
+    let saving = options.checkpoint_every > 0
+        && !options.dry_run
+        && !options.count
+        && !options.reverse_match;
+    let mut next_record = 0;
+    if let Some(path) = &checkpoint_path {
+        match Checkpoint::load(path).map_err(ReconcileError::io(path))? {
+            Some(saved) if options.resume => {
+                if saved.ledger != original_lines || saved.records != csv_records.len() {
+                    return Err(ReconcileError::InvalidOptions(format!(
+                        "{} was saved for a different ledger or statement; delete it or run without --resume",
+                        path
+                    )));
+                }
+                ledger_lines = saved.lines;
+                next_record = saved.next_record;
+                for decision in saved.decisions {
+                    summary.record(decision);
+                }
+                info!(
+                    "Resuming from {} at CSV record {} of {}.",
+                    path,
+                    next_record + 1,
+                    csv_records.len()
+                );
+            }
+            Some(_) => warn!(
+                "{} is left from an interrupted run and will be replaced; pass --resume to continue it instead.",
+                path
+            ),
+            None if options.resume => warn!(
+                "--resume: no checkpoint at {}; starting from the beginning.",
+                path
+            ),
+            None => {}
+        }
+    }
+    let mut saved_decisions = summary.decisions.len();
+
+    // Under --auto, a first pass gives every record with exactly one exact
+    // match a claim on it. The record clears it when its turn comes, and until
+    // then no other record is offered it, so a looser match found earlier
+    // can't take it. The claims are made against the ledger as read, so a
+    // resumed run makes the same ones.
+    let mut claims = BTreeMap::new();
+    if options.auto && !options.count && explained.is_none() {
+        let found = search_all(
+            &csv_records,
+            &uncleared_transactions,
+            &candidate_index,
+            &exact(options),
+            options.threads,
+        )?;
+        for (record_index, found) in found.iter().enumerate() {
+            if let [only] = found.as_slice() {
+                claims.entry(only.header_line).or_insert(record_index);
+            }
+        }
+        debug!(
+            "{} record(s) have an exact match of their own",
+            claims.len()
+        );
+    }
+    let claimed: BTreeMap<usize, usize> = claims
+        .iter()
+        .map(|(&header_line, &record_index)| (record_index, header_line))
+        .collect();
+
+    // Under --threads, every record's matches are searched for up front. Which
+    // of them are still open is checked as usual when the record's turn comes,
+    // and a record that is asked again, or retried with relaxed criteria, is
+    // searched for afresh.
+    let mut searched: Vec<Option<Vec<Match>>> = match options.threads {
+        Some(_)
+            if options.auto && !options.count && !options.group_by_day && explained.is_none() =>
+        {
+            search_all(
+                &csv_records,
+                &uncleared_transactions,
+                &candidate_index,
+                options,
+                options.threads,
+            )?
+            .into_iter()
+            .map(Some)
+            .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    // Under --quiet-matches, a record whose only match is also an exact one
+    // is cleared without a question.
+    let quiet = options.quiet_matches.then(|| exact(options));
+
+    let mut history = History::default();
+    // --count only tallies, the same way records are after "Skip all remaining".
+    let mut skip_remaining = options.count;
+    let mut exited = false;
+    let mut relaxing = None;
+    let mut current_file = None;
+
+    let prompter = &mut progress::Suspending(prompter);
+    let progress = progress::Progress::start(
+        csv_records.len(),
+        options.auto && log::max_level() >= LevelFilter::Info,
+    );
+
+    if options.group_by_day {
+        exited = review_by_day(
+            &csv_records,
+            &uncleared_transactions,
+            &mut ledger_lines,
+            &original_lines,
+            &mut summary,
+            options,
+            prompter,
+        );
+        next_record = csv_records.len();
+    }
+
+    // Under --interactive-edit-unmatched, records that match nothing are
+    // passed over, then gone through once the last record is done. A resumed
+    // run starts at the record after the last saved decision, so progress
+    // stops being saved once a record has been passed over.
+    let mut deferred = 0;
+    let mut reviewing = false;
+    let mut ignoring = BTreeSet::new();
+    loop {
+        if next_record >= csv_records.len() {
+            if reviewing || deferred == 0 {
+                break;
+            }
+            reviewing = true;
+            let pending = undecided(&summary, csv_records.len());
+            if !skip_remaining {
+                ignoring = review_unmatched(&csv_records, &pending, prompter);
+            }
+            next_record = pending.first().copied().unwrap_or(csv_records.len());
+            continue;
+        }
+        // Undoing counts too, since the checkpoint would otherwise keep the
+        // change that was undone.
+        if let Some(path) = checkpoint_path
+            .as_deref()
+            .filter(|_| saving && deferred == 0)
+        {
+            if summary.decisions.len().abs_diff(saved_decisions) >= options.checkpoint_every {
+                let checkpoint = Checkpoint {
+                    ledger: original_lines.clone(),
+                    records: csv_records.len(),
+                    next_record,
+                    lines: ledger_lines.clone(),
+                    decisions: summary.decisions.clone(),
+                };
+                match checkpoint.save(path) {
+                    Ok(()) => debug!("Saved progress to {}", path),
+                    Err(err) => warn!("could not save progress to {}: {}", path, err),
+                }
+                saved_decisions = summary.decisions.len();
+            }
+        }
+
+        let record_index = next_record;
+        let record = &csv_records[record_index];
+        next_record += 1;
+        if explained.is_some_and(|wanted| wanted != record_index) {
+            continue;
+        }
+        // Going back over the records, those with a decision are passed by; an
+        // undo takes decisions back, and their records are asked again.
+        if reviewing
+            && summary
+                .decisions
+                .iter()
+                .any(|decision| decision.record_index == record_index)
+        {
+            continue;
+        }
+        progress.set_position(record_index);
+
+        let file_index = record_files[record_index];
+        if current_file != Some(file_index) && !skip_remaining {
+            info!(
+                "\n=== Processing {} ({} of {}) ===",
+                csv_paths[file_index].as_ref(),
+                file_index + 1,
+                csv_paths.len()
+            );
+        }
+        current_file = Some(file_index);
+
+        let fields = RecordFields::new(record, options);
+        let line = fields.line(record, options);
+        let StatementLine {
+            date,
+            parsed_amount,
+            commodity,
+            ..
+        } = line;
+        let description = record[1].trim().to_lowercase();
+        let amount = &fields.amount;
+        let statement = StatementRecord {
+            date: date.to_string(),
+            description: record[1].trim().to_string(),
+            amount: amount.clone(),
+        };
+
+        let show_record = || {
+            info!(
+                "\n--- CSV Transaction [{}/{}] ---",
+                record_index + 1,
+                csv_records.len()
+            );
+            info!(
+                "Date: {}, Description: {}, Amount: {}",
+                date, description, amount
+            );
+            info!("-----------------------");
+        };
+        // Under --quiet-matches the record is only shown once it is known to
+        // need a question.
+        if !skip_remaining && (quiet.is_none() || explained.is_some()) {
+            show_record();
+        }
+
+        if explained.is_some() {
+            say!(
+                "\n{}",
+                explain(&uncleared_transactions, &line, options).trim_end()
+            );
+            return Ok(ReconcileReport {
+                cleared: 0,
+                ignored: 0,
+                added: 0,
+                unmatched: 0,
+                aborted: false,
+                ledger: layout.render(&original_lines[..own_lines]),
+            });
+        }
+        let relaxed_options =
+            (relaxing.take() == Some(record_index)).then(|| relaxed(options, parsed_amount));
+        let search = relaxed_options.as_ref().unwrap_or(options);
+        let mut matches = match searched.get_mut(record_index).and_then(Option::take) {
+            Some(found) if relaxed_options.is_none() => found,
+            _ => find_matches(&uncleared_transactions, &candidate_index, &line, search),
+        };
+        matches.retain_mut(|matched| still_open(matched, &ledger_lines, &original_lines, options));
+        match claimed.get(&record_index) {
+            Some(&header_line) => matches.retain(|matched| matched.header_line == header_line),
+            None => matches.retain(|matched| {
+                claims
+                    .get(&matched.header_line)
+                    .is_none_or(|&owner| owner < record_index)
+            }),
+        }
+
+        let candidates: Vec<CandidateRef> = matches
+            .iter()
+            .map(|candidate| CandidateRef {
+                line: candidate.header_line + 1,
+                header: ledger_lines[candidate.header_line].clone(),
+            })
+            .collect();
+        let decision = |action: Action, line: Option<usize>| Decision {
+            record_index,
+            record: statement.clone(),
+            candidates: candidates.clone(),
+            action,
+            line,
+        };
+
+        // After "Skip all remaining", the rest of the records are only tallied.
+        if skip_remaining {
+            let action = if matches.is_empty() {
+                Action::Unmatched
+            } else {
+                Action::Ignored
+            };
+            summary.record(decision(action, None));
+            continue;
+        }
+
+        if let Some(exact) = &quiet {
+            let only_exact = matches.len() == 1
+                && relaxed_options.is_none()
+                && find_matches(&uncleared_transactions, &candidate_index, &line, exact)
+                    .iter()
+                    .any(|found| found.header_line == matches[0].header_line);
+            if only_exact {
+                let header_line = matches[0].header_line;
+                let payee = options
+                    .syntax
+                    .dialect()
+                    .description(ledger_lines[header_line].trim_start())
+                    .into_owned();
+                match mark_match(&mut ledger_lines, &matches[0], options, prompter) {
+                    Some(change) => {
+                        info!("{}", color::cleared(format_args!("✓ cleared: {}", payee)));
+                        history.push(record_index, change, &summary);
+                        summary.record(decision(Action::Cleared, Some(header_line + 1)));
+                    }
+                    None => {
+                        info!(
+                            "{}",
+                            color::skipped("No posting chosen; skipped transaction.")
+                        );
+                        summary.record(decision(Action::Ignored, None));
+                    }
+                }
+                continue;
+            }
+            show_record();
+        }
+
+        if matches.is_empty() {
+            if relaxed_options.is_some() {
+                info!(
+                    "{}",
+                    color::skipped("No matching transaction found, even with relaxed criteria.")
+                );
+            } else {
+                info!(
+                    "{}",
+                    color::skipped("No matching transaction found in ledger.")
+                );
+            }
+
+            if let Some(rule) = rules.rule_for(&statement.description) {
+                let entry = drafter.draft(
+                    rule.template.as_deref(),
+                    date,
+                    &description,
+                    amount,
+                    commodity.unwrap_or("$"),
+                    &rule.account,
+                );
+                history.push(record_index, Change::Appended, &summary);
+                summary.record(decision(
+                    Action::Added,
+                    Some(next_line_number(&ledger_lines)),
+                ));
+                ledger_lines.push(entry.clone());
+                info!(
+                    "{}",
+                    color::added("Added new transaction to ledger from rules:")
+                );
+                info!("{}", color::added(&entry));
+                continue;
+            }
+
+            if options.yes || ignoring.remove(&record_index) {
+                info!("{}", color::skipped("Ignored this transaction."));
+                summary.record(decision(Action::Unmatched, None));
+                continue;
+            }
+            if options.interactive_edit_unmatched && !reviewing {
+                info!("Left for the review at the end.");
+                deferred += 1;
+                continue;
+            }
+
+            let mut actions = vec![IGNORE, ADD_NEW, CREATE_AND_CLEAR];
+            if relaxed_options.is_none() {
+                actions.push(RELAX_AND_RETRY);
+            }
+            if parsed_amount.is_some() {
+                actions.push(SPLIT);
+            }
+            actions.extend(options.actions.iter().map(|custom| custom.name.as_str()));
+            actions.extend([SKIP_ALL_REMAINING, EXIT]);
+            if !history.is_empty() {
+                actions.push(UNDO);
+            }
+
+            let choices = actions.iter().map(ToString::to_string).collect();
+            let action = match prompter.select("What would you like to do?", choices) {
+                Some(index) => actions[index],
+                None => match confirm_cancel(prompter) {
+                    Cancelled::AskAgain => {
+                        relaxing = relaxed_options.is_some().then_some(record_index);
+                        next_record = record_index;
+                        continue;
+                    }
+                    Cancelled::Skip => IGNORE,
+                    Cancelled::Exit => EXIT,
+                },
+            };
+
+            let drafted = match action {
+                ADD_NEW => Some((misc_account, false, None)),
+                CREATE_AND_CLEAR => Some((misc_account, true, None)),
+                _ => options
+                    .actions
+                    .iter()
+                    .find(|custom| custom.name == action)
+                    .map(|custom| {
+                        (
+                            custom.account.as_str(),
+                            custom.clear,
+                            custom.template.as_deref(),
+                        )
+                    }),
+            };
+
+            if let Some((account, clear, template)) = drafted {
+                let template = drafter.draft(
+                    template,
+                    date,
+                    &description,
+                    amount,
+                    commodity.unwrap_or("$"),
+                    account,
+                );
+                let (separators, syntax) = (options.separators, options.syntax);
+                let validate =
+                    Rc::new(move |entry: &str| ledger::check_entry(entry, separators, syntax));
+                let Some(entry) = prompter
+                    .edit("Edit the new transaction:", &template, validate)
+                    .and_then(|entry| {
+                        confirm_accounts(prompter, &entry, &mut known_accounts, options.separators)
+                    })
+                else {
+                    info!(
+                        "{}",
+                        color::skipped("Edit cancelled; ignored this transaction.")
+                    );
+                    summary.record(decision(Action::Unmatched, None));
+                    continue;
+                };
+                let mut lines: Vec<String> = entry.trim_end().lines().map(String::from).collect();
+                if clear {
+                    lines[0] = options
+                        .syntax
+                        .dialect()
+                        .mark_cleared(&lines[0], options.mark);
+                }
+                let entry = lines.join("\n");
+                history.push(record_index, Change::Appended, &summary);
+                summary.record(decision(
+                    Action::Added,
+                    Some(next_line_number(&ledger_lines)),
+                ));
+                ledger_lines.push(entry.clone());
+                if clear {
+                    info!(
+                        "{}",
+                        color::added(format_args!(
+                            "Added new transaction to ledger, marked as {}:",
+                            options.mark.label()
+                        ))
+                    );
+                } else {
+                    info!("{}", color::added("Added new transaction to ledger:"));
+                }
+                info!("{}", color::added(&entry));
+            } else if action == SKIP_ALL_REMAINING {
+                info!("Skipping all remaining transactions.");
+                summary.record(decision(Action::Unmatched, None));
+                skip_remaining = true;
+            } else if action == EXIT {
+                info!("Exiting program.");
+                summary.record(decision(Action::Unmatched, None));
+                exited = true;
+                break;
+            } else if action == UNDO {
+                next_record = undo_last(&mut history, &mut ledger_lines, &mut summary)
+                    .unwrap_or(record_index);
+            } else if action == RELAX_AND_RETRY {
+                relaxing = Some(record_index);
+                next_record = record_index;
+            } else if action == SPLIT {
+                // Asked again after a cancel or a wrong total, as after a
+                // cancelled menu.
+                relaxing = relaxed_options.is_some().then_some(record_index);
+                next_record = record_index;
+
+                let parts = split_parts(
+                    &uncleared_transactions,
+                    &line,
+                    search,
+                    &ledger_lines,
+                    &original_lines,
+                );
+                if parts.is_empty() {
+                    info!("No open transactions near that date.");
+                    continue;
+                }
+                let expected = parsed_amount.unwrap_or_default().abs();
+                let choices = parts.iter().map(|(part, _)| part.label()).collect();
+                let Some(chosen) = prompter.multi_select(
+                    &format!("Clear which transactions? They must add up to {}", expected),
+                    choices,
+                    &[],
+                ) else {
+                    continue;
+                };
+                let total: Decimal = chosen.iter().map(|&index| parts[index].1).sum();
+                if chosen.is_empty()
+                    || !amount::amounts_match(expected, total, search.amount_tolerance)
+                {
+                    info!(
+                        "{}",
+                        color::skipped(format_args!(
+                            "The chosen transactions add up to {}, not {}.",
+                            total, expected
+                        ))
+                    );
+                    continue;
+                }
+
+                let mut previous = Vec::new();
+                let mut cleared = Vec::new();
+                for &index in &chosen {
+                    let part = &parts[index].0;
+                    let header = ledger_lines[part.header_line].clone();
+                    if let Some(Change::Marked { previous: lines }) =
+                        mark_match(&mut ledger_lines, part, options, prompter)
+                    {
+                        previous.extend(lines);
+                        cleared.push(CandidateRef {
+                            line: part.header_line + 1,
+                            header,
+                        });
+                    }
+                }
+                next_record = record_index + 1;
+                relaxing = None;
+                if previous.is_empty() {
+                    info!(
+                        "{}",
+                        color::skipped("No posting chosen; skipped transaction.")
+                    );
+                    summary.record(decision(Action::Unmatched, None));
+                    continue;
+                }
+                for (line, _) in &previous {
+                    info!(
+                        "{}",
+                        color::cleared(format_args!(
+                            "Marked as {}: {}",
+                            options.mark.label(),
+                            ledger_lines[*line].trim()
+                        ))
+                    );
+                }
+                // Each part gets a decision of its own, all naming the parts
+                // as the record's candidates, so every one is counted and
+                // exported.
+                history.push(record_index, Change::Marked { previous }, &summary);
+                for part in &cleared {
+                    summary.record(Decision {
+                        candidates: cleared.clone(),
+                        ..decision(Action::Cleared, Some(part.line))
+                    });
+                }
+            } else {
+                info!("{}", color::skipped("Ignored this transaction."));
+                summary.record(decision(Action::Unmatched, None));
+            }
+
+            continue;
+        }
+
+        if search.auto && (matches.len() == 1 || options.yes) {
+            let header_line = matches[0].header_line;
+            match mark_match(&mut ledger_lines, &matches[0], options, prompter) {
+                Some(change) => {
+                    for line in change.lines() {
+                        info!(
+                            "{}",
+                            color::cleared(format_args!(
+                                "Auto-marked as {}: {}",
+                                options.mark.label(),
+                                ledger_lines[line].trim()
+                            ))
+                        );
+                    }
+                    history.push(record_index, change, &summary);
+                    summary.record(decision(Action::Cleared, Some(header_line + 1)));
+                }
+                None => {
+                    info!(
+                        "{}",
+                        color::skipped("No posting chosen; skipped transaction.")
+                    );
+                    summary.record(decision(Action::Ignored, None));
+                }
+            }
+            continue;
+        }
+
+        info!("\n--- Matching Ledger Entries ---");
+        for (i, candidate) in matches.iter().enumerate() {
+            info!("{}. {}", i + 1, candidate.label());
+        }
+        info!("-------------------------------");
+
+        let ignore_choice = matches.len();
+        let skip_all_choice = ignore_choice + 1;
+        let mut choices: Vec<String> = matches.iter().map(Match::label).collect();
+        choices.push(IGNORE_THIS_LINE.to_string());
+        choices.push(SKIP_ALL_REMAINING.to_string());
+        if !history.is_empty() {
+            choices.push(UNDO.to_string());
+        }
+
+        // A confident best match takes one Enter; anything else, including
+        // cancelling, falls through to the full list.
+        let confident = matches[0]
+            .score
+            .zip(options.auto_accept_score)
+            .is_some_and(|(score, threshold)| score >= threshold);
+        let accepted = confident
+            && prompter.select(
+                &format!("Best match: {}", matches[0].label()),
+                vec![ACCEPT_BEST.to_string(), SHOW_ALL_MATCHES.to_string()],
+            ) == Some(0);
+
+        let selected = if accepted {
+            0
+        } else {
+            match prompter.select("Match a transaction:", choices) {
+                Some(selected) => selected,
+                None => match confirm_cancel(prompter) {
+                    Cancelled::AskAgain => {
+                        relaxing = relaxed_options.is_some().then_some(record_index);
+                        next_record = record_index;
+                        continue;
+                    }
+                    Cancelled::Skip => ignore_choice,
+                    Cancelled::Exit => {
+                        info!("Exiting program.");
+                        summary.record(decision(Action::Ignored, None));
+                        exited = true;
+                        break;
+                    }
+                },
+            }
+        };
+
+        if selected == ignore_choice {
+            info!("{}", color::skipped("Skipped transaction."));
+            summary.record(decision(Action::Ignored, None));
+            continue;
+        }
+
+        if selected == skip_all_choice {
+            info!("Skipping all remaining transactions.");
+            summary.record(decision(Action::Ignored, None));
+            skip_remaining = true;
+            continue;
+        }
+
+        if selected > skip_all_choice {
+            next_record =
+                undo_last(&mut history, &mut ledger_lines, &mut summary).unwrap_or(record_index);
+            continue;
+        }
+
+        let header_line = matches[selected].header_line;
+        let Some(change) = mark_match(&mut ledger_lines, &matches[selected], options, prompter)
+        else {
+            info!(
+                "{}",
+                color::skipped("No posting chosen; skipped transaction.")
+            );
+            summary.record(decision(Action::Ignored, None));
+            continue;
+        };
+        for line in change.lines() {
+            info!(
+                "{}",
+                color::cleared(format_args!(
+                    "Marked {} as {}: {}",
+                    if options.posting_level {
+                        "posting"
+                    } else {
+                        "transaction"
+                    },
+                    options.mark.label(),
+                    ledger_lines[line].trim()
+                ))
+            );
+        }
+        history.push(record_index, change, &summary);
+        summary.record(decision(Action::Cleared, Some(header_line + 1)));
+    }
+
+    drop(progress);
+    summary.exact_pass = summary
+        .decisions
+        .iter()
+        .filter(|decision| {
+            decision.action == Action::Cleared
+                && claimed
+                    .get(&decision.record_index)
+                    .is_some_and(|&header_line| decision.line == Some(header_line + 1))
+        })
+        .count();
+
+    if options.count {
+        let preflight = Preflight::from_decisions(&summary.decisions);
+        say!("\n{}", preflight);
+        return Ok(ReconcileReport {
+            cleared: 0,
+            ignored: 0,
+            added: 0,
+            unmatched: preflight.unmatched.len(),
+            aborted: false,
+            ledger: layout.render(&original_lines[..own_lines]),
+        });
+    }
+
+    if options.trim_trailing {
+        let trimmed = trim_touched(&mut ledger_lines, &original_lines);
+        debug!("Trimmed trailing whitespace from {} line(s)", trimmed);
+    }
+
+    // New entries go to the ledger itself, after its own lines; included
+    // files get back just the lines read from them.
+    let included_lines = original_lines.len() - own_lines;
+    let mut own: Vec<String> = ledger_lines[..own_lines].to_vec();
+    own.extend_from_slice(&ledger_lines[original_lines.len()..]);
+    if included_lines > 0 {
+        for decision in &mut summary.decisions {
+            if decision.action == Action::Added {
+                decision.line = decision.line.map(|line| line - included_lines);
+            }
+        }
+    }
+
+    // Entries are appended while the run goes on, so that line indices stay
+    // put, and only moved into place once it is over.
+    let output_lines = if options.sorted_insert {
+        let (merged, moved) = ledger::merge_by_date(&own, own_lines, options.syntax);
+        for decision in &mut summary.decisions {
+            if decision.action == Action::Added {
+                decision.line = decision.line.and_then(|line| {
+                    moved
+                        .iter()
+                        .find(|(old, _)| *old == line)
+                        .map(|(_, new)| *new)
+                });
+            }
+        }
+        merged
+    } else {
+        own
+    };
+
+    say!("\n{}", summary);
+    if let Some(report_path) = &options.report {
+        fs::write(report_path, format!("{}\n", summary))
+            .map_err(ReconcileError::io(report_path))?;
+        info!("Summary written to {}", report_path);
+    }
+    if let Some(json_log_path) = &options.json_log {
+        summary
+            .to_json_log()
+            .map_err(io::Error::from)
+            .and_then(|log| fs::write(json_log_path, log))
+            .map_err(ReconcileError::io(json_log_path))?;
+        info!("Decision log written to {}", json_log_path);
+    }
+    if let Some(rejects_path) = &options.rejects {
+        let mut rejected: Vec<usize> = summary
+            .decisions
+            .iter()
+            .filter(|decision| decision.action == Action::Unmatched)
+            .map(|decision| decision.record_index)
+            .collect();
+        rejected.sort_unstable();
+        rejected.dedup();
+
+        let rows: Vec<(usize, &[String])> = rejected
+            .iter()
+            .map(|&index| (record_files[index], record_rows[index].as_slice()))
+            .collect();
+        let content = statement_csv(&file_headers, csv_paths, &[], &rows)
+            .map_err(ReconcileError::io(rejects_path))?;
+        fs::write(rejects_path, content).map_err(ReconcileError::io(rejects_path))?;
+        info!(
+            "{} rejected record(s) written to {}",
+            rejected.len(),
+            rejects_path
+        );
+    }
+
+    if let Some(print_path) = &options.print_cleared {
+        let content = cleared_entries(
+            &ledger_lines,
+            original_lines.len(),
+            &summary.decisions,
+            options.syntax,
+        );
+        fs::write(print_path, content).map_err(ReconcileError::io(print_path))?;
+        info!(
+            "{} cleared transaction(s) written to {}",
+            summary.cleared, print_path
+        );
+    }
+
+    if let Some(csv_out_path) = &options.csv_out {
+        // A record decided more than once, as after an undo, keeps its last
+        // decision.
+        let decided: BTreeMap<usize, &Decision> = summary
+            .decisions
+            .iter()
+            .map(|decision| (decision.record_index, decision))
+            .collect();
+        let rows: Vec<(usize, Vec<String>)> = (0..csv_records.len())
+            .map(|index| {
+                let mut row = record_rows[index].clone();
+                row.extend(record_status(decided.get(&index).copied(), &output_lines));
+                (record_files[index], row)
+            })
+            .collect();
+        let content = statement_csv(&file_headers, csv_paths, &["Status", "Ledger"], &rows)
+            .map_err(ReconcileError::io(csv_out_path))?;
+        fs::write(csv_out_path, content).map_err(ReconcileError::io(csv_out_path))?;
+        info!(
+            "{} record(s) written to {} with their status",
+            rows.len(),
+            csv_out_path
+        );
+    }
+
+    let mut report = ReconcileReport {
+        cleared: summary.cleared,
+        ignored: summary.ignored,
+        added: summary.added,
+        unmatched: summary.unmatched.len(),
+        aborted: skip_remaining || exited,
+        ledger: layout.render(&output_lines),
+    };
+    if let Some(date) = options.annotate_header {
+        let sources: Vec<&str> = csv_paths.iter().map(AsRef::as_ref).collect();
+        report.ledger = format!(
+            "; reconciled {} from {}\n{}",
+            date,
+            sources.join(", "),
+            report.ledger
+        );
+    }
+
+    // Balances count the cleared postings of included files as well.
+    let opening = original_lines.join("\n");
+    let mut balanced = report.ledger.clone();
+    for file in &included {
+        balanced.push('\n');
+        balanced.push_str(&ledger_lines[file.lines.clone()].join("\n"));
+    }
+
+    if options.dry_run {
+        say!("\n--- Dry Run: Pending Changes ---");
+        let preview = report::preview_changes(&original_lines, &ledger_lines);
+        if !preview.is_empty() {
+            say!("{}", preview.trim_end());
+        }
+        say!("--------------------------------");
+        say!("Dry run: no changes were written to {}", output_path);
+        check_balances(&balanced, &assertions, options)?;
+        check_period(&opening, &balanced, period.as_ref(), options)?;
+        return Ok(report);
+    }
+    if options.reverse_match {
+        info!("--reverse-match: {} was left unchanged.", ledger_path);
+        check_balances(&balanced, &assertions, options)?;
+        check_period(&opening, &balanced, period.as_ref(), options)?;
+        return Ok(report);
+    }
+
+    if options.backup {
+        let backup_path = backup_ledger(ledger_path).map_err(|err| ReconcileError::Io {
+            path: ledger_path.to_string(),
+            source: io::Error::new(
+                err.kind(),
+                format!("could not back up, nothing was written: {}", err),
+            ),
+        })?;
+        info!("Backed up original ledger to {}", backup_path);
+    }
+
+    write_output(output_path, &report.ledger).map_err(ReconcileError::io(output_path))?;
+    if output_path != "-" {
+        info!("Updated ledger written to {}", output_path);
+    }
+    for file in &included {
+        let lines = &ledger_lines[file.lines.clone()];
+        if lines == &original_lines[file.lines.clone()] {
+            continue;
+        }
+        if options.backup {
+            let backup_path = backup_ledger(&file.path).map_err(ReconcileError::io(&file.path))?;
+            info!("Backed up {} to {}", file.path, backup_path);
+        }
+        write_output(&file.path, &file.layout.render(lines))
+            .map_err(ReconcileError::io(&file.path))?;
+        info!("Updated included file {}", file.path);
+    }
+    if options.verify_with_hledger {
+        if output_path == "-" || options.syntax == Syntax::Beancount {
+            info!("--verify-with-hledger needs a ledger file hledger can read; skipping it.");
+        } else if let Some(verified) = verify::verify_with_hledger(
+            "hledger",
+            output_path,
+            options.account.as_deref(),
+            period.as_ref().map(|period| &period.closing),
+            options.amount_tolerance,
+            options.separators,
+        ) {
+            say!("{}", verified);
+        }
+    }
+    if let Some(path) = &checkpoint_path {
+        checkpoint::remove(path).map_err(ReconcileError::io(path))?;
+    }
+
+    // Checked only once the output is safely written, so a mismatch doesn't
+    // throw the session's work away.
+    check_balances(&balanced, &assertions, options)?;
+    check_period(&opening, &balanced, period.as_ref(), options)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+
+    /// Writes `content` to a uniquely named file in the temp directory.
+    fn fixture(name: &str, content: &str) -> String {
+        let path =
+            env::temp_dir().join(format!("ledger-reconcile-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_process_files() {
+        let ledger_path = "test_ledger.ledger";
+        let csv_path = "test_transactions.csv";
+        let output_path = "test_updated.ledger";
+
+        let mut ledger_file = File::create(ledger_path).unwrap();
+        writeln!(
+            ledger_file,
+            r#"
+2025-01-01 Groceries
+    Expenses:Food          $50.00
+    Assets:Bank           -$50.00
+
+2025-01-02 Rent
+    Expenses:Rent         $1000.00
+    Assets:Bank          -$1000.00
+"#
+        )
+        .unwrap();
+
+        let mut csv_file = File::create(csv_path).unwrap();
+        writeln!(
+            csv_file,
+            r#"
+Date,Description,Amount
+2025-01-01,Groceries,$50.00
+2025-01-02,Rent,$1000.00
+"#
+        )
+        .unwrap();
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(ledger_path, &[csv_path], output_path, &options).unwrap();
+
+        let updated_ledger = fs::read_to_string(output_path).unwrap();
+        assert!(updated_ledger.contains("* 2025-01-01 Groceries"));
+        assert!(updated_ledger.contains("* 2025-01-02 Rent"));
+
+        fs::remove_file(ledger_path).unwrap();
+        fs::remove_file(csv_path).unwrap();
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_yes_clears_first_of_several_matches() {
+        let ledger_path = fixture(
+            "yes.ledger",
+            "2025-01-01 Coffee
+    Expenses:Food          $4.00
+    Assets:Bank
+
+2025-01-01 Coffee
+    Expenses:Food          $4.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "yes.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n2025-01-05,Unknown,9.99\n",
+        );
+        let output_path = fixture("yes-out.ledger", "");
+        let report_path = fixture("yes-report.txt", "");
+        let json_log_path = fixture("yes-log.json", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            report: Some(report_path.clone()),
+            json_log: Some(json_log_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert_eq!(updated.matches("* 2025-01-01 Coffee").count(), 1);
+        assert!(!updated.contains("Unknown"));
+
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Cleared:   1"));
+        assert!(report.contains("  2025-01-05  Unknown  9.99"));
+
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_log_path).unwrap()).unwrap();
+        let decisions = log["decisions"].as_array().unwrap();
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0]["action"], "cleared");
+        assert_eq!(decisions[0]["candidates"].as_array().unwrap().len(), 2);
+        assert_eq!(decisions[0]["line"], 1);
+        assert_eq!(decisions[1]["action"], "unmatched");
+
+        for path in [
+            ledger_path,
+            csv_path,
+            output_path,
+            report_path,
+            json_log_path,
+        ] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pending_transactions_are_not_offered_again() {
+        let ledger_path = fixture(
+            "pending.ledger",
+            "! 2025-01-01 Groceries
+    Expenses:Food          $50.00
+    Assets:Bank
+
+2025-01-02 Rent
+    Expenses:Rent         $1000.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "pending.csv",
+            "Date,Description,Amount\n2025-01-01,Groceries,50.00\n2025-01-02,Rent,1000.00\n",
+        );
+        let output_path = fixture("pending-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            mark: Status::Pending,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.contains("\n! 2025-01-02 Rent"));
+        assert!(updated.starts_with("! 2025-01-01 Groceries"));
+        assert!(!updated.contains("! ! "));
+
+        let options = Options {
+            mark: Status::Cleared,
+            include_pending: true,
+            ..options
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("* 2025-01-01 Groceries"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dry_run_does_not_write_output() {
+        let ledger_path = fixture(
+            "dry-run.ledger",
+            "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "dry-run.csv",
+            "Date,Description,Amount\n2025-01-02,Rent,1000.00\n",
+        );
+        let output_path = env::temp_dir()
+            .join(format!(
+                "ledger-reconcile-{}-dry-run-out",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+
+        let options = Options {
+            auto: true,
+            dry_run: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert!(!Path::new(&output_path).exists());
+
+        for path in [ledger_path, csv_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_matches_padded_bank_description() {
+        let ledger_path = fixture(
+            "fuzzy.ledger",
+            "2025-01-03 Coffee Shop\n    Expenses:Food  $4.50\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "fuzzy.csv",
+            "Date,Description,Amount\n2025-01-03,SQ *COFFEE SHOP 123,4.50\n",
+        );
+        let output_path = fixture("fuzzy-out.ledger", "");
+
+        let exact = Options {
+            auto: true,
+            yes: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &exact).unwrap();
+        assert!(!fs::read_to_string(&output_path).unwrap().contains('*'));
+
+        let fuzzy = Options {
+            fuzzy: true,
+            similarity: 80,
+            ..exact
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &fuzzy).unwrap();
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-03 Coffee Shop"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_date_window_accepts_posting_delay() {
+        let ledger = "2025-01-30 Hardware Store\n    Expenses:Home  $25.00\n    Assets:Bank\n";
+        let ledger_path = fixture("window.ledger", ledger);
+        let output_path = fixture("window-out.ledger", "");
+
+        for (posted, window, cleared) in [
+            ("2025-01-31", 0, false),
+            ("2025-01-31", 1, true),
+            ("2025-02-02", 1, false),
+            ("2025-02-02", 3, true),
+        ] {
+            let csv_path = fixture(
+                "window.csv",
+                &format!("Date,Description,Amount\n{},Hardware Store,25.00\n", posted),
+            );
+            let options = Options {
+                auto: true,
+                yes: true,
+                date_window: window,
+                ..Options::default()
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+            let updated = fs::read_to_string(&output_path).unwrap();
+            assert_eq!(
+                updated.starts_with("* "),
+                cleared,
+                "{} at {}",
+                posted,
+                window
+            );
+            fs::remove_file(csv_path).unwrap();
+        }
+
+        for path in [ledger_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_short_csv_rows_are_skipped_or_rejected() {
+        let content = "Date,Description,Amount\n2025-01-01,Groceries,50.00\n2025-01-02,Rent\n";
+
+        let statement =
+            parse_statement("bank.csv", content, InputFormat::Csv, &Options::default()).unwrap();
+        assert_eq!(
+            statement.records,
+            vec![vec!["2025-01-01", "Groceries", "50.00", ""]]
+        );
+        assert_eq!(
+            statement.rows,
+            vec![vec!["2025-01-01", "Groceries", "50.00"]]
+        );
+
+        let strict = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let error = parse_statement("bank.csv", content, InputFormat::Csv, &strict).unwrap_err();
+        assert!(
+            matches!(error, ReconcileError::Csv { line: 3, .. }),
+            "{:?}",
+            error
+        );
+        assert!(error
+            .to_string()
+            .starts_with("bank.csv: CSV row 3 has 2 field(s)"));
+    }
+
+    #[test]
+    fn test_strict_rejects_unreadable_dates_amounts_and_columns() {
+        let strict = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let parse = |content: &str, options: &Options| {
+            parse_statement("bank.csv", content, InputFormat::Csv, options)
+        };
+
+        let dates = "Date,Description,Amount\n2025-01-01,Coffee,4.00\nyesterday,Lunch,12.00\n";
+        assert!(parse(dates, &Options::default()).is_ok());
+        assert!(matches!(
+            parse(dates, &strict),
+            Err(ReconcileError::DateParse { record: 2, .. })
+        ));
+
+        let amounts = "Date,Description,Amount\n2025-01-01,Coffee,four\n";
+        assert!(matches!(
+            parse(amounts, &strict),
+            Err(ReconcileError::AmountParse { record: 1, .. })
+        ));
+
+        let named = Options {
+            columns: ColumnMapping {
+                amount: AmountColumns::Single(ColumnSpec::Name("Value".to_string())),
+                ..ColumnMapping::default()
+            },
+            ..Options::default()
+        };
+        assert!(matches!(
+            parse(amounts, &named),
+            Err(ReconcileError::ColumnNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rules_categorize_unmatched_records() {
+        let ledger_path = fixture(
+            "rules.ledger",
+            "2025-01-01 Opening\n    Assets:Bank  $100\n    Equity\n",
+        );
+        let csv_path = fixture(
+            "rules.csv",
+            "Date,Description,Amount\n2025-01-04,SQ *BLUE BOTTLE,5.25\n2025-01-05,Unknown,9.99\n",
+        );
+        let rules_path = fixture("rules.rules", "/^sq \\*/ => Expenses:Coffee\n");
+        let output_path = fixture("rules-out.ledger", "");
+
+        let options = Options {
+            yes: true,
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.contains("2025-01-04 sq *blue bottle\n    Expenses:Coffee"));
+        assert!(!updated.contains("Unknown"));
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_backup_preserves_original_when_writing_in_place() {
+        let original = "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank";
+        let ledger_path = fixture("backup.ledger", original);
+        let csv_path = fixture(
+            "backup.csv",
+            "Date,Description,Amount\n2025-01-02,Rent,1000.00\n",
+        );
+
+        let options = Options {
+            auto: true,
+            backup: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &ledger_path, &options).unwrap();
+
+        let backup_path = format!("{}.bak", ledger_path);
+        assert_eq!(fs::read_to_string(&backup_path).unwrap(), original);
+        assert!(fs::read_to_string(&ledger_path)
+            .unwrap()
+            .starts_with("* 2025-01-02 Rent"));
+
+        for path in [ledger_path, csv_path, backup_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_account_filter_targets_the_bank_posting() {
+        let ledger_path = fixture(
+            "account.ledger",
+            "2025-01-06 Transfer
+    Assets:Savings        $200.00
+    Assets:Checking      -$200.00
+
+2025-01-06 Transfer
+    Liabilities:Card      $200.00
+    Assets:Savings       -$200.00
+",
+        );
+        let csv_path = fixture(
+            "account.csv",
+            "Date,Description,Amount\n2025-01-06,Transfer,200.00\n",
+        );
+        let output_path = fixture("account-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            account: Some("Liabilities:Card".to_string()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-06 Transfer"));
+        assert!(updated.contains("\n* 2025-01-06 Transfer\n    Liabilities:Card"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_stdio_path_combinations() {
+        let batch = Options {
+            yes: true,
+            ..Options::default()
+        };
+
+        assert!(check_stdio_paths("-", &["-"], "out.ledger", &batch).is_err());
+        assert!(check_stdio_paths("main.ledger", &["-", "-"], "out.ledger", &batch).is_err());
+        assert!(check_stdio_paths("main.ledger", &["-"], "-", &Options::default()).is_err());
+        assert!(check_stdio_paths("main.ledger", &["-"], "-", &batch).is_ok());
+        assert!(check_stdio_paths("-", &["bank.csv"], "out.ledger", &batch).is_ok());
+
+        let backup = Options {
+            backup: true,
+            ..batch
+        };
+        assert!(check_stdio_paths("-", &["bank.csv"], "out.ledger", &backup).is_err());
+    }
+
+    #[test]
+    fn test_us_csv_dates_match_iso_ledger() {
+        let ledger_path = fixture(
+            "dates.ledger",
+            "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "dates.csv",
+            "Date,Description,Amount\n01/02/2025,Rent,1000.00\n",
+        );
+        let output_path = fixture("dates-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-02 Rent"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_skip_all_remaining_leaves_the_rest_untouched() {
+        let ledger_path = fixture(
+            "skip-all.ledger",
+            "2025-01-01 Groceries
+    Expenses:Food  $50.00
+    Assets:Bank
+
+2025-01-02 Rent
+    Expenses:Rent  $1000.00
+    Assets:Bank
+
+2025-01-03 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank",
+        );
+        let csv_path = fixture(
+            "skip-all.csv",
+            "Date,Description,Amount
+2025-01-01,Groceries,50.00
+2025-01-02,Rent,1000.00
+2025-01-03,Coffee,4.00
+",
+        );
+        let output_path = fixture("skip-all-out.ledger", "");
+        let report_path = fixture("skip-all-report.txt", "");
+
+        let options = Options {
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        let mut prompter = prompt::Scripted::new(&["2025-01-01 Groceries", "Skip all remaining"]);
+        let reconciled = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+        assert!(reconciled.aborted);
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.contains("* 2025-01-01 Groceries"));
+        assert!(output.contains("\n2025-01-02 Rent"));
+        assert!(output.contains("\n2025-01-03 Coffee"));
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Cleared:   1"));
+        assert!(report.contains("Ignored:   2"));
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_crlf_ledger_without_matches_is_written_back_unchanged() {
+        let ledger =
+            "; bank\r\n2025-01-01 Rent\r\n    Expenses:Rent  $1000.00\r\n    Assets:Bank\r\n";
+        let ledger_path = fixture("crlf.ledger", ledger);
+        let csv_path = fixture(
+            "crlf.csv",
+            "Date,Description,Amount\n2025-02-01,Coffee,4.00\n",
+        );
+        let output_path = fixture("crlf-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(fs::read(&output_path).unwrap(), ledger.as_bytes());
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_indexed_matches_equal_a_full_scan() {
+        let mut ledger = String::new();
+        for n in 0..3000u32 {
+            let day = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+                + chrono::Days::new(u64::from(n % 365));
+            let marker = if n % 7 == 0 { "* " } else { "" };
+            let date = if n % 97 == 0 {
+                "someday".to_string()
+            } else {
+                day.to_string()
+            };
+            ledger.push_str(&format!(
+                "{}{} Payee {}\n    Expenses:Misc  ${}.{:02}\n    Assets:Bank\n\n",
+                marker,
+                date,
+                n % 40,
+                n % 50,
+                n % 3
+            ));
+        }
+        let ledger_lines: Vec<String> = ledger.lines().map(String::from).collect();
+
+        for options in [
+            Options::default(),
+            Options {
+                date_window: 3,
+                ..Options::default()
+            },
+            Options {
+                fuzzy: true,
+                similarity: 80,
+                ..Options::default()
+            },
+        ] {
+            let candidates = load_candidates(&ledger_lines, None, &Normalizer::default(), &options);
+            let index = index_candidates(&candidates, &options);
+
+            let mut matched = 0;
+            for n in 0..400u32 {
+                // Mirror an uncleared transaction so most records have matches.
+                let m = n * 7 + 3;
+                let parsed_date = NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .map(|day| day + chrono::Days::new(u64::from(m % 365)));
+                let date = parsed_date.unwrap().to_string();
+                let description = format!("payee {}", m % 40);
+                let amount = format!("{}.{:02}", m % 50, m % 3);
+                let line = StatementLine {
+                    date: &date,
+                    parsed_date,
+                    description: &description,
+                    tokens: &fuzzy::Tokens::new(&description),
+                    amount: &amount,
+                    parsed_amount: amount::parse_amount(&amount, Separators::default()),
+                    commodity: None,
+                    id: "",
+                };
+
+                let mut expected: Vec<Match> = candidates
+                    .iter()
+                    .filter_map(|candidate| match_candidate(candidate, &line, &options))
+                    .collect();
+                expected.sort_by_key(|candidate| Reverse(candidate.score));
+                let found = find_matches(&candidates, &index, &line, &options);
+
+                let lines = |matches: &[Match]| -> Vec<usize> {
+                    matches
+                        .iter()
+                        .map(|candidate| candidate.header_line)
+                        .collect()
+                };
+                assert_eq!(lines(&found), lines(&expected), "record {}", n);
+                matched += found.len();
+            }
+            assert!(matched >= 300);
+        }
+    }
+
+    #[test]
+    fn test_multiple_csv_files_share_one_ledger() {
+        let ledger_path = fixture(
+            "multi.ledger",
+            "2025-01-01 Transfer
+    Assets:Savings  $100.00
+    Assets:Checking
+
+2025-01-02 Coffee
+    Expenses:Food  $4.00
+    Liabilities:Card",
+        );
+        let checking_path = fixture(
+            "multi-checking.csv",
+            "Date,Description,Amount\n2025-01-01,Transfer,-100.00\n",
+        );
+        let savings_path = fixture(
+            "multi-savings.csv",
+            "Date,Description,Amount\n2025-01-01,Transfer,100.00\n",
+        );
+        let card_path = fixture(
+            "multi-card.csv",
+            "Date,Description,Amount\n2025-01-02,Coffee,4.00\n",
+        );
+        let output_path = fixture("multi-out.ledger", "");
+        let json_log_path = fixture("multi-log.json", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            json_log: Some(json_log_path.clone()),
+            ..Options::default()
+        };
+        process_files(
+            &ledger_path,
+            &[&checking_path, &savings_path, &card_path],
+            &output_path,
+            &options,
+        )
+        .unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.contains("* 2025-01-01 Transfer"));
+        assert!(output.contains("* 2025-01-02 Coffee"));
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_log_path).unwrap()).unwrap();
+        let actions: Vec<&str> = log["decisions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|decision| decision["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, ["cleared", "unmatched", "cleared"]);
+
+        for path in [
+            ledger_path,
+            checking_path,
+            savings_path,
+            card_path,
+            output_path,
+            json_log_path,
+        ] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_duplicate_csv_records_are_dropped() {
+        let ledger_path = fixture(
+            "dupes.ledger",
+            "2025-01-01 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank",
+        );
+        let csv_path = fixture(
+            "dupes.csv",
+            "Date,Description,Amount
+2025-01-01,Coffee,4.00
+2025-01-01,Coffee ,4.00
+",
+        );
+        let output_path = fixture("dupes-out.ledger", "");
+        let report_path = fixture("dupes-report.txt", "");
+
+        let mut options = Options {
+            auto: true,
+            yes: true,
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Unmatched: 1"));
+
+        options.dedupe = true;
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Unmatched: 0"));
+        assert!(report.contains("Duplicates removed: 1"));
+
+        options.dedupe = false;
+        options.yes = false;
+        let mut prompter = prompt::Scripted::new(&["Drop them"]);
+        process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Duplicates removed: 1"));
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_duplicates_are_keyed_on_date_description_and_amount() {
+        let record = |fields: [&str; 4]| fields.map(String::from).to_vec();
+        let records = [
+            record(["2025-01-01", "Coffee", "4.00", "A1"]),
+            record(["2025-01-01", " Coffee", "4.00", "A2"]),
+            record(["2025-01-02", "Coffee", "4.00", "A3"]),
+            record(["2025-01-02", "Coffee", "4.50", "A3"]),
+        ];
+        assert_eq!(find_duplicates(&records), [1]);
+    }
+
+    #[test]
+    fn test_transaction_code_matches_statement_id() {
+        let ledger_path = fixture(
+            "codes.ledger",
+            "2025-01-01 (TX-1) Amazon
+    Expenses:Shopping  $25.00
+    Assets:Bank
+
+2025-01-01 (TX-2) Amazon
+    Expenses:Shopping  $25.00
+    Assets:Bank",
+        );
+        let csv_path = fixture(
+            "codes.csv",
+            "Date,Description,Amount,Reference
+2025-01-03,AMZN MKTP,25.00,TX-2
+",
+        );
+        let output_path = fixture("codes-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            columns: ColumnMapping {
+                id: Some(ColumnSpec::Name("Reference".to_string())),
+                ..ColumnMapping::default()
+            },
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.contains("\n* 2025-01-01 (TX-2) Amazon"));
+        assert!(output.starts_with("2025-01-01 (TX-1) Amazon"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_one_ledger_entry_clears_only_one_of_two_identical_records() {
+        let ledger_path = fixture(
+            "twice.ledger",
+            "2025-01-01 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank",
+        );
+        let csv_path = fixture(
+            "twice.csv",
+            "Date,Description,Amount
+2025-01-01,Coffee,4.00
+2025-01-01,Coffee,4.00
+",
+        );
+        let output_path = fixture("twice-out.ledger", "");
+        let json_log_path = fixture("twice-log.json", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            json_log: Some(json_log_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.starts_with("* 2025-01-01 Coffee\n"));
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_log_path).unwrap()).unwrap();
+        assert_eq!(log["decisions"][0]["action"], "cleared");
+        assert_eq!(log["decisions"][1]["action"], "unmatched");
+        assert_eq!(log["decisions"][1]["candidates"], serde_json::json!([]));
+
+        for path in [ledger_path, csv_path, output_path, json_log_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_since_and_until_filter_records_across_files() {
+        let ledger_path = fixture(
+            "range.ledger",
+            "2025-01-05 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank
+
+2025-02-05 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank",
+        );
+        let january_path = fixture(
+            "range-jan.csv",
+            "Date,Description,Amount\n2025-01-05,Coffee,4.00\nlater,Coffee,4.00\n",
+        );
+        let february_path = fixture(
+            "range-feb.csv",
+            "Date,Description,Amount\n02/05/2025,Coffee,4.00\n03/01/2025,Tea,3.00\n",
+        );
+        let output_path = fixture("range-out.ledger", "");
+        let report_path = fixture("range-report.txt", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            since: NaiveDate::from_ymd_opt(2025, 2, 1),
+            until: NaiveDate::from_ymd_opt(2025, 2, 28),
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        process_files(
+            &ledger_path,
+            &[&january_path, &february_path],
+            &output_path,
+            &options,
+        )
+        .unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.starts_with("2025-01-05 Coffee"));
+        assert!(output.contains("\n* 2025-02-05 Coffee"));
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Cleared:   1"));
+        assert!(report.contains("Unmatched: 0"));
+        assert!(report.contains("Outside date range: 3"));
+
+        let backwards = Options {
+            since: options.until,
+            until: options.since,
+            ..options
+        };
+        assert!(process_files(&ledger_path, &[&january_path], &output_path, &backwards).is_err());
+
+        for path in [
+            ledger_path,
+            january_path,
+            february_path,
+            output_path,
+            report_path,
+        ] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_added_entry_is_edited_and_must_balance() {
+        let ledger_path = fixture(
+            "edit.ledger",
+            "2025-01-01 Rent
+    Expenses:Rent  $1000.00
+    Assets:Bank",
+        );
+        let csv_path = fixture(
+            "edit.csv",
+            "Date,Description,Amount\n2025-01-03,Hardware Store,-25.00\n",
+        );
+        let output_path = fixture("edit-out.ledger", "");
+
+        let mut prompter = prompt::Scripted::new(&[
+            "Add new transaction",
+            "2025-01-03 Hardware Store\n    Expenses:Home  $25.00\n    Assets:Bank  -$20.00",
+            "2025-01-03 Hardware Store\r\n    Expenses:Home  $25.00\r\n    Assets:Bank  -$25.00\r\n",
+           "Yes, open",
+        ]);
+        process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &Options::default(),
+            &mut prompter,
+        )
+        .unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.ends_with(
+            "\n2025-01-03 Hardware Store\n    Expenses:Home  $25.00\n    Assets:Bank  -$25.00"
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_new_entry_template_balances() {
+        let drafter = Drafter {
+            style: PostingStyle::default(),
+            separators: Separators::default(),
+            syntax: Syntax::Hledger,
+            asset_account: ASSET_ACCOUNT.to_string(),
+            default: None,
+            named: BTreeMap::new(),
+        };
+        // The bank's leg keeps the statement's sign: a withdrawal is booked to
+        // the account, and a deposit taken from it.
+        for (amount, booked) in [
+            ("-25.00", "$25.00"),
+            ("25.00", "-$25.00"),
+            ("(25.00)", "$25.00"),
+        ] {
+            let entry = drafter.draft(
+                None,
+                "2025-01-03",
+                "hardware",
+                amount,
+                "$",
+                "Expenses:Miscellaneous",
+            );
+            assert_eq!(
+                ledger::check_entry(&entry, Separators::default(), Syntax::Hledger),
+                Ok(()),
+                "{}",
+                entry
+            );
+            let account_leg = entry.lines().nth(1).unwrap();
+            assert_eq!(
+                account_leg.split_whitespace().nth(1),
+                Some(booked),
+                "{}",
+                entry
+            );
+        }
+    }
+
+    #[test]
+    fn test_unbalanced_transactions_are_reported_and_kept_under_strict() {
+        let ledger_path = fixture(
+            "unbalanced.ledger",
+            "2025-01-01 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank   -$5.00",
+        );
+        let csv_path = fixture(
+            "unbalanced.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n",
+        );
+        let output_path = fixture("unbalanced-out.ledger", "");
+        let report_path = fixture("unbalanced-report.txt", "");
+
+        let mut options = Options {
+            auto: true,
+            yes: true,
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-01 Coffee"));
+        let report = fs::read_to_string(&report_path).unwrap();
+        assert!(report.contains("Unbalanced: 1"));
+        assert!(report
+            .contains("  line 1  2025-01-01 Coffee: the postings sum to -$1.00 instead of zero"));
+
+        options.strict = true;
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("2025-01-01 Coffee"));
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_amounts_match_only_in_the_same_commodity() {
+        let ledger_path = fixture(
+            "commodity.ledger",
+            "2025-01-01 Transfer
+    Expenses:Travel   €50,00
+    Assets:Bank
+2025-01-02 Transfer
+    Expenses:Travel   $50.00
+    Assets:Bank
+2025-01-03 Transfer
+    Expenses:Travel   50.00 USD
+    Assets:Bank
+2025-01-03 Transfer
+    Expenses:Travel   £50.00
+    Assets:Bank
+2025-01-04 Transfer
+    Expenses:Travel   €50,00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "commodity.csv",
+            "Date,Description,Amount
+2025-01-01,Transfer,\"€50,00\"
+2025-01-02,Transfer,$50.00
+2025-01-03,Transfer,50.00 USD
+2025-01-03,Transfer,50.00
+2025-01-04,Transfer,$50.00
+",
+        );
+        let output_path = fixture("commodity-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            commodity: Some("GBP".to_string()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert_eq!(updated.matches("* 2025-01-").count(), 4, "{}", updated);
+        assert!(updated.contains("\n2025-01-04 Transfer"), "{}", updated);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_eu_and_us_notation_match_the_same_amount() {
+        let cases = [
+            ("eu", "€1.234,56", "\"1.234,56\"", Some(Locale::Eu)),
+            ("us", "$1,234.56", "\"1,234.56\"", None),
+        ];
+        for (name, posting, csv_amount, locale) in cases {
+            let ledger_path = fixture(
+                &format!("{}.ledger", name),
+                &format!(
+                    "2025-01-01 Rent\n    Expenses:Rent   {}\n    Assets:Bank\n",
+                    posting
+                ),
+            );
+            let csv_path = fixture(
+                &format!("{}.csv", name),
+                &format!("Date,Description,Amount\n2025-01-01,Rent,{}\n", csv_amount),
+            );
+            let output_path = fixture(&format!("{}-out.ledger", name), "");
+
+            let options = Options {
+                auto: true,
+                yes: true,
+                separators: locale.map_or(Separators::default(), Locale::separators),
+                ..Options::default()
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+            let updated = fs::read_to_string(&output_path).unwrap();
+            assert!(updated.starts_with("* 2025-01-01 Rent"), "{}", updated);
+
+            for path in [ledger_path, csv_path, output_path] {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_posting_level_marks_each_leg_from_its_own_statement() {
+        let ledger_path = fixture(
+            "legs.ledger",
+            "2025-01-05 Paycheck
+    Assets:Checking    $800.00
+    Assets:Savings     $200.00
+    Income:Salary
+",
+        );
+        let checking_path = fixture(
+            "legs-checking.csv",
+            "Date,Description,Amount\n2025-01-05,Paycheck,800.00\n",
+        );
+        let savings_path = fixture(
+            "legs-savings.csv",
+            "Date,Description,Amount\n2025-01-05,Paycheck,200.00\n",
+        );
+        let output_path = fixture("legs-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            posting_level: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&checking_path], &output_path, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "2025-01-05 Paycheck
+    * Assets:Checking    $800.00
+    Assets:Savings     $200.00
+    Income:Salary
+"
+        );
+
+        process_files(
+            &ledger_path,
+            &[&checking_path, &savings_path],
+            &output_path,
+            &options,
+        )
+        .unwrap();
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.starts_with("2025-01-05 Paycheck\n"), "{}", output);
+        assert!(output.contains("\n    * Assets:Checking "), "{}", output);
+        assert!(output.contains("\n    * Assets:Savings "), "{}", output);
+        assert!(output.contains("\n    Income:Salary"), "{}", output);
+
+        for path in [ledger_path, checking_path, savings_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_posting_level_suggests_the_leg_with_the_statement_sign() {
+        let ledger_path = fixture(
+            "leg-prompt.ledger",
+            "2025-01-01 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank   -$4.00
+",
+        );
+        let csv_path = fixture(
+            "leg-prompt.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,-4.00\n",
+        );
+        let output_path = fixture("leg-prompt-out.ledger", "");
+
+        let options = Options {
+            posting_level: true,
+            ..Options::default()
+        };
+        let mut prompter = prompt::Scripted::new(&["2025-01-01 Coffee", ""]);
+        process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "2025-01-01 Coffee
+    Expenses:Food  $4.00
+    * Assets:Bank   -$4.00
+"
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_added_entries_follow_the_ledger_layout() {
+        let ledger_path = fixture(
+            "layout.ledger",
+            "2025-01-01 Rent\n\tExpenses:Rent\t$1000.00\n\tAssets:Bank\n",
+        );
+        let csv_path = fixture(
+            "layout.csv",
+            "Date,Description,Amount\n2025-01-04,Blue Bottle,-4.50\n",
+        );
+        let rules_path = fixture("layout.rules", "blue bottle => Expenses:Coffee\n");
+        let output_path = fixture("layout-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.ends_with("\tExpenses:Coffee\t$4.50\n\tAssets:Bank\t-$4.50\n"),
+            "{}",
+            updated
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_excluded_accounts_are_never_offered() {
+        let ledger_path = fixture(
+            "exclude.ledger",
+            "2025-01-01 Transfer
+    Assets:Bank:Checking   $500.00
+    Assets:Bank:Savings   -$500.00
+2025-01-01 Transfer
+    Expenses:Rent          $500.00
+    Assets:Bank:Checking  -$500.00
+",
+        );
+        let csv_path = fixture(
+            "exclude.csv",
+            "Date,Description,Amount\n2025-01-01,Transfer,-500.00\n",
+        );
+        let output_path = fixture("exclude-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            account: Some("Assets:Bank".to_string()),
+            exclude_accounts: vec!["Assets:Bank:Savings".to_string()],
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-01 Transfer\n"), "{}", updated);
+        assert!(updated.contains("\n* 2025-01-01 Transfer\n    Expenses:Rent"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_debit_matches_only_a_bank_leg_going_the_same_way() {
+        let ledger_path = fixture(
+            "signed.ledger",
+            "2025-01-03 Groceries
+    Expenses:Food  $50.00
+    Assets:Bank   -$50.00
+
+2025-01-03 Groceries refund
+    Assets:Bank    $50.00
+    Expenses:Food -$50.00
+",
+        );
+        let csv_path = fixture(
+            "signed.csv",
+            "Date,Description,Amount\n2025-01-03,Groceries,-50.00\n",
+        );
+        let output_path = fixture("signed-out.ledger", "");
+        let run = |options: Options| {
+            let options = Options {
+                auto: true,
+                yes: true,
+                ..options
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            fs::read_to_string(&output_path).unwrap()
+        };
+
+        // With the bank's legs known, the deposit of the same size is no match.
+        for options in [
+            Options {
+                account: Some("Assets:Bank".to_string()),
+                ..Options::default()
+            },
+            Options {
+                bank_account_prefixes: vec!["Assets:".to_string()],
+                ..Options::default()
+            },
+        ] {
+            let updated = run(options);
+            assert!(
+                updated.starts_with("* 2025-01-03 Groceries\n"),
+                "{}",
+                updated
+            );
+            assert!(
+                updated.contains("\n2025-01-03 Groceries refund\n"),
+                "{}",
+                updated
+            );
+        }
+        // Otherwise either leg may carry the statement's sign, so both match
+        // and --yes takes the first.
+        let updated = run(Options::default());
+        assert!(
+            updated.starts_with("* 2025-01-03 Groceries\n"),
+            "{}",
+            updated
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_qif_statement_is_detected_and_matched() {
+        let ledger_path = fixture(
+            "qif.ledger",
+            "2025-01-02 Groceries
+    Expenses:Food      $50.00
+    Assets:Checking
+",
+        );
+        let qif_path = fixture(
+            "statement.qif",
+            "!Type:Bank\nD1/ 2'25\nT-50.00\nN1001\nPGroceries\nMWeekly shop\n^\n",
+        );
+        let output_path = fixture("qif-out.ledger", "");
+
+        let content = fs::read_to_string(&qif_path).unwrap();
+        assert_eq!(InputFormat::detect(&qif_path, &content), InputFormat::Qif);
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&qif_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("* 2025-01-02 Groceries\n"),
+            "{}",
+            updated
+        );
+
+        for path in [ledger_path, qif_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    /// Notes the checkpoint on disk whenever it is asked anything, and cancels.
+    struct PeekCheckpoint {
+        path: String,
+        seen: Vec<Option<Checkpoint>>,
+    }
+
+    impl Prompter for PeekCheckpoint {
+        fn select(&mut self, _message: &str, _choices: Vec<String>) -> Option<usize> {
+            self.seen.push(Checkpoint::load(&self.path).unwrap());
+            None
+        }
+
+        fn multi_select(
+            &mut self,
+            _message: &str,
+            _choices: Vec<String>,
+            _defaults: &[usize],
+        ) -> Option<Vec<usize>> {
+            None
+        }
+
+        fn edit(
+            &mut self,
+            _message: &str,
+            _initial: &str,
+            _validate: prompt::Validator,
+        ) -> Option<String> {
+            None
+        }
+
+        fn account(
+            &mut self,
+            _message: &str,
+            _initial: &str,
+            _accounts: &[String],
+        ) -> Option<String> {
+            None
+        }
+
+        fn text(&mut self, _message: &str, _initial: &str) -> Option<String> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_saves_progress_and_resumes() {
+        let ledger = "2025-01-01 Coffee
+    Expenses:Coffee    $4.00
+    Assets:Checking
+2025-01-03 Lunch
+    Expenses:Food     $12.00
+    Assets:Checking
+";
+        let ledger_path = fixture("checkpoint.ledger", ledger);
+        let csv_path = fixture(
+            "checkpoint.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,-4.00\n2025-01-02,Unknown,-9.99\n2025-01-03,Lunch,-12.00\n",
+        );
+        let output_path = fixture("checkpoint-out.ledger", "");
+        let checkpoint_path = checkpoint::path_for(&output_path);
+
+        // Progress is on disk by the time the second record is asked about,
+        // and gone once the run completes.
+        let options = Options {
+            auto: true,
+            checkpoint_every: 1,
+            ..Options::default()
+        };
+        let mut peek = PeekCheckpoint {
+            path: checkpoint_path.clone(),
+            seen: Vec::new(),
+        };
+        process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut peek,
+        )
+        .unwrap();
+        let saved = peek.seen[0].clone().unwrap();
+        assert_eq!(saved.next_record, 1);
+        assert_eq!(saved.lines[0], "* 2025-01-01 Coffee");
+        assert_eq!(saved.decisions.len(), 1);
+        assert!(!Path::new(&checkpoint_path).exists());
+
+        // A resumed run starts from the saved record with the saved lines and
+        // decisions, so the first record is not looked at again.
+        let lines: Vec<String> = ledger.lines().map(String::from).collect();
+        let mut ignored = saved.clone();
+        ignored.lines = lines.clone();
+        ignored.decisions[0].action = Action::Ignored;
+        ignored.save(&checkpoint_path).unwrap();
+        let json_log = fixture("checkpoint.json", "");
+        let resume = Options {
+            resume: true,
+            json_log: Some(json_log.clone()),
+            ..options.clone()
+        };
+        process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &resume,
+            &mut prompt::Scripted::new(&["Ignore"]),
+        )
+        .unwrap();
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-01 Coffee\n"), "{}", updated);
+        assert!(updated.contains("\n* 2025-01-03 Lunch\n"), "{}", updated);
+        let log = fs::read_to_string(&json_log).unwrap();
+        assert_eq!(log.matches("\"action\"").count(), 3, "{}", log);
+        assert!(!Path::new(&checkpoint_path).exists());
+
+        // A checkpoint saved against another ledger is refused.
+        let stale = Checkpoint {
+            ledger: vec!["2024-12-31 Other".to_string()],
+            ..saved
+        };
+        stale.save(&checkpoint_path).unwrap();
+        let error = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &resume,
+            &mut prompt::Scripted::new(&[]),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(error, ReconcileError::InvalidOptions(_)),
+            "{:?}",
+            error
+        );
+
+        for path in [
+            ledger_path,
+            csv_path,
+            output_path,
+            checkpoint_path,
+            json_log,
+        ] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_reconcile_reports_counts_and_the_final_ledger() {
+        let config = ReconcileConfig {
+            ledger: fixture(
+                "report.ledger",
+                "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n",
+            ),
+            statements: vec![fixture(
+                "report.csv",
+                "Date,Description,Amount\n2025-01-01,Coffee,-4.00\n2025-01-05,Unknown,-9.99\n",
+            )],
+            output: fixture("report-out.ledger", ""),
+            options: Options {
+                yes: true,
+                auto: true,
+                ..Options::default()
+            },
+        };
+
+        let report = reconcile_with(&config, &mut prompt::Scripted::new(&[])).unwrap();
+
+        assert_eq!((report.cleared, report.ignored, report.added), (1, 0, 0));
+        assert_eq!(report.unmatched, 1);
+        assert!(report.ledger.starts_with("* 2025-01-01 Coffee\n"));
+        assert_eq!(fs::read_to_string(&config.output).unwrap(), report.ledger);
+
+        for path in [config.ledger, config.output]
+            .into_iter()
+            .chain(config.statements)
+        {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_ledger_filter_narrows_the_candidates() {
+        let ledger_path = fixture(
+            "filter.ledger",
+            "2025-01-01 Card purchase
+    Expenses:Food       $5.00
+    Assets:Checking
+2025-01-01 Card purchase
+    Expenses:Coffee     $5.00
+    Assets:Checking
+",
+        );
+        let csv_path = fixture(
+            "filter.csv",
+            "Date,Description,Amount\n2025-01-01,Card purchase,-5.00\n",
+        );
+        let output_path = fixture("filter-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ledger_filter: Some("expenses:coffee".to_string()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("2025-01-01 Card purchase\n"),
+            "{}",
+            updated
+        );
+        assert!(
+            updated.contains("\n* 2025-01-01 Card purchase\n    Expenses:Coffee"),
+            "{}",
+            updated
+        );
+
+        let invalid = Options {
+            ledger_filter: Some("(".to_string()),
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &invalid),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_rejects_keep_the_original_rows_of_unmatched_records() {
+        let ledger_path = fixture(
+            "rejects.ledger",
+            "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n",
+        );
+        let csv_path = fixture(
+            "rejects.csv",
+            "Date,Description,Amount,Memo
+2025-01-01,Coffee,-4.00,morning
+2025-01-02,Blue Bottle,-5.25,beans
+2025-01-03,\"Mystery, Inc.\",-9.99,\"card, online\"
+",
+        );
+        let rules_path = fixture("rejects.rules", "blue bottle => Expenses:Coffee\n");
+        let output_path = fixture("rejects-out.ledger", "");
+        let rejects_path = fixture("rejects-out.csv", "");
+
+        let options = Options {
+            yes: true,
+            auto: true,
+            rules: Some(rules_path.clone()),
+            rejects: Some(rejects_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&rejects_path).unwrap(),
+            "Date,Description,Amount,Memo\n2025-01-03,\"Mystery, Inc.\",-9.99,\"card, online\"\n"
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path, rejects_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_assert_balance_compares_the_cleared_postings() {
+        let ledger_path = fixture(
+            "balance.ledger",
+            "* 2025-01-01 Opening balance
+    Assets:Checking     $1,000.00
+    Equity:Opening
+2025-01-02 Coffee
+    Expenses:Coffee        $4.00
+    Assets:Checking
+2025-01-03 Rent
+    Expenses:Rent        $800.00
+    Assets:Checking
+",
+        );
+        let csv_path = fixture(
+            "balance.csv",
+            "Date,Description,Amount\n2025-01-02,Coffee,-4.00\n",
+        );
+        let output_path = fixture("balance-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            assert_balances: vec!["Assets:Checking=$996.00".to_string()],
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let wrong = Options {
+            assert_balances: vec!["Assets=$196".to_string()],
+            ..options
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &wrong).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the cleared balance of Assets is $996.00, but $196 was asserted"
+        );
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .contains("\n* 2025-01-02 Coffee\n"));
+
+        let malformed = Options {
+            assert_balances: vec!["Assets:Checking".to_string()],
+            ..Options::default()
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &malformed),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_comments_are_kept_but_not_matched_against() {
+        let ledger_path = fixture(
+            "comments.ledger",
+            "2025-01-02 Hardware store  ; note: refund pending
+    ; keep the receipt
+    Expenses:Tools      $25.00
+    Assets:Checking
+2025-01-02 Refund
+    Assets:Checking     $25.00  ; card: visa
+    Income:Refunds
+",
+        );
+        let csv_path = fixture(
+            "comments.csv",
+            "Date,Description,Amount\n2025-01-02,Refund,25.00\n",
+        );
+        let output_path = fixture("comments-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "2025-01-02 Hardware store  ; note: refund pending
+    ; keep the receipt
+    Expenses:Tools      $25.00
+    Assets:Checking
+* 2025-01-02 Refund
+    Assets:Checking     $25.00  ; card: visa
+    Income:Refunds
+"
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_stamp_cleared_tags_the_header_once() {
+        let ledger_path = fixture(
+            "stamp.ledger",
+            "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "stamp.csv",
+            "Date,Description,Amount\n2025-01-02,Rent,1000.00\n",
+        );
+        let output_path = fixture("stamp-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            mark: Status::Pending,
+            stamp_cleared: NaiveDate::from_ymd_opt(2025, 6, 1),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        let pending = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            pending.starts_with("! 2025-01-02 Rent  ; reconciled: 2025-06-01\n"),
+            "{}",
+            pending
+        );
+
+        // Clearing the pending transaction later keeps the first stamp.
+        let options = Options {
+            mark: Status::Cleared,
+            include_pending: true,
+            stamp_cleared: NaiveDate::from_ymd_opt(2025, 7, 1),
+            ..options
+        };
+        process_files(&output_path, &[&csv_path], &output_path, &options).unwrap();
+        let cleared = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            cleared.starts_with("* 2025-01-02 Rent  ; reconciled: 2025-06-01\n"),
+            "{}",
+            cleared
+        );
+        assert_eq!(cleared.matches("reconciled:").count(), 1);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_case_and_accent_options_for_descriptions() {
+        let ledger = "2025-01-02 Café\n    Expenses:Coffee  $4.50\n    Assets:Bank\n";
+        let run = |name: &str, csv: &str, options: &Options| {
+            let ledger_path = fixture(&format!("{}.ledger", name), ledger);
+            let csv_path = fixture(
+                &format!("{}.csv", name),
+                &format!("Date,Description,Amount\n2025-01-02,{},4.50\n", csv),
+            );
+            let output_path = fixture(&format!("{}-out.ledger", name), "");
+            let options = Options {
+                auto: true,
+                ..options.clone()
+            };
+            let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            for path in [ledger_path, csv_path, output_path] {
+                fs::remove_file(path).unwrap();
+            }
+            report.cleared
+        };
+
+        assert_eq!(run("case-default", "CAFÉ", &Options::default()), 1);
+        assert_eq!(run("case-unfolded", "CAFE", &Options::default()), 0);
+
+        let folding = Options {
+            ascii_fold: true,
+            ..Options::default()
+        };
+        assert_eq!(run("case-folded", "CAFE", &folding), 1);
+
+        let exact = Options {
+            case_sensitive: true,
+            ..Options::default()
+        };
+        assert_eq!(run("case-exact", "CAFÉ", &exact), 0);
+        assert_eq!(run("case-exact-match", "Café", &exact), 1);
+
+        let exact_folding = Options {
+            ascii_fold: true,
+            ..exact
+        };
+        assert_eq!(run("case-exact-folded", "CAFE", &exact_folding), 0);
+        assert_eq!(run("case-exact-folded-match", "Cafe", &exact_folding), 1);
+    }
+
+    #[test]
+    fn test_negative_debit_matches_the_bank_leg() {
+        let ledger = "2025-01-03 Groceries\n    Expenses:Food  $50.00\n    Assets:Bank  -$50.00\n";
+        let run = |name: &str, options: Options| {
+            let ledger_path = fixture(&format!("{}.ledger", name), ledger);
+            let csv_path = fixture(
+                &format!("{}.csv", name),
+                "Date,Description,Amount\n2025-01-03,Groceries,-50.00\n",
+            );
+            let output_path = fixture(&format!("{}-out.ledger", name), "");
+            let options = Options {
+                auto: true,
+                ..options
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            let updated = fs::read_to_string(&output_path).unwrap();
+            for path in [ledger_path, csv_path, output_path] {
+                fs::remove_file(path).unwrap();
+            }
+            updated
+        };
+
+        assert!(run("debit", Options::default()).starts_with("* 2025-01-03 Groceries"));
+
+        let bank = Options {
+            account: Some("Assets:Bank".to_string()),
+            ..Options::default()
+        };
+        assert!(run("debit-bank", bank).starts_with("* 2025-01-03 Groceries"));
+
+        let legs = Options {
+            posting_level: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            run("debit-leg", legs),
+            "2025-01-03 Groceries\n    Expenses:Food  $50.00\n    * Assets:Bank  -$50.00\n"
+        );
+    }
+
+    #[test]
+    fn test_configured_actions_draft_entries_and_can_clear_them() {
+        let ledger_path = fixture(
+            "actions.ledger",
+            "2025-01-01 Opening\n    Assets:Bank  $100.00\n    Equity\n",
+        );
+        let csv_path = fixture(
+            "actions.csv",
+            "Date,Description,Amount\n2025-01-03,Transfer,-40.00\n2025-01-04,Bakery,-5.00\n",
+        );
+        let output_path = fixture("actions-out.ledger", "");
+
+        let options = Options {
+            actions: vec![MenuAction {
+                name: "Transfer to savings".to_string(),
+                account: "Assets:Savings".to_string(),
+                clear: true,
+                template: None,
+            }],
+            ..Options::default()
+        };
+        let mut prompter = prompt::Scripted::new(&[
+            "Transfer to savings",
+            "2025-01-03 transfer\n    Assets:Savings  $40.00\n    Assets:Bank  -$40.00",
+            "Create and clear",
+            "2025-01-04 bakery\n    Expenses:Food  $5.00\n    Assets:Bank  -$5.00",
+            "Yes, open",
+        ]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!(report.added, 2);
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.contains("\n* 2025-01-03 transfer\n    Assets:Savings  $40.00\n"));
+        assert!(output.contains("\n* 2025-01-04 bakery\n"));
+
+        let clashing = Options {
+            actions: vec![MenuAction {
+                name: "Ignore".to_string(),
+                account: "Expenses:Misc".to_string(),
+                clear: false,
+                template: None,
+            }],
+            ..Options::default()
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &clashing).unwrap_err();
+        assert!(
+            matches!(error, ReconcileError::InvalidOptions(_)),
+            "{}",
+            error
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_templates_draft_rule_entries() {
+        let ledger_path = fixture(
+            "templates.ledger",
+            "2025-01-01 Opening\n    Assets:Checking  $100\n    Equity\n",
+        );
+        let csv_path = fixture(
+            "templates.csv",
+            "Date,Description,Amount\n2025-01-04,Blue Bottle,-5.25\n2025-01-05,Diner,-20.00\n",
+        );
+        let rules_path = fixture(
+            "templates.rules",
+            "blue bottle => Expenses:Coffee\ndiner => Expenses:Eating Out @tip\n",
+        );
+        let default_path = fixture(
+            "templates-default.ledger",
+            "{date} * {description}\n    {account}  {amount}\n    Assets:Checking  {-amount}\n",
+        );
+        let tip_path = fixture(
+            "templates-tip.ledger",
+            "{date} {description}\n    {account}  {amount}\n    Expenses:Tips\n    Assets:Checking  {-amount}\n",
+        );
+        let output_path = fixture("templates-out.ledger", "");
+
+        let options = Options {
+            yes: true,
+            rules: Some(rules_path.clone()),
+            template: Some(default_path.clone()),
+            templates: BTreeMap::from([("tip".to_string(), tip_path.clone())]),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.contains(
+            "2025-01-04 * blue bottle\n    Expenses:Coffee  $5.25\n    Assets:Checking  -$5.25\n"
+        ));
+        assert!(updated
+            .contains("2025-01-05 diner\n    Expenses:Eating Out  $20.00\n    Expenses:Tips\n"));
+
+        // A rule naming a template that isn't configured fails up front.
+        let missing = Options {
+            templates: BTreeMap::new(),
+            ..options.clone()
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &missing).unwrap_err();
+        assert!(error.to_string().contains("\"tip\""), "{}", error);
+
+        // So does one that can't balance.
+        fs::write(
+            &default_path,
+            "{date} {description}\n    {account}  {amount}\n",
+        )
+        .unwrap();
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap_err();
+        assert!(
+            matches!(error, ReconcileError::Template { .. }),
+            "{}",
+            error
+        );
+
+        for path in [
+            ledger_path,
+            csv_path,
+            rules_path,
+            default_path,
+            tip_path,
+            output_path,
+        ] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_headerless_csv_keeps_its_first_row() {
+        let ledger_path = fixture(
+            "headerless.ledger",
+            "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n\n2025-01-03 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "headerless.csv",
+            "2025-01-02,Rent,1000.00\n2025-01-03,Coffee,4.00\n",
+        );
+        let rejects_path = fixture("headerless-rejects.csv", "");
+        let output_path = fixture("headerless-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            no_header: true,
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 2);
+
+        // With a header assumed, the first transaction is taken for one.
+        let report = process_files(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &Options {
+                no_header: false,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.cleared, 1);
+
+        let named = Options {
+            columns: ColumnMapping {
+                date: ColumnSpec::Name("Date".to_string()),
+                ..ColumnMapping::default()
+            },
+            ..options.clone()
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &named).unwrap_err();
+        assert!(
+            matches!(error, ReconcileError::ColumnNotFound { .. }),
+            "{}",
+            error
+        );
+
+        // Rejected rows are written back without a header either.
+        let unmatched = fixture("headerless-unmatched.csv", "2025-02-01,Gym,30.00\n");
+        let rejecting = Options {
+            yes: true,
+            rejects: Some(rejects_path.clone()),
+            ..options
+        };
+        process_files(&ledger_path, &[&unmatched], &output_path, &rejecting).unwrap();
+        assert_eq!(
+            fs::read_to_string(&rejects_path).unwrap(),
+            "2025-02-01,Gym,30.00\n"
+        );
+
+        for path in [ledger_path, csv_path, unmatched, rejects_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_relax_and_retry_offers_near_misses() {
+        let ledger_path = fixture(
+            "relax.ledger",
+            "2025-01-10 Hardware Store\n    Expenses:Home  $25.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "relax.csv",
+            "Date,Description,Amount\n2025-01-03,Hardware Store,-25.40\n2025-02-20,Hardware Store,-25.00\n",
+        );
+        let output_path = fixture("relax-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        let mut prompter =
+            prompt::Scripted::new(&["Relax", "2025-01-10 Hardware Store", "Relax", "Ignore"]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        // The near miss was offered rather than cleared outright, while the
+        // record weeks away found nothing even with relaxed criteria.
+        assert_eq!(report.cleared, 1);
+        assert_eq!(report.unmatched, 1);
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-10 Hardware Store"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_gzipped_statement_and_ledger_are_read_and_written() {
+        let gzipped = |name: &str, content: &str| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            let path = env::temp_dir()
+                .join(format!("ledger-reconcile-{}-{}", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned();
+            fs::write(&path, encoder.finish().unwrap()).unwrap();
+            path
+        };
+        let ledger_path = gzipped(
+            "gzip.ledger.gz",
+            "2025-01-02 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n",
+        );
+        let csv_path = gzipped(
+            "gzip.csv.gz",
+            "Date,Description,Amount\n2025-01-02,Rent,1000.00\n",
+        );
+        let output_path = gzipped("gzip-out.ledger.gz", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert!(fs::read(&output_path).unwrap().starts_with(&GZIP_MAGIC));
+        assert!(read_input(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-02 Rent"));
+        assert_eq!(InputFormat::detect(&csv_path, ""), InputFormat::Csv);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_normalize_strips_statement_boilerplate() {
+        let ledger_path = fixture(
+            "normalize.ledger",
+            "2025-01-02 Blue Bottle\n    Expenses:Coffee  $4.50\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "normalize.csv",
+            "Date,Description,Amount\n2025-01-02,POS DEBIT    BLUE BOTTLE  ,-4.50\n",
+        );
+        let output_path = fixture("normalize-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 0);
+
+        let options = Options {
+            normalize: vec![Normalization {
+                field: StatementField::Description,
+                pattern: r"^POS DEBIT\s+".to_string(),
+                replace: String::new(),
+                ledger: false,
+            }],
+            ..options
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 1);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sort_csv_orders_records_by_date() {
+        let records = |rows: &[&str]| -> Vec<Vec<String>> {
+            rows.iter()
+                .map(|date| {
+                    vec![
+                        date.to_string(),
+                        String::new(),
+                        "1".to_string(),
+                        String::new(),
+                    ]
+                })
+                .collect()
+        };
+        let sort = |options: &Options| {
+            let mut sorted = records(&["2025-01-03", "soon", "2025-01-01", "2025-01-02"]);
+            sorted.sort_by(|a, b| by_date(a, b, options));
+            sorted
+                .into_iter()
+                .map(|record| record[0].clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sort(&Options::default()),
+            ["2025-01-01", "2025-01-02", "2025-01-03", "soon"]
+        );
+        let reversed = Options {
+            reverse: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            sort(&reversed),
+            ["2025-01-03", "2025-01-02", "2025-01-01", "soon"]
+        );
+    }
+
+    #[test]
+    fn test_sorted_records_keep_their_rows_for_rejects() {
+        let ledger_path = fixture(
+            "sort.ledger",
+            "2025-01-01 Opening\n    Assets:Bank  $100\n    Equity\n",
+        );
+        let csv_path = fixture(
+            "sort.csv",
+            "Date,Description,Amount\n2025-01-09,Late,1.00\n2025-01-02,Early,2.00\n",
+        );
+        let rejects_path = fixture("sort-rejects.csv", "");
+        let output_path = fixture("sort-out.ledger", "");
+
+        let options = Options {
+            yes: true,
+            sort_csv: Some(CsvSort::Date),
+            rejects: Some(rejects_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&rejects_path).unwrap(),
+            "Date,Description,Amount\n2025-01-02,Early,2.00\n2025-01-09,Late,1.00\n"
+        );
+
+        for path in [ledger_path, csv_path, rejects_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_balance_assertions_and_lot_prices_are_not_matched_as_amounts() {
+        let ledger_lines: Vec<String> = "2025-01-03 Rent
+    Expenses:Rent  $1234.56
+    Assets:Bank
+
+2025-01-03 Coffee
+    Expenses:Coffee  $4.00
+    Assets:Bank  $-4.00 = $1229.56
+
+2025-01-03 Broker
+    Assets:Broker  10 AAPL {$150.00} @ $155.00
+    Assets:Bank
+"
+        .lines()
+        .map(String::from)
+        .collect();
+        let options = Options::default();
+        let candidates = load_candidates(&ledger_lines, None, &Normalizer::default(), &options);
+        // `parsed` false stands in for an amount the statement's notation
+        // hides from the parser, which is then looked for in the entry's text.
+        let matches = |description: &str, amount: &str, parsed: bool| -> Vec<usize> {
+            let line = StatementLine {
+                date: "2025-01-03",
+                parsed_date: NaiveDate::from_ymd_opt(2025, 1, 3),
+                description,
+                tokens: &fuzzy::Tokens::new(description),
+                amount,
+                parsed_amount: amount::parse_amount(amount, Separators::default())
+                    .filter(|_| parsed),
+                commodity: None,
+                id: "",
+            };
+            candidates
+                .iter()
+                .filter_map(|candidate| match_candidate(candidate, &line, &options))
+                .map(|candidate| candidate.header_line)
+                .collect()
+        };
+
+        // The assertion figure is the bank balance, not what the coffee cost.
+        for parsed in [true, false] {
+            assert_eq!(matches("coffee", "1229.56", parsed), Vec::<usize>::new());
+            assert_eq!(matches("coffee", "-4.00", parsed), vec![4]);
+            assert_eq!(matches("broker", "150.00", parsed), Vec::<usize>::new());
+        }
+        assert_eq!(matches("broker", "10", true), vec![8]);
+        assert_eq!(matches("broker", "10150.00", true), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_confident_fuzzy_match_is_accepted_with_one_choice() {
+        let ledger_path = fixture(
+            "accept.ledger",
+            "2025-01-03 Coffee Shop
+    Expenses:Food  $4.50
+    Assets:Bank
+
+2025-01-03 Corner Store
+    Expenses:Food  $4.50
+    Assets:Bank
+
+2025-01-05 Book Barn
+    Expenses:Books  $9.00
+    Assets:Bank
+
+2025-01-05 Bookshop Annex
+    Expenses:Books  $9.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "accept.csv",
+            "Date,Description,Amount\n2025-01-03,COFFEE SHOP,-4.50\n2025-01-05,BOOK NOOK,-9.00\n",
+        );
+        let output_path = fixture("accept-out.ledger", "");
+
+        let options = Options {
+            fuzzy: true,
+            similarity: 0,
+            auto_accept_score: Some(90),
+            ..Options::default()
+        };
+        // The first record's best match is offered on its own; the second has
+        // no confident match, so the full list is shown straight away.
+        let mut prompter = prompt::Scripted::new(&["Accept", "Ignore this line"]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!(report.cleared, 1);
+        assert_eq!(report.ignored, 1);
+        assert!(fs::read_to_string(&output_path)
+            .unwrap()
+            .starts_with("* 2025-01-03 Coffee Shop"));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_count_tallies_matches_without_writing() {
+        let ledger = "2025-01-03 Coffee
+    Expenses:Food  $4.50
+    Assets:Bank
+
+2025-01-05 Books
+    Expenses:Books  $9.00
+    Assets:Bank
+
+2025-01-05 Books
+    Expenses:Books  $9.00
+    Assets:Bank
+";
+        let ledger_path = fixture("count.ledger", ledger);
+        let csv_path = fixture(
+            "count.csv",
+            "Date,Description,Amount\n2025-01-03,Coffee,-4.50\n2025-01-05,Books,-9.00\n2025-01-09,Mystery,-1.00\n",
+        );
+        let output_path = env::temp_dir()
+            .join(format!(
+                "ledger-reconcile-{}-count-out.ledger",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+
+        let options = Options {
+            count: true,
+            checkpoint_every: 1,
+            ..Options::default()
+        };
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompt::Scripted::new(&[]),
+        )
+        .unwrap();
+
+        assert_eq!(report.unmatched, 1);
+        assert_eq!(report.cleared + report.ignored + report.added, 0);
+        assert!(!report.aborted);
+        assert_eq!(report.ledger, ledger);
+        assert!(!Path::new(&output_path).exists());
+        assert!(!Path::new(&checkpoint::path_for(&output_path)).exists());
+
+        for path in [ledger_path, csv_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_beancount_ledger_is_cleared_and_extended() {
+        let ledger_path = fixture(
+            "beancount.beancount",
+            "2025-01-01 open Assets:Checking USD
+
+2025-01-03 txn \"Blue Bottle\" \"Coffee\"
+  receipt: \"bb.pdf\"
+  Expenses:Coffee  4.50 USD
+  Assets:Checking  -4.50 USD
+
+2025-01-04 * \"Landlord\" \"Rent\"
+  Expenses:Rent  1000.00 USD
+  Assets:Checking
+",
+        );
+        let csv_path = fixture(
+            "beancount.csv",
+            "Date,Description,Amount\n2025-01-03,Blue Bottle,-4.50\n2025-01-04,Landlord,-1000.00\n2025-01-05,Bakery,-3.00\n",
+        );
+        let rules_path = fixture("beancount.rules", "bakery => Expenses:Food\n");
+        let output_path = fixture("beancount-out.beancount", "");
+
+        let options = Options {
+            syntax: Syntax::Beancount,
+            auto: true,
+            yes: true,
+            account: Some("Assets:Checking".to_string()),
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        // The already cleared rent is not offered again, and the metadata
+        // line isn't taken for a posting without an amount.
+        assert_eq!(report.cleared, 1);
+        assert_eq!(report.added, 1);
+        assert_eq!(report.unmatched, 1);
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.contains("2025-01-03 * \"Blue Bottle\" \"Coffee\"\n  receipt:"));
+        let added = &updated[updated.find("2025-01-05").unwrap()..];
+        assert!(added.starts_with("2025-01-05 txn \"bakery\"\n  Expenses:Food "));
+        assert!(added.contains("-3.00 USD"));
+        assert_eq!(
+            ledger::check_entry(added, Separators::default(), Syntax::Beancount),
+            Ok(())
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_hledger_output_is_pinned_byte_for_byte() {
+        let ledger_path = fixture(
+            "pinned.ledger",
+            "; household
+2025-01-02 (1042) Coffee Shop  ; morning
+    Expenses:Food          $4.00
+    Assets:Bank
+
+! 2025-01-03 Rent
+    Expenses:Rent       $1000.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "pinned.csv",
+            "Date,Description,Amount\n2025-01-02,Coffee Shop,-4.00\n2025-01-05,Bakery,-3.00\n",
+        );
+        let rules_path = fixture("pinned.rules", "bakery => Expenses:Food\n");
+        let output_path = fixture("pinned-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "; household
+* 2025-01-02 (1042) Coffee Shop  ; morning
+    Expenses:Food          $4.00
+    Assets:Bank
+
+! 2025-01-03 Rent
+    Expenses:Rent       $1000.00
+    Assets:Bank
+2025-01-05 bakery
+    Expenses:Food          $3.00
+    Assets:Bank           -$3.00
+"
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sorted_insert_places_new_entries_by_date() {
+        let ledger_path = fixture(
+            "sorted.ledger",
+            "2025-01-10 Rent
+    Expenses:Rent  $1000.00
+    Assets:Bank
+
+2025-12-10 Rent
+    Expenses:Rent  $1000.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "sorted.csv",
+            "Date,Description,Amount\n2025-06-15,Bakery,-3.00\n",
+        );
+        let rules_path = fixture("sorted.rules", "bakery => Expenses:Food\n");
+        let output_path = fixture("sorted-out.ledger", "");
+        let json_log_path = fixture("sorted-log.json", "");
+
+        let options = Options {
+            yes: true,
+            sorted_insert: true,
+            rules: Some(rules_path.clone()),
+            json_log: Some(json_log_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("2025-01-10 Rent\n    Expenses:Rent  $1000.00\n    Assets:Bank\n\n2025-06-15 bakery\n"),
+            "{}",
+            updated
+        );
+        assert!(
+            updated.contains("-$3.00\n\n2025-12-10 Rent\n"),
+            "{}",
+            updated
+        );
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_log_path).unwrap()).unwrap();
+        assert_eq!(log["decisions"][0]["line"], 5);
+
+        for path in [
+            ledger_path,
+            csv_path,
+            rules_path,
+            output_path,
+            json_log_path,
+        ] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_trim_trailing_only_touches_written_lines() {
+        let ledger_path = fixture(
+            "trim.ledger",
+            "2025-01-03 Coffee Shop   \n    Expenses:Food  $4.00\n    Assets:Bank\n\n2025-01-04 Rent  \n    Expenses:Rent  $1000.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "trim.csv",
+            "Date,Description,Amount\n2025-01-03,Coffee Shop,4.00\n",
+        );
+        let output_path = fixture("trim-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            trim_trailing: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("* 2025-01-03 Coffee Shop\n"),
+            "{}",
+            updated
+        );
+        assert!(updated.contains("\n2025-01-04 Rent  \n"), "{}", updated);
+
+        let mut lines = vec!["a".to_string(), "b ".to_string(), "c\t".to_string()];
+        let original = lines.clone();
+        lines[1] = "* b ".to_string();
+        lines.push("2025-01-05 new \n    Expenses:Food  $1.00  ".to_string());
+        assert_eq!(trim_touched(&mut lines, &original), 3);
+        assert_eq!(
+            lines,
+            [
+                "a",
+                "* b",
+                "c\t",
+                "2025-01-05 new\n    Expenses:Food  $1.00"
+            ]
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_blank_descriptions_match_on_date_and_amount() {
+        let ledger_path = fixture(
+            "fields.ledger",
+            "2025-01-02 Coffee Shop
+    Expenses:Food  $4.00
+    Assets:Bank
+
+2025-01-04 Corner Store
+    Expenses:Food  $4.00
+    Assets:Bank
+
+2025-01-04 Grocer
+    Expenses:Food  $9.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "fields.csv",
+            "Date,Description,Amount\n2025-01-04,,4.00\n2025-01-05,POS 991,9.00\n",
+        );
+        let output_path = fixture("fields-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            date_window: 3,
+            match_fields: vec![MatchField::Date, MatchField::Amount],
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("2025-01-02 Coffee Shop\n"),
+            "{}",
+            updated
+        );
+        assert!(
+            updated.contains("\n* 2025-01-04 Corner Store\n"),
+            "{}",
+            updated
+        );
+        assert!(updated.contains("\n* 2025-01-04 Grocer\n"), "{}", updated);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_new_entries_use_the_configured_accounts() {
+        let ledger_path = fixture(
+            "accounts.ledger",
+            "2025-01-01 Rent\n    Expenses:Rent  $1000.00\n    Liabilities:Visa\n",
+        );
+        let csv_path = fixture(
+            "accounts.csv",
+            "Date,Description,Amount\n2025-01-03,Bakery,-3.00\n",
+        );
+        let rules_path = fixture("accounts.rules", "bakery => Expenses:Food\n");
+        let output_path = fixture("accounts-out.ledger", "");
+
+        let options = Options {
+            yes: true,
+            rules: Some(rules_path.clone()),
+            asset_account: Some("Liabilities:Visa".to_string()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.contains(
+                "2025-01-03 bakery\n    Expenses:Food     $3.00\n    Liabilities:Visa  -$3.00"
+            ),
+            "{}",
+            updated
+        );
+
+        let blank = Options {
+            misc_account: Some(" ".to_string()),
+            ..Options::default()
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &blank).unwrap_err();
+        assert!(
+            matches!(&error, ReconcileError::InvalidOptions(message) if message.contains("--misc-account")),
+            "{}",
+            error
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cancelled_prompt_asks_before_skipping() {
+        let ledger_path = fixture(
+            "cancel.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n\n2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "cancel.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n",
+        );
+        let output_path = fixture("cancel-out.ledger", "");
+        let run = |answers: &[&str]| {
+            process_files_with(
+                &ledger_path,
+                &[&csv_path],
+                &output_path,
+                &Options::default(),
+                &mut prompt::Scripted::new(answers),
+            )
+            .unwrap()
+        };
+
+        let report = run(&[prompt::ESC, "No, ask again", "2025-01-01 Coffee"]);
+        assert_eq!((report.cleared, report.aborted), (1, false));
+
+        let report = run(&[prompt::ESC, "Yes, skip it"]);
+        assert_eq!(
+            (report.cleared, report.ignored, report.aborted),
+            (0, 1, false)
+        );
+
+        let report = run(&[prompt::ESC, prompt::ESC]);
+        assert_eq!((report.cleared, report.aborted), (0, true));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bank_account_prefix_compares_the_card_posting() {
+        let ledger_path = fixture(
+            "prefix.ledger",
+            "2025-01-06 Dinner
+    Expenses:Dining            $40.00
+    Expenses:Tips               $5.00
+    Liabilities:CreditCard    -$45.00
+
+2025-01-06 Dinner
+    Expenses:Dining            $40.00
+    Liabilities:CreditCard    -$40.00
+",
+        );
+        let csv_path = fixture(
+            "prefix.csv",
+            "Date,Description,Amount\n2025-01-06,Dinner,-40.00\n",
+        );
+        let output_path = fixture("prefix-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            bank_account_prefixes: vec!["Assets:".to_string(), "liabilities:credit".to_string()],
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-06 Dinner\n"), "{}", updated);
+        assert!(
+            updated.contains(
+                "\n* 2025-01-06 Dinner\n    Expenses:Dining            $40.00\n    Liabilities"
+            ),
+            "{}",
+            updated
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_limit_processes_only_the_first_records() {
+        let ledger_path = fixture(
+            "limit.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n\n2025-01-02 Lunch\n    Expenses:Food  $12.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "limit.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n2025-01-02,Lunch,12.00\n2025-01-03,Unknown,9.99\n",
+        );
+        let output_path = fixture("limit-out.ledger", "");
+        let report_path = fixture("limit-report.txt", "");
+
+        let options = Options {
+            auto: true,
+            limit: Some(1),
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!((report.cleared, report.unmatched), (1, 0));
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("* 2025-01-01 Coffee\n"), "{}", updated);
+        assert!(updated.contains("\n2025-01-02 Lunch\n"), "{}", updated);
+        let summary = fs::read_to_string(&report_path).unwrap();
+        assert!(
+            summary.contains("Not processed (--limit): 2 (partial run)"),
+            "{}",
+            summary
+        );
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_split_payment_clears_the_parts_that_add_up() {
+        let ledger_path = fixture(
+            "split.ledger",
+            "2025-01-05 Hardware Store
+    Expenses:Home  $60.00
+    Assets:Bank
+
+2025-01-05 Garden Centre
+    Expenses:Garden  $40.00
+    Assets:Bank
+
+2025-01-06 Bakery
+    Expenses:Food  $5.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "split.csv",
+            "Date,Description,Amount\n2025-01-05,CONSOLIDATED CHARGE,-100.00\n",
+        );
+        let output_path = fixture("split-out.ledger", "");
+        let options = Options {
+            date_window: 2,
+            ..Options::default()
+        };
+
+        let mut prompter = prompt::Scripted::new(&[
+            "Clear several",
+            "2025-01-05 Hardware Store,2025-01-06 Bakery",
+            "Clear several",
+            "2025-01-05 Hardware Store,2025-01-05 Garden Centre",
+        ]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!((report.cleared, report.unmatched), (2, 0));
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("* 2025-01-05 Hardware Store\n"),
+            "{}",
+            updated
+        );
+        assert!(
+            updated.contains("\n* 2025-01-05 Garden Centre\n"),
+            "{}",
+            updated
+        );
+        assert!(updated.contains("\n2025-01-06 Bakery\n"), "{}", updated);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_strict_amount_format_skips_malformed_amounts() {
+        let ledger_path = fixture(
+            "amount-format.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "amount-format.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n2025-01-02,Hotel,\"50,00.0\"\n",
+        );
+        let output_path = fixture("amount-format-out.ledger", "");
+        let report_path = fixture("amount-format-report.txt", "");
+
+        let options = Options {
+            auto: true,
+            strict_amount_format: true,
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!((report.cleared, report.unmatched), (1, 0));
+        let summary = fs::read_to_string(&report_path).unwrap();
+        assert!(summary.contains("Malformed amounts: 1"), "{}", summary);
+
+        let strict = Options {
+            strict: true,
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &strict),
+            Err(ReconcileError::AmountParse { record: 2, .. })
+        ));
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_same_file_sees_through_relative_paths() {
+        let dir =
+            std::env::temp_dir().join(format!("ledger-reconcile-same-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ledger = dir.join("main.ledger");
+        fs::write(&ledger, "").unwrap();
+        let ledger = ledger.to_string_lossy();
+        let dotted = dir.join(".").join("main.ledger");
+
+        assert!(same_file(&ledger, &dotted.to_string_lossy()));
+        assert!(!same_file(
+            &ledger,
+            &dir.join("updated.ledger").to_string_lossy()
+        ));
+        assert!(!same_file("-", "-"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_annotate_header_leaves_the_ledger_untouched() {
+        let original = "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n";
+        let ledger_path = fixture("annotate.ledger", original);
+        let csv_path = fixture(
+            "annotate.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n",
+        );
+        let output_path = fixture("annotate-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            annotate_header: NaiveDate::from_ymd_opt(2025, 6, 1),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let annotated = fs::read_to_string(&output_path).unwrap();
+        let header = format!("; reconciled 2025-06-01 from {}\n", csv_path);
+        assert!(annotated.starts_with(&header), "{}", annotated);
+        assert!(
+            annotated.contains("\n* 2025-01-01 Coffee\n"),
+            "{}",
+            annotated
+        );
+        assert_eq!(fs::read_to_string(&ledger_path).unwrap(), original);
+
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &ledger_path, &options),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+        assert_eq!(fs::read_to_string(&ledger_path).unwrap(), original);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unknown_account_in_new_entry_needs_confirming() {
+        let ledger_path = fixture(
+            "unknown-account.ledger",
+            "2025-01-01 Groceries\n    Expenses:Food  $30.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "unknown-account.csv",
+            "Date,Description,Amount\n2025-01-03,Bakery,-5.00\n2025-01-04,Cinema,-12.00\n",
+        );
+        let output_path = fixture("unknown-account-out.ledger", "");
+
+        let mut prompter = prompt::Scripted::new(&[
+            "Add new transaction",
+            "2025-01-03 Bakery\n    Expenses:Fod  $5.00\n    Assets:Bank  -$5.00",
+            "No, pick",
+            "Expenses:Food",
+            "Add new transaction",
+            "2025-01-04 Cinema\n    Expenses:Fun  $12.00\n    Assets:Bank  -$12.00",
+            "Yes, open",
+        ]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &Options::default(),
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!(report.added, 2);
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            output.contains("\n2025-01-03 Bakery\n    Expenses:Food  $5.00\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("\n    Expenses:Fun  $12.00\n"),
+            "{}",
+            output
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_explain_names_the_failing_field() {
+        let ledger_lines: Vec<String> = "2025-01-03 Hardware Store
+    Expenses:Home  $25.00
+    Assets:Bank
+
+2025-01-20 Hardware Store
+    Expenses:Home  $25.00
+    Assets:Bank"
+            .lines()
+            .map(String::from)
+            .collect();
+        let options = Options::default();
+        let candidates = load_candidates(&ledger_lines, None, &Normalizer::default(), &options);
+        let line = StatementLine {
+            date: "2025-01-03",
+            parsed_date: NaiveDate::from_ymd_opt(2025, 1, 3),
+            description: "hardware store",
+            tokens: &fuzzy::Tokens::new("hardware store"),
+            amount: "25.00",
+            parsed_amount: amount::parse_amount("25.00", Separators::default()),
+            commodity: None,
+            id: "",
+        };
+
+        let explained = explain(&candidates, &line, &options);
+        let (first, second) = explained.split_once("\nline 5:").unwrap();
+        assert!(
+            first.contains("\nline 1: 2025-01-03 Hardware Store\n"),
+            "{}",
+            explained
+        );
+        assert!(first.contains("  date: pass ("), "{}", explained);
+        assert!(first.contains("  => matches"), "{}", explained);
+        assert!(
+            second.contains("  date: fail (2025-01-03 against 2025-01-20, within 0 day(s))"),
+            "{}",
+            explained
+        );
+        assert!(second.contains("  amount: pass ("), "{}", explained);
+        assert!(second.contains("  => does not match"), "{}", explained);
+    }
+
+    #[test]
+    fn test_explain_writes_nothing() {
+        let ledger_path = fixture(
+            "explain.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "explain.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,4.00\n2025-01-02,Lunch,12.00\n",
+        );
+        let output_path = fixture("explain-out.ledger", "");
+
+        for wanted in ["2", "lunch"] {
+            let options = Options {
+                explain: Some(wanted.to_string()),
+                ..Options::default()
+            };
+            let report = process_files_with(
+                &ledger_path,
+                &[&csv_path],
+                &output_path,
+                &options,
+                &mut prompt::Scripted::new(&[]),
+            )
+            .unwrap();
+            assert_eq!(report.cleared, 0);
+        }
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "");
+
+        let missing = Options {
+            explain: Some("9".to_string()),
+            ..Options::default()
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &missing),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bom_prefixed_csv_keeps_its_date_column() {
+        let ledger_path = fixture(
+            "bom.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "bom.csv",
+            "\u{feff}Date,Description,Amount\n2025-01-01,Coffee,4.00\n",
+        );
+        let headless_path = fixture("bom-headless.csv", "\u{feff}2025-01-01,Coffee,4.00\n");
+        let output_path = fixture("bom-out.ledger", "");
+
+        let named = Options {
+            auto: true,
+            columns: ColumnMapping {
+                date: ColumnSpec::Name("Date".to_string()),
+                ..ColumnMapping::default()
+            },
+            ..Options::default()
+        };
+        let headless = Options {
+            auto: true,
+            no_header: true,
+            ..Options::default()
+        };
+        for (csv_path, options) in [(&csv_path, named), (&headless_path, headless)] {
+            let report = process_files(&ledger_path, &[csv_path], &output_path, &options).unwrap();
+            assert_eq!((report.cleared, report.unmatched), (1, 0), "{}", csv_path);
+        }
+        for path in [ledger_path, csv_path, headless_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_semicolon_and_tab_delimiters_are_detected() {
+        let ledger_path = fixture(
+            "delimiter.ledger",
+            "2025-01-01 Coffee\n    Expenses:Food  $4.50\n    Assets:Bank\n",
+        );
+        let output_path = fixture("delimiter-out.ledger", "");
+
+        for (name, content) in [
+            (
+                "semicolon.csv",
+                "Datum;Omschrijving;Bedrag\n2025-01-01;Coffee;4,50\n",
+            ),
+            (
+                "tab.csv",
+                "Date\tDescription\tAmount\n2025-01-01\tCoffee\t4.50\n",
+            ),
+        ] {
+            let csv_path = fixture(name, content);
+            let options = Options {
+                auto: true,
+                ..Options::default()
+            };
+            let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            assert_eq!((report.cleared, report.unmatched), (1, 0), "{}", name);
+
+            let given = Options {
+                delimiter: Some(','),
+                ..options
+            };
+            assert!(
+                process_files(&ledger_path, &[&csv_path], &output_path, &given).is_err(),
+                "{}",
+                name
+            );
+            fs::remove_file(csv_path).unwrap();
+        }
+
+        for path in [ledger_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_exact_matches_are_claimed_before_looser_ones() {
+        let ledger_path = fixture(
+            "passes.ledger",
+            "2025-01-03 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n\n2025-01-06 Lunch\n    Expenses:Food  $12.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "passes.csv",
+            "Date,Description,Amount\n2025-01-02,Coffee,4.00\n2025-01-03,Coffee,4.00\n2025-01-05,Lunch,12.00\n",
+        );
+        let output_path = fixture("passes-out.ledger", "");
+        let report_path = fixture("passes-report.txt", "");
+        let log_path = fixture("passes-log.json", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            date_window: 1,
+            report: Some(report_path.clone()),
+            json_log: Some(log_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!((report.cleared, report.unmatched), (2, 1));
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&log_path).unwrap()).unwrap();
+        let actions: Vec<&str> = log["decisions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|decision| decision["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, ["unmatched", "cleared", "cleared"]);
+        let summary = fs::read_to_string(&report_path).unwrap();
+        assert!(
+            summary.contains("  exact pass: 1, second pass: 1\n"),
+            "{}",
+            summary
+        );
+
+        for path in [ledger_path, csv_path, output_path, report_path, log_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_group_by_day_clears_a_days_picks_at_once() {
+        let ledger_path = fixture(
+            "by-day.ledger",
+            "2025-01-03 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank
+
+2025-01-03 Lunch
+    Expenses:Food  $12.00
+    Assets:Bank
+
+2025-01-04 Cinema
+    Expenses:Fun  $10.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "by-day.csv",
+            "Date,Description,Amount\n2025-01-03,Lunch,12.00\n2025-01-04,Cinema,10.00\n2025-01-03,Coffee,4.00\n",
+        );
+        let output_path = fixture("by-day-out.ledger", "");
+
+        let options = Options {
+            group_by_day: true,
+            ..Options::default()
+        };
+        let mut prompter = prompt::Scripted::new(&["", prompt::ESC, "Yes, skip it"]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&csv_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+
+        assert_eq!((report.cleared, report.ignored), (2, 1));
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(output.starts_with("* 2025-01-03 Coffee\n"), "{}", output);
+        assert!(output.contains("\n* 2025-01-03 Lunch\n"), "{}", output);
+        assert!(output.contains("\n2025-01-04 Cinema\n"), "{}", output);
+
+        let auto = Options {
+            auto: true,
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &auto),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_match_in_included_file_is_cleared_there() {
+        let child = "2025-01-02 Rent\n    Expenses:Rent  $900.00\n    Assets:Bank\n";
+        let child_path = fixture("include-child.journal", child);
+        let child_name = Path::new(&child_path)
+            .file_name()
+            .unwrap()
+            .to_string_lossy();
+        let parent = format!(
+            "include {}\n\n2025-01-01 Coffee\n    Expenses:Food  $4.00\n    Assets:Bank\n",
+            child_name
+        );
+        let ledger_path = fixture("include-parent.journal", &parent);
+        let csv_path = fixture(
+            "include.csv",
+            "Date,Description,Amount\n2025-01-02,Rent,900.00\n2025-01-05,Bakery,3.00\n",
+        );
+        let rules_path = fixture("include.rules", "bakery => Expenses:Food\n");
+        let output_path = env::temp_dir()
+            .join(format!(
+                "ledger-reconcile-{}-include-out.journal",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        // Written to another file, the ledger would leave the child to be
+        // overwritten in place, so nothing is written at all.
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &options),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+        assert_eq!(fs::read_to_string(&child_path).unwrap(), child);
+        assert!(!Path::new(&output_path).exists());
+
+        let report = process_files(&ledger_path, &[&csv_path], &ledger_path, &options).unwrap();
+        assert_eq!((report.cleared, report.added), (1, 1));
+
+        let updated_child = fs::read_to_string(&child_path).unwrap();
+        assert_eq!(updated_child, format!("* {}", child));
+        let updated = fs::read_to_string(&ledger_path).unwrap();
+        assert!(updated.starts_with(&parent), "{}", updated);
+        assert!(updated.contains("2025-01-05 bakery"), "{}", updated);
+        assert!(!updated.contains("Rent"), "{}", updated);
+
+        for path in [ledger_path, child_path, csv_path, rules_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_debit_column_matches_the_inferred_asset_amount() {
+        let ledger_path = fixture(
+            "inferred.ledger",
+            "2025-02-01 Hardware store\n    Expenses:Home  $37.25\n    Assets:Checking\n",
+        );
+        let csv_path = fixture(
+            "inferred.csv",
+            "Date,Description,Debit,Credit\n2025-02-01,Hardware store,37.25,\n",
+        );
+        let output_path = fixture("inferred-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            account: Some("Assets:Checking".to_string()),
+            columns: ColumnMapping {
+                amount: AmountColumns::Split {
+                    debit: ColumnSpec::Name("Debit".to_string()),
+                    credit: ColumnSpec::Name("Credit".to_string()),
+                    debit_sign: DebitSign::Negative,
+                },
+                ..ColumnMapping::default()
+            },
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        // Only the elided leg is compared, and it can only match as -$37.25.
+        assert_eq!(report.cleared, 1);
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("* 2025-02-01 Hardware store"),
+            "{}",
+            updated
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_markers_after_the_date_count_and_are_replaced_in_place() {
+        let ledger_path = fixture(
+            "post-date.ledger",
+            "2025-01-03 * Rent
+    Expenses:Rent  $1000.00
+    Assets:Bank
+
+2025-01-04 ! Gym
+    Expenses:Gym  $40.00
+    Assets:Bank
+
+2025-01-05 Coffee
+    Expenses:Food  $4.00
+    Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "post-date.csv",
+            "Date,Description,Amount\n2025-01-03,Rent,-1000.00\n2025-01-04,Gym,-40.00\n2025-01-05,Coffee,-4.00\n",
+        );
+        let output_path = fixture("post-date-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 1);
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-03 * Rent\n"), "{}", updated);
+        assert!(updated.contains("\n2025-01-04 ! Gym\n"), "{}", updated);
+        assert!(updated.contains("\n* 2025-01-05 Coffee\n"), "{}", updated);
+
+        // Only `*` counts, so the pending transaction is offered and cleared.
+        let options = Options {
+            cleared_markers: vec![Status::Cleared],
+            ..options
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 2);
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("2025-01-03 * Rent\n"), "{}", updated);
+        assert!(updated.contains("\n2025-01-04 * Gym\n"), "{}", updated);
+
+        let both = Options {
+            include_pending: true,
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &both),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_indented_transactions_are_matched_and_keep_their_indentation() {
+        let ledger_path = fixture(
+            "indented.ledger",
+            "  2025-01-02 Rent
+      Expenses:Rent  $900.00
+      Assets:Bank
+
+  2025-01-03 Coffee
+      Expenses:Food  $4.00
+      Assets:Bank
+",
+        );
+        let csv_path = fixture(
+            "indented.csv",
+            "Date,Description,Amount\n2025-01-02,Rent,-900.00\n2025-01-03,Coffee,-4.00\n",
+        );
+        let output_path = fixture("indented-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!((report.cleared, report.unmatched), (2, 0));
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(updated.starts_with("  * 2025-01-02 Rent\n"), "{}", updated);
+        assert!(updated.contains("\n  * 2025-01-03 Coffee\n"), "{}", updated);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_payee_map_renames_descriptions_before_matching() {
+        let ledger_path = fixture(
+            "payees.ledger",
+            "2025-01-03 Blue Bottle\n    Expenses:Coffee  $4.50\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "payees.csv",
+            "Date,Description,Amount\n2025-01-03,SQ *BLUE BOTTLE 0412,4.50\n2025-01-04,AMZN Mktp US*2K4,12.00\n",
+        );
+        let payees_path = fixture(
+            "payees.map",
+            "/^sq \\*blue/ => Blue Bottle\namzn mktp => Amazon\n",
+        );
+        let rules_path = fixture("payees.rules", "amazon => Expenses:Shopping\n");
+        let output_path = fixture("payees-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            payee_map: Some(payees_path.clone()),
+            rules: Some(rules_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!((report.cleared, report.added), (1, 1));
+        let updated = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            updated.starts_with("* 2025-01-03 Blue Bottle"),
+            "{}",
+            updated
+        );
+        assert!(updated.contains("2025-01-04 amazon\n"), "{}", updated);
+
+        for path in [ledger_path, csv_path, payees_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_print_cleared_writes_only_this_runs_transactions() {
+        let ledger_path = fixture(
+            "print.ledger",
+            "* 2024-12-30 Earlier\n    Expenses:Food  $1.00\n    Assets:Bank\n\n2025-01-02 Rent\n    Expenses:Rent  $900.00\n    Assets:Bank\n\n2025-01-03 Coffee  ; oat\n    Expenses:Food  $4.00\n    Assets:Bank\n\n2025-01-09 Still open\n    Expenses:Food  $7.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "print.csv",
+            "Date,Description,Amount\n2025-01-03,Coffee,4.00\n2025-01-02,Rent,900.00\n",
+        );
+        let output_path = fixture("print-out.ledger", "");
+        let print_path = fixture("print-cleared.journal", "");
+
+        let options = Options {
+            auto: true,
+            print_cleared: Some(print_path.clone()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&print_path).unwrap(),
+            "* 2025-01-02 Rent\n    Expenses:Rent  $900.00\n    Assets:Bank\n\n* 2025-01-03 Coffee  ; oat\n    Expenses:Food  $4.00\n    Assets:Bank\n"
+        );
+
+        // A record split across both open transactions exports each of them.
+        let split_path = fixture(
+            "print-split.csv",
+            "Date,Description,Amount\n2025-01-06,CONSOLIDATED,-907.00\n",
+        );
+        let options = Options {
+            auto: false,
+            date_window: 7,
+            ..options
+        };
+        let mut prompter =
+            prompt::Scripted::new(&["Clear several", "2025-01-02 Rent,2025-01-09 Still open"]);
+        let report = process_files_with(
+            &ledger_path,
+            &[&split_path],
+            &output_path,
+            &options,
+            &mut prompter,
+        )
+        .unwrap();
+        assert_eq!(report.cleared, 2);
+        assert_eq!(
+            fs::read_to_string(&print_path).unwrap(),
+            "* 2025-01-02 Rent\n    Expenses:Rent  $900.00\n    Assets:Bank\n\n* 2025-01-09 Still open\n    Expenses:Food  $7.00\n    Assets:Bank\n"
+        );
+
+        for path in [ledger_path, csv_path, split_path, output_path, print_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unparsed_amount_is_found_only_as_a_whole_figure() {
+        let posting = "2025-01-03 coffee\n    expenses:food  15.00\n    assets:bank";
+        assert!(!contains_amount(posting, "5.00"));
+        assert!(!contains_amount(posting, "15.0"));
+        assert!(contains_amount(posting, "15.00"));
+        assert!(contains_amount("expenses:food  5.00.", "5.00"));
+        assert!(!contains_amount("expenses:food  1,205.00", "205.00"));
+
+        let ledger_path = fixture(
+            "whole-figure.ledger",
+            "2025-01-03 Coffee\n    Expenses:Food  $15.00\n    Assets:Bank\n",
+        );
+        let csv_path = fixture(
+            "whole-figure.csv",
+            "Date,Description,Amount\n2025-01-03,Coffee,5.00\n",
+        );
+        let output_path = fixture("whole-figure-out.ledger", "");
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+        assert_eq!(report.cleared, 0);
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_match_label_shows_location_and_context_within_the_width() {
+        let ledger_lines: Vec<String> = "; groceries
+2025-01-03 Whole Foods Market Downtown Location #1042
+    Expenses:Food  $50.00
+    Assets:Bank
+; end of january"
+            .lines()
+            .map(String::from)
+            .collect();
+        let options = Options {
+            match_context: 1,
+            ..Options::default()
+        };
+        let candidates = load_candidates(&ledger_lines, None, &Normalizer::default(), &options);
+        let line = StatementLine {
+            date: "2025-01-03",
+            parsed_date: NaiveDate::from_ymd_opt(2025, 1, 3),
+            description: "whole foods",
+            tokens: &fuzzy::Tokens::new("whole foods"),
+            amount: "50.00",
+            parsed_amount: amount::parse_amount("50.00", Separators::default()),
+            commodity: None,
+            id: "",
+        };
+        let matched = Match::new(&candidates[0], &line, Some(90), &options);
+
+        assert_eq!(
+            matched.label_within(None),
+            "  ┆ ; groceries
+[ 90%] 2025-01-03 Whole Foods Market Downtown Location #1042  (line 2)
+    Expenses:Food  $50.00
+    Assets:Bank
+  ┆ ; end of january"
+        );
+        let narrow = matched.label_within(Some(42));
+        assert!(
+            narrow.contains("\n[ 90%] 2025-01-03 Whole Foods…  (line 2)\n"),
+            "{}",
+            narrow
+        );
+        assert!(narrow
+            .lines()
+            .all(|line| console::measure_text_width(line) <= 40));
+    }
+
+    #[test]
+    fn test_invert_amount_flips_the_direction_of_drafted_entries() {
+        // A card export with charges positive and the refund negative.
+        let ledger_path = fixture(
+            "signs.ledger",
+            "2025-01-01 Opening\n    Liabilities:Card  -$100.00\n    Equity\n",
+        );
+        let csv_path = fixture(
+            "signs.csv",
+            "Date,Description,Amount\n2025-01-05,CAFE,12.00\n2025-01-06,REFUND,-30.00\n",
+        );
+        let rules_path = fixture(
+            "signs.rules",
+            "cafe => Expenses:Food\nrefund => Income:Refunds\n",
+        );
+        let output_path = fixture("signs-out.ledger", "");
+        let run = |invert_amount| {
+            let options = Options {
+                auto: true,
+                yes: true,
+                rules: Some(rules_path.clone()),
+                asset_account: Some("Liabilities:Card".to_string()),
+                invert_amount,
+                ..Options::default()
+            };
+            let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            assert_eq!(report.added, 2);
+            let updated = fs::read_to_string(&output_path).unwrap();
+            let postings: Vec<(String, String)> = updated
+                .lines()
+                .skip(3)
+                .filter(|line| line.starts_with(' '))
+                .map(|line| {
+                    let mut fields = line.split_whitespace();
+                    let account = fields.next().unwrap().to_string();
+                    (account, fields.next().unwrap_or_default().to_string())
+                })
+                .collect();
+            postings
+        };
+        let posting = |account: &str, amount: &str| (account.to_string(), amount.to_string());
+
+        assert_eq!(
+            run(true),
+            [
+                posting("Expenses:Food", "$12.00"),
+                posting("Liabilities:Card", "-$12.00"),
+                posting("Income:Refunds", "-$30.00"),
+                posting("Liabilities:Card", "$30.00"),
+            ]
+        );
+        // As exported, the charge reads as money in and the refund as money out.
+        assert_eq!(
+            run(false),
+            [
+                posting("Expenses:Food", "-$12.00"),
+                posting("Liabilities:Card", "$12.00"),
+                posting("Income:Refunds", "$30.00"),
+                posting("Liabilities:Card", "-$30.00"),
+            ]
+        );
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_invert_amount_picks_the_card_leg_of_a_positive_charge() {
+        let ledger = "2025-01-04 Bookshop\n    Expenses:Books  $30.00\n    Liabilities:Card\n";
+        let run = |name: &str, invert_amount: bool| {
+            let ledger_path = fixture(&format!("{}.ledger", name), ledger);
+            let csv_path = fixture(
+                &format!("{}.csv", name),
+                "Date,Description,Amount\n2025-01-04,Bookshop,30.00\n",
+            );
+            let output_path = fixture(&format!("{}-out.ledger", name), "");
+            let options = Options {
+                auto: true,
+                posting_level: true,
+                invert_amount,
+                ..Options::default()
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+            let updated = fs::read_to_string(&output_path).unwrap();
+            for path in [ledger_path, csv_path, output_path] {
+                fs::remove_file(path).unwrap();
+            }
+            updated
+        };
+
+        assert_eq!(
+            run("card-as-exported", false),
+            "2025-01-04 Bookshop\n    * Expenses:Books  $30.00\n    Liabilities:Card\n"
+        );
+        assert_eq!(
+            run("card-inverted", true),
+            "2025-01-04 Bookshop\n    Expenses:Books  $30.00\n    * Liabilities:Card\n"
+        );
+    }
+
+    #[test]
+    fn test_interactive_edit_unmatched_reviews_them_after_the_rest() {
+        let config = ReconcileConfig {
+            ledger: fixture(
+                "review.ledger",
+                "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n",
+            ),
+            statements: vec![fixture(
+                "review.csv",
+                "Date,Description,Amount\n2025-01-02,Unknown A,-9.99\n2025-01-01,Coffee,-4.00\n2025-01-05,Bakery,-7.50\n2025-01-06,Unknown C,-1.00\n",
+            )],
+            output: fixture("review-out.ledger", ""),
+            options: Options {
+                interactive_edit_unmatched: true,
+                ..Options::default()
+            },
+        };
+        let added = "2025-01-05 Bakery\n    Expenses:Coffee  $7.50\n    Assets:Checking";
+        // The first question is about the second record; the three without a
+        // match come after it, two of them ignored together.
+        let mut prompter = prompt::Scripted::new(&[
+            "2025-01-01 Coffee",
+            "Choose which",
+            "[1],[4]",
+            "Add new",
+            added,
+        ]);
+
+        let report = reconcile_with(&config, &mut prompter).unwrap();
+
+        assert_eq!((report.cleared, report.added, report.unmatched), (1, 1, 2));
+        assert!(!report.aborted);
+        assert!(
+            report.ledger.starts_with("* 2025-01-01 Coffee\n"),
+            "{}",
+            report.ledger
+        );
+        assert!(
+            report.ledger.ends_with(&format!("{}\n", added)),
+            "{}",
+            report.ledger
+        );
+
+        let mut options = config.options.clone();
+        options.group_by_day = true;
+        assert!(matches!(
+            reconcile_with(
+                &ReconcileConfig {
+                    options,
+                    ..config.clone()
+                },
+                &mut prompt::Scripted::new(&[]),
+            ),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [&config.ledger, &config.statements[0], &config.output] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_closing_balance_is_the_opening_plus_this_runs_clears() {
+        // Rent was cleared by an earlier statement, so it is already in the
+        // opening balance.
+        let ledger_path = fixture(
+            "period.ledger",
+            "* 2025-01-01 Rent
+    Expenses:Rent        $800.00
+    Assets:Checking
+2025-02-02 Coffee
+    Expenses:Coffee        $4.00
+    Assets:Checking
+2025-02-03 Books
+    Expenses:Books        $20.00
+    Assets:Checking
+",
+        );
+        let csv_path = fixture(
+            "period.csv",
+            "Date,Description,Amount\n2025-02-02,Coffee,-4.00\n",
+        );
+        let output_path = fixture("period-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            account: Some("Assets:Checking".to_string()),
+            opening_balance: Some("$1,000.00".to_string()),
+            closing_balance: Some("$996.00".to_string()),
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let short = Options {
+            closing_balance: Some("$990.00".to_string()),
+            ..options.clone()
+        };
+        let error = process_files(&ledger_path, &[&csv_path], &output_path, &short).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Assets:Checking closes at $996.00 after this run, but the statement closes at $990.00, $6.00 apart"
+        );
+
+        let unscoped = Options {
+            account: None,
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &unscoped),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_quiet_matches_asks_only_about_inexact_and_missing_matches() {
+        let config = ReconcileConfig {
+            ledger: fixture(
+                "quiet.ledger",
+                "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n\n2025-01-06 Tea\n    Expenses:Coffee    $3.00\n    Assets:Checking\n",
+            ),
+            statements: vec![fixture(
+                "quiet.csv",
+                "Date,Description,Amount\n2025-01-01,Coffee,-4.00\n2025-01-05,Tea,-3.00\n2025-01-07,Unknown,-9.99\n",
+            )],
+            output: fixture("quiet-out.ledger", ""),
+            options: Options {
+                quiet_matches: true,
+                date_window: 2,
+                ..Options::default()
+            },
+        };
+        // Coffee is cleared without a question; Tea is a day off, so it is
+        // asked about like the record that matches nothing.
+        let mut prompter = prompt::Scripted::new(&["2025-01-06 Tea", "Ignore"]);
+
+        let report = reconcile_with(&config, &mut prompter).unwrap();
+
+        assert_eq!((report.cleared, report.unmatched), (2, 1));
+        assert!(
+            report.ledger.contains("* 2025-01-01 Coffee\n")
+                && report.ledger.contains("* 2025-01-06 Tea\n"),
+            "{}",
+            report.ledger
+        );
+
+        for path in [&config.ledger, &config.statements[0], &config.output] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_clearing_marks_the_date_line_below_attached_comments() {
+        let ledger_path = fixture(
+            "attached.ledger",
+            "P 2025-01-01 EUR $1.10
+; paid with the new card
+2025-01-02 Coffee
+    ; receipt in the drawer
+    Expenses:Coffee        $4.00
+    (Budget:Coffee)       -$4.00
+    Assets:Checking
+",
+        );
+        let csv_path = fixture(
+            "attached.csv",
+            "Date,Description,Amount\n2025-01-02,Coffee,-4.00\n",
+        );
+        let output_path = fixture("attached-out.ledger", "");
+
+        let options = Options {
+            auto: true,
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "P 2025-01-01 EUR $1.10
+; paid with the new card
+* 2025-01-02 Coffee
+    ; receipt in the drawer
+    Expenses:Coffee        $4.00
+    (Budget:Coffee)       -$4.00
+    Assets:Checking
+"
+        );
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_reverse_match_annotates_the_statement_and_leaves_the_ledger() {
+        let ledger = "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n\n2025-01-02 Market\n    Expenses:Food    $50.00\n    Assets:Checking\n\n2025-01-02 Market\n    Expenses:Food    $50.00\n    Assets:Checking\n";
+        let config = ReconcileConfig {
+            ledger: fixture("reverse.ledger", ledger),
+            statements: vec![fixture(
+                "reverse.csv",
+                "Date,Description,Amount,Memo\n2025-01-01,Coffee,-4.00,card\n2025-01-02,Market,-50.00,\n2025-01-09,Unknown,-9.99,atm\n",
+            )],
+            output: fixture("reverse-out.ledger", ""),
+            options: Options {
+                reverse_match: true,
+                csv_out: Some(fixture("reverse-out.csv", "")),
+                ..Options::default()
+            },
+        };
+        let mut prompter =
+            prompt::Scripted::new(&["2025-01-01 Coffee", "Ignore this line", "Ignore"]);
+
+        let report = reconcile_with(&config, &mut prompter).unwrap();
+
+        assert_eq!(
+            (report.cleared, report.ignored, report.unmatched),
+            (1, 1, 1)
+        );
+        let csv_out = config.options.csv_out.as_deref().unwrap();
+        assert_eq!(
+            fs::read_to_string(csv_out).unwrap(),
+            "Date,Description,Amount,Memo,Status,Ledger
+2025-01-01,Coffee,-4.00,card,cleared,line 1: 2025-01-01 Coffee
+2025-01-02,Market,-50.00,,matched,line 5: 2025-01-02 Market
+2025-01-09,Unknown,-9.99,atm,unmatched,
+"
+        );
+        assert_eq!(fs::read_to_string(&config.output).unwrap(), "");
+        assert_eq!(fs::read_to_string(&config.ledger).unwrap(), ledger);
+
+        let unwritten = Options {
+            csv_out: None,
+            ..config.options.clone()
+        };
+        assert!(matches!(
+            reconcile_with(
+                &ReconcileConfig {
+                    options: unwritten,
+                    ..config.clone()
+                },
+                &mut prompt::Scripted::new(&[]),
+            ),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [&config.ledger, &config.statements[0], &config.output] {
+            fs::remove_file(path).ok();
+        }
+        fs::remove_file(csv_out).ok();
+    }
+
+    #[test]
+    fn test_description_columns_together_pick_the_transaction() {
+        let ledger_path = fixture(
+            "memo.ledger",
+            "2025-01-03 Amazon order 1234\n    Expenses:Books    $20.00\n    Assets:Checking\n\n2025-01-03 Amazon order 5678\n    Expenses:Games    $20.00\n    Assets:Checking\n",
+        );
+        let csv_path = fixture(
+            "memo.csv",
+            "Date,Name,Memo,Amount\n2025-01-03,AMAZON,Order 5678,-20.00\n2025-01-04,Bakery,Main St,-6.50\n",
+        );
+        let rules_path = fixture("memo.rules", "bakery => Expenses:Food\n");
+        let output_path = fixture("memo-out.ledger", "");
+
+        // The payee alone matches both orders; the memo tells them apart.
+        let options = Options {
+            auto: true,
+            yes: true,
+            rules: Some(rules_path.clone()),
+            columns: ColumnMapping {
+                description: vec![
+                    ColumnSpec::Name("Name".to_string()),
+                    ColumnSpec::Name("Memo".to_string()),
+                ],
+                amount: AmountColumns::Single(ColumnSpec::Name("Amount".to_string())),
+                ..ColumnMapping::default()
+            },
+            ..Options::default()
+        };
+        process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        assert!(
+            output.starts_with("2025-01-03 Amazon order 1234\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("\n* 2025-01-03 Amazon order 5678\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("2025-01-04 bakery main st\n"), "{}", output);
+
+        for path in [ledger_path, csv_path, rules_path, output_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_noise_records_are_skipped_and_counted() {
+        let ledger_path = fixture(
+            "noise.ledger",
+            "2025-01-01 Coffee\n    Expenses:Coffee    $4.00\n    Assets:Checking\n",
+        );
+        let csv_path = fixture(
+            "noise.csv",
+            "Date,Description,Amount\n2025-01-01,Coffee,-4.00\n2025-01-31,Interest,0.03\n2025-01-31,Monthly fee,-2.00\n2025-01-31,Monthly fee refund,2.00\n",
+        );
+        let output_path = fixture("noise-out.ledger", "");
+        let report_path = fixture("noise-report.txt", "");
+
+        let options = Options {
+            auto: true,
+            yes: true,
+            ignore_below: Some(Decimal::new(50, 2)),
+            ignore_descs: vec!["/^monthly fee$/".to_string()],
+            report: Some(report_path.clone()),
+            ..Options::default()
+        };
+        let report = process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap();
+
+        // The refund is neither small nor exactly the fee.
+        assert_eq!((report.cleared, report.unmatched), (1, 1));
+        let summary = fs::read_to_string(&report_path).unwrap();
+        assert!(summary.contains("Skipped as noise: 2"), "{}", summary);
+
+        let invalid = Options {
+            ignore_descs: vec!["/(/".to_string()],
+            ..options
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &invalid),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path, report_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_threaded_search_gives_the_serial_results() {
+        // Records share dates, amounts and payees with several transactions,
+        // so which one each clears depends on the claims and on going in order.
+        let payees = ["Coffee Shop", "Grocer", "Book Store", "Gas Station"];
+        let mut ledger = String::new();
+        for n in 0..30 {
+            ledger.push_str(&format!(
+                "2025-01-{:02} {} {}\n    Expenses:Misc  ${}.00\n    Assets:Bank\n\n",
+                n % 5 + 1,
+                payees[n % 4],
+                n,
+                n % 6 + 1
+            ));
+        }
+        let mut csv = String::from("Date,Description,Amount\n");
+        for n in 0..36 {
+            csv.push_str(&format!(
+                "2025-01-{:02},{},{}.00\n",
+                n % 5 + 1 + n % 2,
+                payees[n % 4].to_uppercase(),
+                n % 6 + 1
+            ));
+        }
+        let ledger_path = fixture("threads.ledger", &ledger);
+        let csv_path = fixture("threads.csv", &csv);
+        let output_path = fixture("threads-out.ledger", "");
+
+        let run = |threads| {
+            let options = Options {
+                auto: true,
+                yes: true,
+                fuzzy: true,
+                date_window: 1,
+                threads,
+                ..Options::default()
+            };
+            process_files(&ledger_path, &[&csv_path], &output_path, &options).unwrap()
+        };
+        let serial = run(None);
+        assert!(serial.cleared > 0 && serial.cleared < 30, "{:?}", serial);
+        for threads in [1, 4, 0] {
+            assert_eq!(run(Some(threads)), serial, "--threads {}", threads);
+        }
+
+        let interactive = Options {
+            threads: Some(4),
+            ..Options::default()
+        };
+        assert!(matches!(
+            process_files(&ledger_path, &[&csv_path], &output_path, &interactive),
+            Err(ReconcileError::InvalidOptions(_))
+        ));
+
+        for path in [ledger_path, csv_path, output_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -1,201 +1,773 @@
-use clap::Parser;
-use csv;
-use inquire::Select;
-use std::collections::HashSet;
+mod config;
+
+use chrono::NaiveDate;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::{Config, Settings};
+use ledger_reconcile::{
+    AmountColumns, ColumnMapping, ColumnSpec, CsvSort, DebitSign, Encoding, InputFormat, Locale,
+    LogWriter, MatchField, MenuAction, Normalization, Options, ReconcileConfig, ReconcileError,
+    ReconcileReport, Separators, Status, Syntax,
+};
+use log::{Level, LevelFilter};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
 use std::env;
-use std::fs;
 use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::path::Path;
 
 /// CLI tool to mark hledger transactions as cleared by matching them to a CSV file.
 #[derive(Parser)]
 #[command(name = "hledger_clear", version = "0.1.0", author = "Ian Wilson (uid0")]
 #[command(about = "Mark hledger transactions as cleared by matching CSV files")]
+#[command(subcommand_negates_reqs = true)]
+#[command(
+    after_help = "Exit status: 0 if every statement record was cleared or added, 1 on an error, 2 on a bad command line, 3 if some records were left unmatched or skipped, 4 if the run was stopped early."
+)]
 struct Cli {
-    /// Path to the ledger file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the ledger file, or `-` to read it from stdin
     #[arg(short, long)]
     ledger: Option<String>,
 
-    /// Path to the CSV file
-    #[arg(short, long)]
-    csv: String,
+    /// Syntax of the ledger file
+    #[arg(long, value_enum, default_value_t = Syntax::Hledger)]
+    syntax: Syntax,
+
+    /// Path to the CSV (or OFX/QFX/QIF) statement file, or `-` to read it from stdin;
+    /// repeat the flag or separate paths with commas to reconcile several files
+    #[arg(short, long, required = true, value_delimiter = ',')]
+    csv: Vec<String>,
+
+    /// Format of the statement file (detected from the extension or contents if omitted)
+    #[arg(short, long, value_enum)]
+    format: Option<InputFormat>,
 
-    /// Output file for the updated ledger
+    /// Field delimiter of CSV statements, e.g. `;` or `\t` for tab [default: whichever of `,`
+    /// `;` and tab the header uses most]
+    #[arg(long, value_parser = parse_delimiter)]
+    delimiter: Option<char>,
+
+    /// Text encoding of the statement (a byte-order mark is honoured, then UTF-8 is tried, falling
+    /// back to windows1252)
+    #[arg(long, value_enum)]
+    encoding: Option<Encoding>,
+
+    /// Output file for the updated ledger, or `-` for stdout (requires --yes)
     #[arg(short, long, default_value = "updated.ledger")]
     output: String,
-}
 
-/// Read and process the ledger and CSV files, then match transactions interactively.
-fn process_files(ledger_path: &str, csv_path: &str, output_path: &str) -> io::Result<()> {
-    let ledger_content = fs::read_to_string(ledger_path)?;
-    let mut ledger_lines: Vec<String> = ledger_content.lines().map(String::from).collect();
-
-    // Filter out cleared transactions (marked with "*")
-    let uncleared_lines: Vec<_> = ledger_lines
-        .windows(3)
-        .filter(|block| !block[0].starts_with("*"))
-        .map(|block| block.join("\n"))
-        .collect();
-
-    let csv_content = fs::read_to_string(csv_path)?;
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(csv_content.as_bytes());
-    let csv_records: Vec<Vec<String>> = csv_reader
-        .records()
-        .filter_map(|result| result.ok())
-        .map(|record| record.iter().map(String::from).collect())
-        .collect();
-
-    for record in csv_records {
-        let date = record[0].trim();
-        let description = record[1].trim().to_lowercase();
-        let amount = record[2].trim().replace('$', "");
-
-        println!("\n--- CSV Transaction ---");
-        println!(
-            "Date: {}, Description: {}, Amount: {}",
-            date, description, amount
-        );
-        println!("-----------------------");
-
-        let matches: Vec<(usize, String)> = uncleared_lines
-            .iter()
-            .enumerate()
-            .filter_map(|(index, combined)| {
-                let normalized = combined.to_lowercase().replace('$', "");
-
-                if normalized.contains(date)
-                    && normalized.contains(&description)
-                    && normalized.contains(&amount)
-                {
-                    Some((index, combined.clone()))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        if matches.is_empty() {
-            println!("No matching transaction found in ledger.");
-
-            let action = Select::new(
-                "What would you like to do?",
-                vec!["Ignore", "Add Stock Expense Item", "Exit"],
-            )
-            .prompt()
-            .unwrap_or_else(|_| "Ignore");
-
-            if action == "Add Stock Expense Item" {
-                let new_entry = format!(
-                    "{} {}
-    Expenses:Miscellaneous          ${}
-    Assets:Bank                    -${}",
-                    date, description, amount, amount
-                );
-                ledger_lines.push(new_entry.clone());
-                println!("Added new transaction to ledger:");
-                println!("{}", new_entry);
-            } else if action == "Exit" {
-                println!("Exiting program.");
-                fs::write(output_path, ledger_lines.join("\n"))?;
-                println!("Updated ledger written to {}", output_path);
-                return Ok(());
-            } else {
-                println!("Ignored this transaction.");
-            }
+    /// Write the result back to the ledger file itself (implies --backup)
+    #[arg(short, long, conflicts_with = "output")]
+    in_place: bool,
 
-            continue;
-        }
+    /// Let --output overwrite the ledger file without --backup, and without asking
+    #[arg(long)]
+    force: bool,
+
+    /// CSV column holding the date (zero-based index or header name)
+    #[arg(long, default_value = "0")]
+    date_col: ColumnSpec,
+
+    /// CSV column holding the description (zero-based index or header name); several, as in
+    /// `Name,Memo`, are joined with a space
+    #[arg(long, default_value = "1", value_delimiter = ',')]
+    desc_col: Vec<ColumnSpec>,
+
+    /// CSV column holding the signed amount (zero-based index or header name) [default: 2]
+    #[arg(long, conflicts_with_all = ["debit_col", "credit_col"])]
+    amount_col: Option<ColumnSpec>,
+
+    /// CSV column holding debits, for exports without a single amount column
+    #[arg(long, requires = "credit_col")]
+    debit_col: Option<ColumnSpec>,
+
+    /// CSV column holding credits, for exports without a single amount column
+    #[arg(long, requires = "debit_col")]
+    credit_col: Option<ColumnSpec>,
+
+    /// CSV column holding the bank's transaction ID, matched exactly against ledger `(code)`s
+    #[arg(long)]
+    id_col: Option<ColumnSpec>,
+
+    /// Sign applied to values in the debit column (credits get the opposite sign)
+    #[arg(long, value_enum, default_value_t = DebitSign::Negative)]
+    debit_sign: DebitSign,
+
+    /// Negate every statement amount before matching and drafting entries, for exports
+    /// that show charges as positive and payments as negative
+    #[arg(long)]
+    invert_amount: bool,
+
+    /// Accept amounts that differ from the CSV by at most this much
+    #[arg(long, default_value = "0.00")]
+    amount_tolerance: Decimal,
+
+    /// Only match transactions with a posting to this account, comparing amounts on that posting
+    #[arg(long)]
+    account: Option<String>,
+
+    /// Never offer transactions with a posting to this account (repeatable); wins over --account
+    #[arg(long = "exclude-account", value_name = "ACCOUNT")]
+    exclude_accounts: Vec<String>,
+
+    /// Only compare statement amounts with postings whose account starts with this prefix
+    /// (repeatable), such as `Assets:` or `Liabilities:CreditCard`; by default any posting is
+    #[arg(long = "bank-account-prefix", value_name = "PREFIX")]
+    bank_account_prefixes: Vec<String>,
+
+    /// Only offer transactions whose text (header and postings) matches this case-insensitive
+    /// regular expression; --account and --exclude-account then narrow them further
+    #[arg(long, value_name = "REGEX")]
+    ledger_filter: Option<String>,
+
+    /// Commodity of CSV amounts written without one, e.g. `EUR` or `€`
+    #[arg(long)]
+    commodity: Option<String>,
+
+    /// Decimal mark of amounts in the CSV and the ledger (inferred per amount if neither separator is given)
+    #[arg(long, conflicts_with = "locale")]
+    decimal_sep: Option<char>,
+
+    /// Thousands separator of amounts in the CSV and the ledger
+    #[arg(long, conflicts_with = "locale")]
+    thousands_sep: Option<char>,
+
+    /// Number notation, as a shortcut for --decimal-sep and --thousands-sep
+    #[arg(long, value_enum)]
+    locale: Option<Locale>,
+
+    /// chrono format of CSV dates, e.g. `%m/%d/%Y` (common formats are detected if omitted)
+    #[arg(long)]
+    csv_date_format: Option<String>,
+
+    /// The CSV files have no header row, so every row is a record; columns must be given by index
+    #[arg(long)]
+    no_header: bool,
+
+    /// Work through the statement records in this order instead of as the files list them
+    #[arg(long, value_enum)]
+    sort_csv: Option<CsvSort>,
+
+    /// With --sort-csv, take the newest records first
+    #[arg(long, requires = "sort_csv")]
+    reverse: bool,
 
-        println!("\n--- Matching Ledger Entries ---");
-        for (i, (_, block)) in matches.iter().enumerate() {
-            println!("{}. {}", i + 1, block);
+    /// Accept ledger dates up to this many days before or after the CSV date
+    #[arg(long, default_value_t = 0)]
+    date_window: u32,
+
+    /// Only process CSV records dated on or after this day (YYYY-MM-DD)
+    #[arg(long)]
+    since: Option<NaiveDate>,
+
+    /// Only process CSV records dated on or before this day (YYYY-MM-DD)
+    #[arg(long)]
+    until: Option<NaiveDate>,
+
+    /// Skip CSV records whose amount is smaller than this, such as interest of a few cents
+    #[arg(long, value_name = "AMOUNT")]
+    ignore_below: Option<Decimal>,
+
+    /// Skip CSV records whose description contains this text, or matches it when written as
+    /// /regex/ (repeatable)
+    #[arg(long = "ignore-desc", value_name = "PATTERN")]
+    ignore_descs: Vec<String>,
+
+    /// Clear records with exactly one ledger match without prompting
+    #[arg(long)]
+    auto: bool,
+
+    /// Like --auto, but also take the first of several matches and skip unmatched records
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Status to mark matched transactions with
+    #[arg(long, value_enum, default_value_t = Status::Cleared)]
+    mark: Status,
+
+    /// Offer transactions already marked pending ("!") as candidates
+    #[arg(long)]
+    include_pending: bool,
+
+    /// Comma-separated status markers of transactions (and postings) that are already
+    /// reconciled and never offered; the rest are candidates [default: *,!]
+    #[arg(
+        long,
+        value_name = "MARKERS",
+        value_delimiter = ',',
+        value_parser = parse_marker,
+        conflicts_with = "include_pending"
+    )]
+    cleared_markers: Vec<Status>,
+
+    /// Mark the matched posting(s) instead of the transaction header, for split
+    /// transactions whose legs settle separately
+    #[arg(long)]
+    posting_level: bool,
+
+    /// Also accept descriptions that are similar to, rather than contained in, the ledger payee
+    #[arg(long)]
+    fuzzy: bool,
+
+    /// Minimum description similarity (0-100) accepted by --fuzzy
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(0..=100))]
+    similarity: u8,
+
+    /// Offer to accept the best --fuzzy match with a single Enter when its
+    /// similarity (0-100) is at least this, before listing every match
+    #[arg(long, value_name = "SCORE", value_parser = clap::value_parser!(u8).range(0..=100))]
+    auto_accept_score: Option<u8>,
+
+    /// Which of date, description and amount a transaction must agree on, e.g. `date,amount`
+    /// for statements whose descriptions are useless; matches are then ordered by how close
+    /// they are in date and amount
+    #[arg(long, value_name = "FIELDS", value_delimiter = ',')]
+    match_fields: Vec<MatchField>,
+
+    /// Compare descriptions with their case as written instead of ignoring it
+    #[arg(long)]
+    case_sensitive: bool,
+
+    /// Strip accents before comparing descriptions, so `Café` matches `CAFE`
+    #[arg(long)]
+    ascii_fold: bool,
+
+    /// Show this many ledger lines before and after each transaction offered as a match
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    match_context: usize,
+
+    /// Regex substitutions applied to statement fields before matching, only read from the
+    /// config file
+    #[arg(skip)]
+    normalize: Vec<Normalization>,
+
+    /// Rules file of `pattern => account` lines used to add unmatched records automatically
+    #[arg(long)]
+    rules: Option<String>,
+
+    /// File of `bank text => payee` lines; a statement description matching one is replaced
+    /// by that payee before matching and in new entries
+    #[arg(long, value_name = "PATH")]
+    payee_map: Option<String>,
+
+    /// Ledger entry file with {date}, {description}, {amount}, {-amount} and {account}
+    /// placeholders, used to draft the entries added for unmatched records
+    #[arg(long, value_name = "PATH")]
+    template: Option<String>,
+
+    /// Account that entries added from the prompt are booked to [default: Expenses:Miscellaneous]
+    #[arg(long, value_name = "ACCOUNT")]
+    misc_account: Option<String>,
+
+    /// Account that balances new entries drafted without a template [default: Assets:Bank]
+    #[arg(long, value_name = "ACCOUNT")]
+    asset_account: Option<String>,
+
+    /// Named entry templates that rules (`=> account @name`) and menu actions may pick, only
+    /// read from the config file
+    #[arg(skip)]
+    templates: BTreeMap<String, String>,
+
+    /// Abort on malformed input, including statement dates and amounts that can't be read,
+    /// instead of skipping it with a warning, and never clear transactions whose postings
+    /// don't balance
+    #[arg(long)]
+    strict: bool,
+
+    /// Skip statement records whose amount isn't a well-formed number (such as `50,00.0`), or
+    /// with --strict stop at the first one
+    #[arg(long)]
+    strict_amount_format: bool,
+
+    /// Drop repeated CSV records (same date, description and amount) without asking
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Copy the ledger to `<ledger>.bak` before writing, so writing over it with --output is safe
+    #[arg(long)]
+    backup: bool,
+
+    /// Insert new entries among the existing transactions by date instead of appending them
+    #[arg(long, visible_alias = "merge-output")]
+    sorted_insert: bool,
+
+    /// Strip trailing whitespace from the lines this run writes, leaving the rest of the ledger as it is
+    #[arg(long)]
+    trim_trailing: bool,
+
+    /// Run the full matching flow but only preview the changes instead of writing them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Only count how many records match exactly, ambiguously or not at all, listing the
+    /// unmatched ones, without prompting or writing anything
+    #[arg(long, visible_alias = "preflight", conflicts_with_all = ["dry_run", "resume"])]
+    count: bool,
+
+    /// Review a day at a time: each date's records are shown with the open transactions around
+    /// it, and several can be cleared from one list
+    #[arg(long)]
+    group_by_day: bool,
+
+    /// Pass over records that match nothing, then list them together once the rest are done, to
+    /// be ignored at once or given the usual menu one by one
+    #[arg(long, conflicts_with = "group_by_day")]
+    interactive_edit_unmatched: bool,
+
+    /// Clear a record's only match without asking when it is also an exact one, printing just
+    /// `✓ cleared: PAYEE`; records with several matches or none are asked about as usual
+    #[arg(long)]
+    quiet_matches: bool,
+
+    /// Once the output is written, ask hledger for its cleared balance (of --account, if given)
+    /// and print it, compared with --closing-balance when that is given; skipped with a notice
+    /// if hledger isn't installed
+    #[arg(long)]
+    verify_with_hledger: bool,
+
+    /// Under --auto, search for every record's matches up front on N threads (0 for one per
+    /// CPU); the records are still cleared in order, with the same results as without it
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Show why one record did or didn't match each open transaction, field by field, then exit
+    /// without writing; give its number in the run (as in `[3/40]`) or text from its description
+    #[arg(long, value_name = "ROW|TEXT", conflicts_with_all = ["count", "resume"])]
+    explain: Option<String>,
+
+    /// Only process the first N statement records (after --since/--until, duplicates and
+    /// --sort-csv), then write the output as usual; handy with --dry-run for trying out rules
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Tag each transaction marked with `; reconciled: DATE`, as an audit trail in the ledger
+    #[arg(long)]
+    stamp_cleared: bool,
+
+    /// Date written by --stamp-cleared (YYYY-MM-DD) [default: today]
+    #[arg(long, requires = "stamp_cleared")]
+    stamp_date: Option<NaiveDate>,
+
+    /// Start the output with `; reconciled DATE from CSV`, for a reviewed copy kept apart from
+    /// the ledger; refuses to write over the ledger itself
+    #[arg(long, conflicts_with = "in_place")]
+    annotate_header: bool,
+
+    /// Save progress to `<output>.checkpoint` after this many decisions (0 to never), so an
+    /// interrupted run can be picked up with --resume
+    #[arg(long, default_value_t = 10, value_name = "N")]
+    checkpoint_every: usize,
+
+    /// Pick up an interrupted run from its checkpoint instead of starting over
+    #[arg(long)]
+    resume: bool,
+
+    /// Describe the columns of the CSV files and suggest a config profile, without reconciling
+    #[arg(long)]
+    inspect: bool,
+
+    /// Also write the end-of-run summary to this file
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Write a JSON log of every decision made to this file
+    #[arg(long)]
+    json_log: Option<String>,
+
+    /// Write the CSV rows that matched nothing and were not added to this file, under their
+    /// original header, so they can be looked into and reconciled again later
+    #[arg(long, value_name = "PATH")]
+    rejects: Option<String>,
+
+    /// Write just the transactions this run cleared to this file, as the updated ledger has
+    /// them, so `hledger -f PATH print` and balance reports can check the run on its own
+    #[arg(long, value_name = "PATH")]
+    print_cleared: Option<String>,
+
+    /// Write every statement row to this file with two more columns: its status (cleared,
+    /// added, matched or unmatched) and the ledger transaction behind it
+    #[arg(long, value_name = "PATH")]
+    csv_out: Option<String>,
+
+    /// Leave the ledger as it is and only write the statement annotated with each record's
+    /// status to --csv-out, for when the spreadsheet is the record kept
+    #[arg(long, requires = "csv_out")]
+    reverse_match: bool,
+
+    /// After the run, fail unless the cleared postings to ACCOUNT (and its subaccounts) sum to
+    /// AMOUNT, the statement's ending balance, within --amount-tolerance (repeatable)
+    #[arg(long = "assert-balance", value_name = "ACCOUNT=AMOUNT")]
+    assert_balances: Vec<String>,
+
+    /// The --account's cleared balance when the statement period began; with --closing-balance,
+    /// the run fails unless it plus what the run clears comes to the closing balance
+    #[arg(long, value_name = "AMOUNT", requires_all = ["closing_balance", "account"])]
+    opening_balance: Option<String>,
+
+    /// The --account's balance at the end of the statement period, checked against
+    /// --opening-balance plus what the run clears, within --amount-tolerance
+    #[arg(long, value_name = "AMOUNT", requires = "opening_balance")]
+    closing_balance: Option<String>,
+
+    /// Extra choices for unmatched records, only read from the config file
+    #[arg(skip)]
+    actions: Vec<MenuAction>,
+
+    /// Also log why each candidate was accepted or rejected
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only print errors and the final summary
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Print messages without colors, as when NO_COLOR is set or stderr isn't a terminal
+    #[arg(long)]
+    no_color: bool,
+
+    /// Config file to read settings from [default: ./ledger-reconcile.toml, then ~/.config/ledger-reconcile.toml]
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Apply the settings from this `[profile.<name>]` section of the config file
+    #[arg(short, long)]
+    profile: Option<String>,
+}
+
+impl Cli {
+    /// Read the config file, if there is one, and take every setting not given
+    /// on the command line from it.
+    fn load_config(&mut self, matches: &ArgMatches) -> io::Result<()> {
+        let Some(path) = config::find_config_file(self.config.as_deref()) else {
+            return match &self.profile {
+                Some(_) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "--profile was given but no config file was found",
+                )),
+                None => Ok(()),
+            };
+        };
+
+        let path = path.to_string_lossy();
+        let settings = Config::load(&path)?
+            .resolve(self.profile.as_deref())
+            .map_err(|message| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message))
+            })?;
+        self.apply_settings(matches, settings);
+        Ok(())
+    }
+
+    /// Overwrite the fields that were not given on the command line with the
+    /// values in `settings`.
+    fn apply_settings(&mut self, matches: &ArgMatches, settings: Settings) {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        macro_rules! fill {
+            ($($field:ident),*; optional $($optional:ident),*) => {
+                $(
+                    if let (false, Some(value)) = (given(stringify!($field)), settings.$field) {
+                        self.$field = value;
+                    }
+                )*
+                $(
+                    if let (false, Some(value)) = (given(stringify!($optional)), settings.$optional) {
+                        self.$optional = Some(value);
+                    }
+                )*
+            };
         }
-        println!("-------------------------------");
 
-        let mut options: Vec<&str> = matches.iter().map(|(_, block)| block.as_str()).collect();
-        options.push("Ignore this line");
+        fill!(
+            syntax, output, in_place, date_col, desc_col, debit_sign, amount_tolerance, no_header, reverse, date_window,
+            exclude_accounts, bank_account_prefixes, ignore_descs, auto, yes, mark, include_pending, cleared_markers, posting_level,
+            fuzzy, similarity, match_fields, case_sensitive, ascii_fold, strict,
+            strict_amount_format, dedupe, backup, match_context, invert_amount,
+            sorted_insert, trim_trailing, dry_run, force, stamp_cleared, checkpoint_every, assert_balances,
+            no_color, reverse_match, annotate_header, group_by_day, interactive_edit_unmatched, quiet_matches,
+            verify_with_hledger;
+            optional ledger, ignore_below, format, encoding, delimiter, sort_csv, id_col, account, ledger_filter,
+            commodity, csv_date_format, since, until, limit, stamp_date,
+            auto_accept_score, rules, payee_map, template, misc_account, asset_account,
+            report,
+            json_log, rejects, print_cleared, csv_out, opening_balance, closing_balance, threads
+        );
 
-        let selected = Select::new("Match a transaction:", options)
-            .prompt()
-            .unwrap_or_else(|_| "Ignore this line");
+        // There are no flags for the menu actions, named templates and
+        // normalizations, only the config file.
+        if let Some(actions) = settings.actions {
+            self.actions = actions;
+        }
+        if let Some(templates) = settings.templates {
+            self.templates = templates;
+        }
+        if let Some(normalize) = settings.normalize {
+            self.normalize = normalize;
+        }
 
-        if selected == "Ignore this line" {
-            println!("Skipped transaction.");
-            continue;
+        if !["decimal_sep", "thousands_sep", "locale"]
+            .into_iter()
+            .any(given)
+        {
+            self.decimal_sep = settings.decimal_sep;
+            self.thousands_sep = settings.thousands_sep;
+            self.locale = settings.locale;
         }
 
-        if let Some(&(selected_index, _)) =
-            matches.iter().find(|(_, block)| block.as_str() == selected)
+        // The amount columns are one choice, so a config value must not mix
+        // with a different layout picked on the command line. The same goes
+        // for the number notation and the verbosity.
+        if !["amount_col", "debit_col", "credit_col"]
+            .into_iter()
+            .any(given)
         {
-            let start_line = selected_index;
-            ledger_lines[start_line] = format!("* {}", ledger_lines[start_line].trim_start());
-            println!(
-                "Marked transaction as cleared: {}",
-                ledger_lines[start_line]
-            );
+            self.amount_col = settings.amount_col;
+            self.debit_col = settings.debit_col;
+            self.credit_col = settings.credit_col;
+        }
+        if !["verbose", "quiet"].into_iter().any(given) {
+            self.verbose = settings.verbose.unwrap_or(self.verbose);
+            self.quiet = settings.quiet.unwrap_or(self.quiet);
         }
     }
 
-    fs::write(output_path, ledger_lines.join("\n"))?;
-    println!("Updated ledger written to {}", output_path);
+    /// The most detailed log level to print.
+    fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            LevelFilter::Error
+        } else if self.verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        }
+    }
+}
 
-    Ok(())
+impl From<&Cli> for Options {
+    fn from(cli: &Cli) -> Self {
+        Options {
+            syntax: cli.syntax,
+            format: cli.format,
+            encoding: cli.encoding,
+            delimiter: cli.delimiter,
+            columns: ColumnMapping {
+                date: cli.date_col.clone(),
+                description: cli.desc_col.clone(),
+                amount: match (&cli.debit_col, &cli.credit_col) {
+                    (Some(debit), Some(credit)) => AmountColumns::Split {
+                        debit: debit.clone(),
+                        credit: credit.clone(),
+                        debit_sign: cli.debit_sign,
+                    },
+                    _ => AmountColumns::Single(
+                        cli.amount_col.clone().unwrap_or(ColumnSpec::Index(2)),
+                    ),
+                },
+                id: cli.id_col.clone(),
+            },
+            amount_tolerance: cli.amount_tolerance,
+            csv_date_format: cli.csv_date_format.clone(),
+            no_header: cli.no_header,
+            sort_csv: cli.sort_csv,
+            reverse: cli.reverse,
+            date_window: cli.date_window,
+            since: cli.since,
+            until: cli.until,
+            ignore_below: cli.ignore_below,
+            ignore_descs: cli.ignore_descs.clone(),
+            account: cli.account.clone(),
+            exclude_accounts: cli.exclude_accounts.clone(),
+            bank_account_prefixes: cli.bank_account_prefixes.clone(),
+            ledger_filter: cli.ledger_filter.clone(),
+            commodity: cli.commodity.clone(),
+            separators: cli.locale.map_or(
+                Separators {
+                    decimal: cli.decimal_sep,
+                    thousands: cli.thousands_sep,
+                },
+                Locale::separators,
+            ),
+            auto: cli.auto || cli.yes,
+            yes: cli.yes,
+            mark: cli.mark,
+            include_pending: cli.include_pending,
+            cleared_markers: cli.cleared_markers.clone(),
+            posting_level: cli.posting_level,
+            fuzzy: cli.fuzzy,
+            similarity: cli.similarity,
+            auto_accept_score: cli.auto_accept_score,
+            match_fields: cli.match_fields.clone(),
+            case_sensitive: cli.case_sensitive,
+            ascii_fold: cli.ascii_fold,
+            invert_amount: cli.invert_amount,
+            match_context: cli.match_context,
+            normalize: cli.normalize.clone(),
+            rules: cli.rules.clone(),
+            payee_map: cli.payee_map.clone(),
+            template: cli.template.clone(),
+            misc_account: cli.misc_account.clone(),
+            asset_account: cli.asset_account.clone(),
+            templates: cli.templates.clone(),
+            strict: cli.strict,
+            strict_amount_format: cli.strict_amount_format,
+            dedupe: cli.dedupe,
+            backup: cli.backup || cli.in_place,
+            dry_run: cli.dry_run,
+            count: cli.count,
+            explain: cli.explain.clone(),
+            group_by_day: cli.group_by_day,
+            interactive_edit_unmatched: cli.interactive_edit_unmatched,
+            quiet_matches: cli.quiet_matches,
+            verify_with_hledger: cli.verify_with_hledger,
+            threads: cli.threads,
+            limit: cli.limit,
+            sorted_insert: cli.sorted_insert,
+            trim_trailing: cli.trim_trailing,
+            checkpoint_every: cli.checkpoint_every,
+            resume: cli.resume,
+            report: cli.report.clone(),
+            json_log: cli.json_log.clone(),
+            rejects: cli.rejects.clone(),
+            csv_out: cli.csv_out.clone(),
+            reverse_match: cli.reverse_match,
+            print_cleared: cli.print_cleared.clone(),
+            stamp_cleared: cli.stamp_cleared.then(|| {
+                cli.stamp_date
+                    .unwrap_or_else(|| chrono::Local::now().date_naive())
+            }),
+            annotate_header: cli
+                .annotate_header
+                .then(|| chrono::Local::now().date_naive()),
+            assert_balances: cli.assert_balances.clone(),
+            opening_balance: cli.opening_balance.clone(),
+            closing_balance: cli.closing_balance.clone(),
+            actions: cli.actions.clone(),
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::Write;
+/// Send log messages to stderr without timestamps, honouring `RUST_LOG` if set.
+fn init_logging(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            Level::Error => writeln!(buf, "Error: {}", record.args()),
+            Level::Warn => writeln!(buf, "Warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .target(env_logger::Target::Pipe(Box::new(LogWriter)))
+        .init();
+}
 
-    #[test]
-    fn test_process_files() {
-        let ledger_path = "test_ledger.ledger";
-        let csv_path = "test_transactions.csv";
-        let output_path = "test_updated.ledger";
-
-        let mut ledger_file = File::create(ledger_path).unwrap();
-        writeln!(
-            ledger_file,
-            r#"
-2025-01-01 Groceries
-    Expenses:Food          $50.00
-    Assets:Bank           -$50.00
-
-2025-01-02 Rent
-    Expenses:Rent         $1000.00
-    Assets:Bank          -$1000.00
-"#
-        )
-        .unwrap();
+/// What to try next after `err`.
+fn hint(err: &ReconcileError) -> &'static str {
+    match err {
+        ReconcileError::Io { .. } => "Check that the path exists and that you may read or write it.",
+        ReconcileError::Csv { .. } => {
+            "Fix or delete that row, or run without --strict to skip malformed rows."
+        }
+        ReconcileError::ColumnNotFound { .. } => {
+            "Check --date-col, --desc-col, --amount-col and --id-col against the header; --inspect lists the columns."
+        }
+        ReconcileError::AmountParse { .. } => {
+            "Check that --amount-col points at the amounts and that --decimal-sep or --locale matches the file."
+        }
+        ReconcileError::DateParse { .. } => {
+            "Set --csv-date-format to the statement's date format, e.g. \"%d/%m/%Y\"."
+        }
+        ReconcileError::Rules { .. } => "Each rule must read `pattern => account`, with /regex/ patterns valid.",
+        ReconcileError::Template { .. } => {
+            "Templates may use {date}, {description}, {amount}, {-amount} and {account}."
+        }
+        ReconcileError::BalanceMismatch { .. } | ReconcileError::ClosingBalanceMismatch { .. } => {
+            "The ledger was still written. Look for statement records left unmatched or skipped (see --rejects), or transactions cleared by mistake."
+        }
+        ReconcileError::InvalidOptions(_) => "See --help for how the flags work together.",
+    }
+}
 
-        let mut csv_file = File::create(csv_path).unwrap();
-        writeln!(
-            csv_file,
-            r#"
-Date,Description,Amount
-2025-01-01,Groceries,$50.00
-2025-01-02,Rent,$1000.00
-"#
-        )
-        .unwrap();
+/// What to do instead of reconciling statements.
+#[derive(Subcommand)]
+enum Command {
+    /// Ask for a transaction and add it to the ledger, no statement needed; ledger flags such as
+    /// --ledger, --sorted-insert and --backup go before `add`
+    Add,
+}
 
-        process_files(ledger_path, csv_path, output_path).unwrap();
+// The exit statuses listed in `--help`; clap itself exits with 2 on a bad
+// command line.
+const EXIT_ERROR: i32 = 1;
+const EXIT_UNRECONCILED: i32 = 3;
+const EXIT_ABORTED: i32 = 4;
+
+/// The exit status for a run that finished: whether every record was dealt
+/// with, some were left unmatched or skipped, or the user stopped early.
+fn exit_status(report: &ReconcileReport) -> i32 {
+    if report.aborted {
+        EXIT_ABORTED
+    } else if report.unmatched > 0 || report.ignored > 0 {
+        EXIT_UNRECONCILED
+    } else {
+        0
+    }
+}
 
-        let updated_ledger = fs::read_to_string(output_path).unwrap();
-        assert!(updated_ledger.contains("* 2025-01-01 Groceries"));
-        assert!(updated_ledger.contains("* 2025-01-02 Rent"));
+/// Reads one of `--cleared-markers`.
+fn parse_marker(value: &str) -> Result<Status, String> {
+    Status::parse_marker(value)
+        .ok_or_else(|| format!("{:?} is not a status marker; use * or !", value))
+}
 
-        fs::remove_file(ledger_path).unwrap();
-        fs::remove_file(csv_path).unwrap();
-        fs::remove_file(output_path).unwrap();
+/// Reads `--delimiter`, accepting `\t` or `tab` for a tab.
+fn parse_delimiter(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (value, chars.next(), chars.next()) {
+        ("\\t" | "tab", _, _) => Ok('\t'),
+        (_, Some(delimiter), None) if delimiter.is_ascii() => Ok(delimiter),
+        _ => Err("expected a single ASCII character, or `\\t` for a tab".to_string()),
     }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = cli.load_config(&matches) {
+        eprintln!("Error loading config: {}", err);
+        std::process::exit(EXIT_ERROR);
+    }
+    init_logging(cli.log_level());
+    if cli.no_color {
+        ledger_reconcile::disable_colors();
+    }
+
+    if cli.inspect {
+        for csv_path in &cli.csv {
+            let profile = Path::new(csv_path)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|stem| *stem != "-")
+                .unwrap_or("bank")
+                .replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', "-");
+            let described = ledger_reconcile::read_statement(csv_path, cli.encoding)
+                .and_then(|content| {
+                    ledger_reconcile::inspect(csv_path, &profile, &content)
+                        .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+                })
+                .unwrap_or_else(|err| {
+                    eprintln!("Error inspecting {}: {}", csv_path, err);
+                    std::process::exit(EXIT_ERROR);
+                });
+            println!("{}", described);
+        }
+        return;
+    }
+    let options = Options::from(&cli);
 
     let ledger_path = cli
         .ledger
@@ -204,11 +776,168 @@ fn main() {
             eprintln!(
                 "Error: No ledger file specified and LEDGER_FILE environment variable is not set."
             );
-            std::process::exit(1);
+            std::process::exit(EXIT_ERROR);
         });
 
-    if let Err(err) = process_files(&ledger_path, &cli.csv, &cli.output) {
-        eprintln!("Error processing files: {}", err);
-        std::process::exit(1);
+    if let Some(Command::Add) = cli.command {
+        match ledger_reconcile::add_entry(&ledger_path, &options) {
+            Ok(Some(_)) => return,
+            Ok(None) => std::process::exit(EXIT_ABORTED),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                eprintln!("{}", hint(&err));
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    let output_path = if cli.in_place {
+        if ledger_path == "-" {
+            eprintln!("Error: --in-place cannot be used when the ledger is read from stdin.");
+            std::process::exit(EXIT_ERROR);
+        }
+        ledger_path.clone()
+    } else {
+        cli.output.clone()
+    };
+    let writes = !options.dry_run && !options.count && options.explain.is_none();
+    if writes
+        && !options.backup
+        && !cli.force
+        && ledger_reconcile::same_file(&ledger_path, &output_path)
+    {
+        let confirmed = io::stdin().is_terminal()
+            && inquire::Confirm::new(&format!(
+                "--output {} is the ledger itself and no backup will be taken. Overwrite it?",
+                output_path
+            ))
+            .with_default(false)
+            .prompt()
+            .unwrap_or(false);
+        if !confirmed {
+            eprintln!(
+                "Error: --output {} would overwrite the ledger without a backup.",
+                output_path
+            );
+            eprintln!(
+                "Pass --backup or --in-place to keep a copy, or --force to overwrite it anyway."
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+
+    let config = ReconcileConfig {
+        ledger: ledger_path,
+        statements: cli.csv,
+        output: output_path,
+        options,
+    };
+    match ledger_reconcile::reconcile(&config) {
+        Ok(report) => std::process::exit(exit_status(&report)),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            eprintln!("{}", hint(&err));
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_flags_override_config_settings() {
+        let settings = Config::parse(
+            "date-window = 5\nfuzzy = true\nledger = \"main.ledger\"\namount-col = \"Amount\"",
+        )
+        .unwrap()
+        .resolve(None)
+        .unwrap();
+        let matches = Cli::command()
+            .try_get_matches_from([
+                "ledger-reconcile",
+                "--csv",
+                "statement.csv",
+                "--date-window",
+                "2",
+                "--debit-col",
+                "3",
+                "--credit-col",
+                "4",
+            ])
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+
+        cli.apply_settings(&matches, settings);
+
+        assert_eq!(cli.date_window, 2);
+        assert!(cli.fuzzy);
+        assert_eq!(cli.ledger.as_deref(), Some("main.ledger"));
+        assert_eq!(cli.amount_col, None);
+        assert_eq!(cli.debit_col, Some(ColumnSpec::Index(3)));
+    }
+
+    #[test]
+    fn test_config_fills_the_date_range_limit_and_force() {
+        let settings = Config::parse(
+            "since = \"2025-01-01\"\nuntil = \"2025-01-31\"\nlimit = 10\nforce = true\nstamp-cleared = true\nstamp-date = \"2025-02-01\"",
+        )
+        .unwrap()
+        .resolve(None)
+        .unwrap();
+        let matches = Cli::command()
+            .try_get_matches_from(["ledger-reconcile", "--csv", "statement.csv", "--limit", "3"])
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+
+        cli.apply_settings(&matches, settings);
+
+        assert_eq!(cli.since, NaiveDate::from_ymd_opt(2025, 1, 1));
+        assert_eq!(cli.until, NaiveDate::from_ymd_opt(2025, 1, 31));
+        assert_eq!(cli.limit, Some(3));
+        assert!(cli.force);
+        assert_eq!(cli.stamp_date, NaiveDate::from_ymd_opt(2025, 2, 1));
+    }
+
+    #[test]
+    fn test_add_needs_no_statement() {
+        let cli = Cli::try_parse_from(["ledger-reconcile", "-l", "main.ledger", "--backup", "add"])
+            .unwrap();
+        assert!(matches!(cli.command, Some(Command::Add)));
+        assert!(cli.csv.is_empty());
+        assert!(Cli::try_parse_from(["ledger-reconcile", "-l", "main.ledger"]).is_err());
+    }
+
+    #[test]
+    fn test_verbosity_flags_pick_the_log_level() {
+        let level = |args: &[&str]| {
+            let args = ["ledger-reconcile", "--csv", "statement.csv"]
+                .iter()
+                .chain(args);
+            Cli::try_parse_from(args).map(|cli| cli.log_level())
+        };
+
+        assert_eq!(level(&[]).unwrap(), LevelFilter::Info);
+        assert_eq!(level(&["-v"]).unwrap(), LevelFilter::Debug);
+        assert_eq!(level(&["--quiet"]).unwrap(), LevelFilter::Error);
+        assert!(level(&["-v", "-q"]).is_err());
+    }
+
+    #[test]
+    fn test_exit_status_reflects_what_was_left() {
+        let report = |ignored, unmatched, aborted| ReconcileReport {
+            cleared: 2,
+            ignored,
+            added: 1,
+            unmatched,
+            aborted,
+            ledger: String::new(),
+        };
+
+        assert_eq!(exit_status(&report(0, 0, false)), 0);
+        assert_eq!(exit_status(&report(1, 0, false)), EXIT_UNRECONCILED);
+        assert_eq!(exit_status(&report(0, 3, false)), EXIT_UNRECONCILED);
+        assert_eq!(exit_status(&report(0, 3, true)), EXIT_ABORTED);
     }
 }
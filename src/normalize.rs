@@ -0,0 +1,150 @@
+//! Regex substitutions that clean up statement fields before matching.
+//!
+//! Each `[[normalize]]` table in the config file rewrites one field of every
+//! statement record, and with `ledger = true` the ledger payees as well, so
+//! bank boilerplate doesn't get in the way of comparisons:
+//!
+//! ```toml
+//! [[normalize]]
+//! pattern = '^POS DEBIT\s+'
+//! replace = ""
+//! ledger = true
+//! ```
+//!
+//! Patterns are matched case-insensitively and may refer to their groups in
+//! `replace` as `$1` or `${name}`. They are applied in order.
+
+use log::debug;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+/// The statement field a substitution rewrites.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementField {
+    #[default]
+    Description,
+    Amount,
+}
+
+/// One substitution as written in the config file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Normalization {
+    #[serde(default)]
+    pub field: StatementField,
+    pub pattern: String,
+    #[serde(default)]
+    pub replace: String,
+    /// Also rewrite the ledger's payees; only meaningful for descriptions.
+    #[serde(default)]
+    pub ledger: bool,
+}
+
+/// The compiled substitutions.
+#[derive(Clone, Debug, Default)]
+pub struct Normalizer {
+    substitutions: Vec<(Regex, Normalization)>,
+}
+
+impl Normalizer {
+    /// Compiles every pattern, reporting the first that isn't a valid regex.
+    pub fn new(normalizations: &[Normalization]) -> Result<Normalizer, String> {
+        let substitutions = normalizations
+            .iter()
+            .map(|normalization| {
+                RegexBuilder::new(&normalization.pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map(|regex| (regex, normalization.clone()))
+                    .map_err(|error| {
+                        format!("normalize pattern {:?}: {}", normalization.pattern, error)
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Normalizer { substitutions })
+    }
+
+    /// `value` of a statement record's `field` with every substitution for
+    /// that field applied, trimmed.
+    pub fn statement(&self, field: StatementField, value: &str) -> String {
+        self.apply(field, value, |_| true)
+    }
+
+    /// A ledger payee with the description substitutions marked `ledger`
+    /// applied, trimmed.
+    pub fn ledger(&self, description: &str) -> String {
+        self.apply(StatementField::Description, description, |normalization| {
+            normalization.ledger
+        })
+    }
+
+    fn apply(
+        &self,
+        field: StatementField,
+        value: &str,
+        wanted: impl Fn(&Normalization) -> bool,
+    ) -> String {
+        let mut rewritten = value.to_string();
+        for (regex, normalization) in &self.substitutions {
+            if normalization.field == field && wanted(normalization) {
+                rewritten = regex
+                    .replace_all(&rewritten, normalization.replace.as_str())
+                    .into_owned();
+            }
+        }
+        let rewritten = rewritten.trim().to_string();
+        if rewritten != value.trim() {
+            debug!("Normalized {:?} to {:?}", value, rewritten);
+        }
+        rewritten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(pattern: &str, replace: &str, ledger: bool) -> Normalization {
+        Normalization {
+            field: StatementField::Description,
+            pattern: pattern.to_string(),
+            replace: replace.to_string(),
+            ledger,
+        }
+    }
+
+    #[test]
+    fn test_substitutions_apply_in_order_to_their_field() {
+        let normalizer = Normalizer::new(&[
+            description(r"^pos debit\s+", "", true),
+            description(r"\s{2,}", " ", false),
+            Normalization {
+                field: StatementField::Amount,
+                pattern: r"\s*USD$".to_string(),
+                replace: String::new(),
+                ledger: false,
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(
+            normalizer.statement(StatementField::Description, "POS DEBIT   Blue    Bottle   "),
+            "Blue Bottle"
+        );
+        assert_eq!(
+            normalizer.statement(StatementField::Amount, "-4.50 USD"),
+            "-4.50"
+        );
+        assert_eq!(
+            normalizer.ledger("POS DEBIT Blue    Bottle"),
+            "Blue    Bottle"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_reported() {
+        let error = Normalizer::new(&[description("(", "", false)]).unwrap_err();
+        assert!(error.contains("\"(\""), "{}", error);
+    }
+}
@@ -0,0 +1,169 @@
+//! Minimal OFX/QFX statement parser.
+//!
+//! Handles both the SGML flavour (OFX 1.x, unclosed leaf tags) and the XML
+//! flavour (OFX 2.x) by scanning for `<STMTTRN>` blocks and reading the leaf
+//! values inside them.
+
+/// Returns true if the content looks like an OFX/QFX document.
+pub fn looks_like_ofx(content: &str) -> bool {
+    let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+    let head: String = trimmed.chars().take(16).collect::<String>().to_uppercase();
+
+    head.starts_with("OFXHEADER")
+        || head.starts_with("<OFX>")
+        || (head.starts_with("<?XML") && trimmed.to_uppercase().contains("<OFX>"))
+}
+
+/// Converts every `<STMTTRN>` block into a `[date, description, amount, id]` record.
+///
+/// `DTPOSTED` is reduced to an ISO `YYYY-MM-DD` date, `NAME` is used as the
+/// description (falling back to `MEMO`), and `TRNAMT` and `FITID` are passed
+/// through as-is. Blocks missing a date or amount are skipped.
+pub fn parse_transactions(content: &str) -> Vec<Vec<String>> {
+    let upper = content.to_ascii_uppercase();
+    let mut records = Vec::new();
+    let mut cursor = 0;
+
+    while let Some(found) = upper[cursor..].find("<STMTTRN>") {
+        let start = cursor + found + "<STMTTRN>".len();
+        let end = upper[start..]
+            .find("</STMTTRN>")
+            .or_else(|| upper[start..].find("<STMTTRN>"))
+            .map(|offset| start + offset)
+            .unwrap_or(content.len());
+
+        let block = &content[start..end];
+        cursor = end;
+
+        let date = tag_value(block, "DTPOSTED").and_then(|raw| ofx_date(&raw));
+        let description = tag_value(block, "NAME")
+            .or_else(|| tag_value(block, "MEMO"))
+            .unwrap_or_default();
+        let amount = tag_value(block, "TRNAMT");
+        let id = tag_value(block, "FITID").unwrap_or_default();
+
+        if let (Some(date), Some(amount)) = (date, amount) {
+            records.push(vec![date, description, amount, id]);
+        }
+    }
+
+    records
+}
+
+/// Reads the value of a leaf tag, stopping at the next tag or line break.
+fn tag_value(block: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let position = block.to_ascii_uppercase().find(&open)?;
+    let rest = &block[position + open.len()..];
+    let value = rest.split(['<', '\n', '\r']).next().unwrap_or("").trim();
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(decode_entities(value))
+    }
+}
+
+/// OFX dates are `YYYYMMDD[HHMMSS[.XXX]][TZ]`; only the date part matters here.
+fn ofx_date(raw: &str) -> Option<String> {
+    let digits: String = raw.chars().take(8).collect();
+    if digits.len() != 8 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(format!(
+        "{}-{}-{}",
+        &digits[0..4],
+        &digits[4..6],
+        &digits[6..8]
+    ))
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20250101120000.000[-5:EST]
+<TRNAMT>-50.00
+<FITID>1001
+<NAME>Groceries
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20250102
+<TRNAMT>-1000.00
+<FITID>1002
+<MEMO>Rent &amp; fees
+</STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+    #[test]
+    fn test_parse_sgml_transactions() {
+        let records = parse_transactions(SGML);
+
+        assert_eq!(
+            records,
+            vec![
+                vec![
+                    "2025-01-01".to_string(),
+                    "Groceries".to_string(),
+                    "-50.00".to_string(),
+                    "1001".to_string()
+                ],
+                vec![
+                    "2025-01-02".to_string(),
+                    "Rent & fees".to_string(),
+                    "-1000.00".to_string(),
+                    "1002".to_string()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_xml_transactions() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220"?>
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20250315</DTPOSTED><TRNAMT>12.34</TRNAMT><NAME>Refund</NAME><MEMO>Store</MEMO></STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>"#;
+
+        assert_eq!(
+            parse_transactions(xml),
+            vec![vec![
+                "2025-03-15".to_string(),
+                "Refund".to_string(),
+                "12.34".to_string(),
+                String::new()
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_looks_like_ofx() {
+        assert!(looks_like_ofx(SGML));
+        assert!(looks_like_ofx("  \n<OFX>\n</OFX>"));
+        assert!(!looks_like_ofx(
+            "Date,Description,Amount\n2025-01-01,Groceries,$50.00"
+        ));
+    }
+}
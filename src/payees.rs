@@ -0,0 +1,86 @@
+//! Translating the bank's descriptions to the payees the ledger uses.
+//!
+//! A payee map holds one `bank text => Ledger Payee` entry per line. The bank
+//! text is a pattern as in a rules file: a case-insensitive substring, or a
+//! regular expression between slashes. Blank lines and lines starting with
+//! `#` or `;` are ignored.
+
+use crate::rules::Pattern;
+use crate::ReconcileError;
+use std::fs;
+
+/// An ordered list of payee entries; the first that matches wins.
+#[derive(Clone, Debug, Default)]
+pub struct PayeeMap {
+    entries: Vec<(Pattern, String)>,
+}
+
+impl PayeeMap {
+    /// Reads and parses a payee map.
+    pub fn load(path: &str) -> Result<PayeeMap, ReconcileError> {
+        let content = fs::read_to_string(path).map_err(ReconcileError::io(path))?;
+        PayeeMap::parse(&content).map_err(|message| ReconcileError::Rules {
+            path: path.to_string(),
+            message,
+        })
+    }
+
+    /// Parses payee entries from text, reporting the first malformed line.
+    pub fn parse(content: &str) -> Result<PayeeMap, String> {
+        let mut entries = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let (pattern, payee) = line
+                .split_once("=>")
+                .map(|(pattern, payee)| (pattern.trim(), payee.trim()))
+                .filter(|(pattern, payee)| !pattern.is_empty() && !payee.is_empty())
+                .ok_or_else(|| format!("line {}: expected `bank text => payee`", index + 1))?;
+            let pattern = Pattern::parse(pattern)
+                .map_err(|error| format!("line {}: {}", index + 1, error))?;
+            entries.push((pattern, payee.to_string()));
+        }
+
+        Ok(PayeeMap { entries })
+    }
+
+    /// The ledger payee for a bank description, if an entry matches it.
+    pub fn payee_for(&self, description: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(pattern, _)| pattern.matches(description))
+            .map(|(_, payee)| payee.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_matching_entry_gives_the_payee() {
+        let payees = PayeeMap::parse(
+            "# card processors
+/^sq \\*blue/ => Blue Bottle
+amzn mktp => Amazon
+amzn => Amazon Prime
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            payees.payee_for("SQ *BLUE BOTTLE 0412"),
+            Some("Blue Bottle")
+        );
+        assert_eq!(payees.payee_for("AMZN Mktp US*2K4"), Some("Amazon"));
+        assert_eq!(payees.payee_for("AMZN DIGITAL"), Some("Amazon Prime"));
+        assert_eq!(payees.payee_for("Rent"), None);
+
+        let error = PayeeMap::parse("amzn => Amazon\nAmazon\n").unwrap_err();
+        assert!(error.starts_with("line 2"));
+    }
+}
@@ -0,0 +1,113 @@
+//! A progress bar for `--auto` runs, kept intact while log lines and prompts
+//! are printed around it.
+
+use crate::prompt::{Prompter, Validator};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The bar being drawn, if any, so other output can make room for it.
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+fn active() -> MutexGuard<'static, Option<ProgressBar>> {
+    ACTIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `f` with the bar hidden, so it can use the terminal freely.
+fn suspend<T>(f: impl FnOnce() -> T) -> T {
+    // Taken out rather than borrowed, so log lines written by `f` don't try to
+    // suspend the bar a second time.
+    let bar = active().take();
+    match bar {
+        Some(bar) => {
+            let result = bar.suspend(f);
+            *active() = Some(bar);
+            result
+        }
+        None => f(),
+    }
+}
+
+/// A bar over the statement records, drawn on stderr and cleared when dropped.
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    /// Starts a bar for `total` records. It is only drawn if `visible` and
+    /// stderr is a terminal.
+    pub fn start(total: usize, visible: bool) -> Progress {
+        let bar = if visible {
+            ProgressBar::new(total as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+        if let Ok(style) = ProgressStyle::with_template("{wide_bar} {pos}/{len} records") {
+            bar.set_style(style);
+        }
+        if !bar.is_hidden() {
+            *active() = Some(bar.clone());
+        }
+        Progress { bar }
+    }
+
+    /// Moves the bar to `done` records, which goes back after an undo.
+    pub fn set_position(&self, done: usize) {
+        self.bar.set_position(done as u64);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if !self.bar.is_hidden() {
+            active().take();
+        }
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Writes log output to stderr, lifting the bar out of the way first.
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match active().as_ref() {
+            Some(bar) => bar.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Hides the bar while the wrapped prompter asks its questions.
+pub struct Suspending<'a>(pub &'a mut dyn Prompter);
+
+impl Prompter for Suspending<'_> {
+    fn select(&mut self, message: &str, choices: Vec<String>) -> Option<usize> {
+        suspend(|| self.0.select(message, choices))
+    }
+
+    fn multi_select(
+        &mut self,
+        message: &str,
+        choices: Vec<String>,
+        defaults: &[usize],
+    ) -> Option<Vec<usize>> {
+        suspend(|| self.0.multi_select(message, choices, defaults))
+    }
+
+    fn edit(&mut self, message: &str, initial: &str, validate: Validator) -> Option<String> {
+        suspend(|| self.0.edit(message, initial, validate))
+    }
+
+    fn account(&mut self, message: &str, initial: &str, accounts: &[String]) -> Option<String> {
+        suspend(|| self.0.account(message, initial, accounts))
+    }
+
+    fn text(&mut self, message: &str, initial: &str) -> Option<String> {
+        suspend(|| self.0.text(message, initial))
+    }
+}
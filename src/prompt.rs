@@ -0,0 +1,221 @@
+//! Asking the user to choose between options.
+
+use inquire::autocompletion::{Autocomplete, Replacement};
+use inquire::validator::Validation;
+use inquire::{CustomUserError, Editor, MultiSelect, Select, Text};
+use std::rc::Rc;
+
+/// Checks edited text, describing what is wrong with it.
+pub type Validator = Rc<dyn Fn(&str) -> Result<(), String>>;
+
+/// Something that can ask the user to pick one of several choices.
+pub trait Prompter {
+    /// Shows `choices` under `message` and returns the index of the one picked,
+    /// or `None` if the prompt was cancelled.
+    fn select(&mut self, message: &str, choices: Vec<String>) -> Option<usize>;
+
+    /// Shows `choices` under `message` with those in `defaults` ticked, and
+    /// returns the indices of the ones picked, or `None` if cancelled.
+    fn multi_select(
+        &mut self,
+        message: &str,
+        choices: Vec<String>,
+        defaults: &[usize],
+    ) -> Option<Vec<usize>>;
+
+    /// Lets the user edit `initial`, asking again until `validate` accepts the
+    /// text. Returns `None` if the edit was cancelled.
+    fn edit(&mut self, message: &str, initial: &str, validate: Validator) -> Option<String>;
+
+    /// Asks for an account name, starting from `initial` and suggesting the
+    /// `accounts` that contain what has been typed. Returns `None` if cancelled.
+    fn account(&mut self, message: &str, initial: &str, accounts: &[String]) -> Option<String>;
+
+    /// Asks for a line of text, starting from `initial`. Returns `None` if
+    /// cancelled.
+    fn text(&mut self, message: &str, initial: &str) -> Option<String>;
+}
+
+/// Suggests the known account names containing the typed text, ignoring case.
+#[derive(Clone)]
+struct Accounts(Vec<String>);
+
+impl Autocomplete for Accounts {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        let typed = input.to_lowercase();
+        Ok(self
+            .0
+            .iter()
+            .filter(|account| account.to_lowercase().contains(&typed))
+            .cloned()
+            .collect())
+    }
+
+    /// Tab takes the highlighted suggestion, or else extends the input to the
+    /// longest prefix every account starting with it shares.
+    fn get_completion(
+        &mut self,
+        input: &str,
+        highlighted: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        if highlighted.is_some() {
+            return Ok(highlighted);
+        }
+        let mut starting = self.0.iter().filter(|account| account.starts_with(input));
+        let Some(first) = starting.next() else {
+            return Ok(None);
+        };
+        let common = starting.fold(first.as_str(), |common, account| {
+            let shared = common
+                .char_indices()
+                .zip(account.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(account.len()), |((index, _), _)| index);
+            &common[..shared]
+        });
+        Ok(Some(common.to_string()))
+    }
+}
+
+/// Prompts on the terminal with `inquire`.
+pub struct Terminal;
+
+impl Prompter for Terminal {
+    fn select(&mut self, message: &str, choices: Vec<String>) -> Option<usize> {
+        Select::new(message, choices)
+            .raw_prompt()
+            .ok()
+            .map(|choice| choice.index)
+    }
+
+    fn multi_select(
+        &mut self,
+        message: &str,
+        choices: Vec<String>,
+        defaults: &[usize],
+    ) -> Option<Vec<usize>> {
+        MultiSelect::new(message, choices)
+            .with_default(defaults)
+            .raw_prompt()
+            .ok()
+            .map(|picked| picked.into_iter().map(|choice| choice.index).collect())
+    }
+
+    fn edit(&mut self, message: &str, initial: &str, validate: Validator) -> Option<String> {
+        Editor::new(message)
+            .with_predefined_text(initial)
+            .with_file_extension(".ledger")
+            .with_validator(move |text: &str| {
+                Ok(match validate(text) {
+                    Ok(()) => Validation::Valid,
+                    Err(message) => Validation::Invalid(message.into()),
+                })
+            })
+            .prompt()
+            .ok()
+    }
+
+    fn account(&mut self, message: &str, initial: &str, accounts: &[String]) -> Option<String> {
+        Text::new(message)
+            .with_initial_value(initial)
+            .with_autocomplete(Accounts(accounts.to_vec()))
+            .prompt()
+            .ok()
+    }
+
+    fn text(&mut self, message: &str, initial: &str) -> Option<String> {
+        Text::new(message).with_initial_value(initial).prompt().ok()
+    }
+}
+
+/// An answer that cancels the prompt, as Esc would.
+#[cfg(test)]
+pub const ESC: &str = "\u{1b}";
+
+/// Answers prompts from a fixed list, for tests.
+#[cfg(test)]
+pub struct Scripted {
+    answers: std::collections::VecDeque<String>,
+}
+
+#[cfg(test)]
+impl Scripted {
+    /// Each answer picks the first choice that starts with it; [`ESC`] and
+    /// prompts beyond the end of the list are cancelled.
+    pub fn new(answers: &[&str]) -> Scripted {
+        Scripted {
+            answers: answers.iter().map(|answer| answer.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Prompter for Scripted {
+    fn select(&mut self, message: &str, choices: Vec<String>) -> Option<usize> {
+        let answer = self.answers.pop_front().filter(|answer| answer != ESC)?;
+        let index = choices
+            .iter()
+            .position(|choice| choice.starts_with(&answer));
+        assert!(
+            index.is_some(),
+            "no choice starting with {:?} for {:?} in {:?}",
+            answer,
+            message,
+            choices
+        );
+        index
+    }
+
+    /// An empty answer keeps the defaults; otherwise each comma-separated part
+    /// picks the first choice that starts with it.
+    fn multi_select(
+        &mut self,
+        message: &str,
+        choices: Vec<String>,
+        defaults: &[usize],
+    ) -> Option<Vec<usize>> {
+        let answer = self.answers.pop_front().filter(|answer| answer != ESC)?;
+        if answer.is_empty() {
+            return Some(defaults.to_vec());
+        }
+        let picked = answer
+            .split(',')
+            .map(|part| {
+                choices
+                    .iter()
+                    .position(|choice| choice.starts_with(part))
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "no choice starting with {:?} for {:?} in {:?}",
+                            part, message, choices
+                        )
+                    })
+            })
+            .collect();
+        Some(picked)
+    }
+
+    /// Takes answers until one passes `validate`, as the editor would re-open.
+    fn edit(&mut self, _message: &str, _initial: &str, validate: Validator) -> Option<String> {
+        loop {
+            let answer = self.answers.pop_front()?;
+            if validate(&answer).is_ok() {
+                return Some(answer);
+            }
+        }
+    }
+
+    fn account(&mut self, _message: &str, _initial: &str, _accounts: &[String]) -> Option<String> {
+        self.answers.pop_front().filter(|answer| answer != ESC)
+    }
+
+    /// An empty answer keeps `initial`, as Enter would.
+    fn text(&mut self, _message: &str, initial: &str) -> Option<String> {
+        let answer = self.answers.pop_front().filter(|answer| answer != ESC)?;
+        Some(if answer.is_empty() {
+            initial.to_string()
+        } else {
+            answer
+        })
+    }
+}
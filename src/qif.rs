@@ -0,0 +1,185 @@
+//! Minimal QIF statement parser.
+//!
+//! A QIF file is a sequence of records, one field per line, each field tagged by
+//! its first character and each record ended by a `^` line. Only the fields
+//! needed for matching are read; `!Type:` headers and `!Option` lines are
+//! skipped.
+
+/// Returns true if the content looks like a QIF document.
+pub fn looks_like_qif(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("!type:")
+}
+
+/// Converts every record into a `[date, description, amount, id]` record.
+///
+/// The date comes from `D`, with its year written out in full (see
+/// [`full_year`]). The description is the payee (`P`), falling back to the memo
+/// (`M`); the amount is `T` (or `U`); and the check or reference number `N` is
+/// the ID. Records missing a date or
+/// amount are skipped.
+pub fn parse_transactions(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut fields = Fields::default();
+
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim_end();
+        let mut chars = line.chars();
+        let Some(tag) = chars.next() else {
+            continue;
+        };
+        let value = chars.as_str().trim();
+
+        match tag {
+            '^' => {
+                if let Some(record) = std::mem::take(&mut fields).into_record() {
+                    records.push(record);
+                }
+            }
+            'D' => fields.date = Some(full_year(value)),
+            'T' => fields.amount = Some(value.to_string()),
+            'U' => {
+                fields.amount.get_or_insert_with(|| value.to_string());
+            }
+            'P' => fields.payee = Some(value.to_string()),
+            'M' => fields.memo = Some(value.to_string()),
+            'N' => fields.number = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    // The last record may lack its closing `^`.
+    if let Some(record) = fields.into_record() {
+        records.push(record);
+    }
+
+    records
+}
+
+/// Rewrites a QIF date with a four-digit year, so `1/ 5'25` reads as `1/5/2025`.
+///
+/// Quicken pads dates with spaces and writes years from 2000 on after a `'`.
+/// Any other two-digit year is read as chrono's `%y` would, so `01/05/99` is
+/// 1999 and `01/05/25` is 2025. The day and month are left in place.
+fn full_year(raw: &str) -> String {
+    let date: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    let with_apostrophe = date.contains('\'');
+    let date = date.replace('\'', "/");
+
+    let Some(split) = date.rfind(['/', '-', '.']) else {
+        return date;
+    };
+    let year = &date[split + 1..];
+    match year.parse::<u32>() {
+        Ok(short) if year.len() == 2 => {
+            let century = if with_apostrophe || short < 70 {
+                2000
+            } else {
+                1900
+            };
+            format!("{}{}", &date[..=split], century + short)
+        }
+        _ => date,
+    }
+}
+
+/// The fields read so far for the current record.
+#[derive(Default)]
+struct Fields {
+    date: Option<String>,
+    amount: Option<String>,
+    payee: Option<String>,
+    memo: Option<String>,
+    number: Option<String>,
+}
+
+impl Fields {
+    fn into_record(self) -> Option<Vec<String>> {
+        let date = self.date.filter(|date| !date.is_empty())?;
+        let amount = self.amount.filter(|amount| !amount.is_empty())?;
+        let description = self
+            .payee
+            .filter(|payee| !payee.is_empty())
+            .or(self.memo)
+            .unwrap_or_default();
+
+        Some(vec![
+            date,
+            description,
+            amount,
+            self.number.unwrap_or_default(),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QIF: &str = "!Type:Bank
+D01/02/2025
+T-50.00
+U-50.00
+CX
+N1001
+PGroceries
+MWeekly shop
+LFood:Groceries
+A123 Main St
+^
+D1/ 3'25
+T1,000.00
+MPayroll
+^
+D01/04/2025
+PNo amount
+^
+D01/05/2025
+U-12.34
+PCoffee
+";
+
+    #[test]
+    fn test_parse_transactions() {
+        assert_eq!(
+            parse_transactions(QIF),
+            vec![
+                vec![
+                    "01/02/2025".to_string(),
+                    "Groceries".to_string(),
+                    "-50.00".to_string(),
+                    "1001".to_string()
+                ],
+                vec![
+                    "1/3/2025".to_string(),
+                    "Payroll".to_string(),
+                    "1,000.00".to_string(),
+                    String::new()
+                ],
+                vec![
+                    "01/05/2025".to_string(),
+                    "Coffee".to_string(),
+                    "-12.34".to_string(),
+                    String::new()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_year() {
+        assert_eq!(full_year("1/ 5'25"), "1/5/2025");
+        assert_eq!(full_year("01/05/99"), "01/05/1999");
+        assert_eq!(full_year("01/05/25"), "01/05/2025");
+        assert_eq!(full_year("15.01.2025"), "15.01.2025");
+    }
+
+    #[test]
+    fn test_looks_like_qif() {
+        assert!(looks_like_qif(QIF));
+        assert!(looks_like_qif("\u{feff}!type:CCard\n"));
+        assert!(!looks_like_qif("Date,Description,Amount\n"));
+    }
+}
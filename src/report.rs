@@ -0,0 +1,362 @@
+//! Tallying what happened during a reconciliation run.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the `--json-log` layout, bumped whenever it changes incompatibly.
+pub const JSON_LOG_SCHEMA_VERSION: u32 = 1;
+
+/// The fields of a CSV record as they were matched.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementRecord {
+    pub date: String,
+    pub description: String,
+    pub amount: String,
+}
+
+/// A ledger transaction that was offered as a match.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CandidateRef {
+    /// 1-based line number of the transaction header.
+    pub line: usize,
+    pub header: String,
+}
+
+/// What was done with a CSV record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Cleared,
+    Ignored,
+    Added,
+    Unmatched,
+}
+
+/// The outcome for one CSV record.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decision {
+    /// 0-based position of the record among all the statement records of the
+    /// run, after any were filtered out or dropped as duplicates.
+    pub record_index: usize,
+    pub record: StatementRecord,
+    pub candidates: Vec<CandidateRef>,
+    pub action: Action,
+    /// 1-based line number of the cleared header or of the added entry.
+    pub line: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonLog<'a> {
+    schema_version: u32,
+    decisions: &'a [Decision],
+}
+
+/// An uncleared ledger transaction whose postings don't balance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnbalancedTransaction {
+    /// 1-based line number of the transaction header.
+    pub line: usize,
+    pub header: String,
+    pub problem: String,
+}
+
+/// Counts of the decisions made during a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Ledger transactions marked as cleared.
+    pub cleared: usize,
+    /// Of those, the ones cleared by `--auto` as a record's only exact match,
+    /// before looser matches were looked at.
+    pub exact_pass: usize,
+    /// CSV records that had matches but were skipped.
+    pub ignored: usize,
+    /// New transactions appended to the ledger.
+    pub added: usize,
+    /// CSV records with no ledger match that were not added.
+    pub unmatched: Vec<StatementRecord>,
+    /// Repeated CSV records dropped before matching.
+    pub duplicates_removed: usize,
+    /// CSV records left out by `--since`/`--until`.
+    pub filtered_out: usize,
+    /// CSV records skipped by `--ignore-below`/`--ignore-desc`.
+    pub noise: usize,
+    /// CSV records past `--limit`, which this run didn't look at.
+    pub beyond_limit: usize,
+    /// CSV records whose amount isn't a well-formed number, whether or not
+    /// `--strict-amount-format` dropped them.
+    pub malformed_amounts: usize,
+    /// CSV descriptions replaced by a `--payee-map` payee.
+    pub remapped: usize,
+    /// Ledger transactions found not to balance when the ledger was read.
+    pub unbalanced: Vec<UnbalancedTransaction>,
+    /// Every decision, in the order it was made.
+    pub decisions: Vec<Decision>,
+}
+
+impl Summary {
+    /// Counts a decision and keeps it for the JSON log.
+    pub fn record(&mut self, decision: Decision) {
+        match decision.action {
+            Action::Cleared => self.cleared += 1,
+            Action::Ignored => self.ignored += 1,
+            Action::Added => self.added += 1,
+            Action::Unmatched => self.unmatched.push(decision.record.clone()),
+        }
+        self.decisions.push(decision);
+    }
+
+    /// Serializes the decisions, with the schema version, as pretty-printed JSON.
+    pub fn to_json_log(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&JsonLog {
+            schema_version: JSON_LOG_SCHEMA_VERSION,
+            decisions: &self.decisions,
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- Reconciliation Summary ---")?;
+        writeln!(f, "Cleared:   {}", self.cleared)?;
+        if self.exact_pass > 0 {
+            writeln!(
+                f,
+                "  exact pass: {}, second pass: {}",
+                self.exact_pass,
+                self.cleared - self.exact_pass
+            )?;
+        }
+        writeln!(f, "Ignored:   {}", self.ignored)?;
+        writeln!(f, "Added:     {}", self.added)?;
+        writeln!(f, "Unmatched: {}", self.unmatched.len())?;
+        for record in &self.unmatched {
+            writeln!(
+                f,
+                "  {}  {}  {}",
+                record.date, record.description, record.amount
+            )?;
+        }
+        if self.duplicates_removed > 0 {
+            writeln!(f, "Duplicates removed: {}", self.duplicates_removed)?;
+        }
+        if self.filtered_out > 0 {
+            writeln!(f, "Outside date range: {}", self.filtered_out)?;
+        }
+        if self.noise > 0 {
+            writeln!(f, "Skipped as noise: {}", self.noise)?;
+        }
+        if self.malformed_amounts > 0 {
+            writeln!(f, "Malformed amounts: {}", self.malformed_amounts)?;
+        }
+        if self.remapped > 0 {
+            writeln!(f, "Remapped descriptions: {}", self.remapped)?;
+        }
+        if self.beyond_limit > 0 {
+            writeln!(
+                f,
+                "Not processed (--limit): {} (partial run)",
+                self.beyond_limit
+            )?;
+        }
+        if !self.unbalanced.is_empty() {
+            writeln!(f, "Unbalanced: {}", self.unbalanced.len())?;
+            for transaction in &self.unbalanced {
+                writeln!(
+                    f,
+                    "  line {}  {}: {}",
+                    transaction.line, transaction.header, transaction.problem
+                )?;
+            }
+        }
+        write!(f, "------------------------------")
+    }
+}
+
+/// How many statement records would match, tallied by `--count` without
+/// deciding anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preflight {
+    /// Records with exactly one ledger match.
+    pub exact: usize,
+    /// Records with more than one ledger match.
+    pub ambiguous: usize,
+    /// Records with no ledger match.
+    pub unmatched: Vec<StatementRecord>,
+}
+
+impl Preflight {
+    /// Tallies decisions by how many candidates each record had.
+    pub fn from_decisions(decisions: &[Decision]) -> Preflight {
+        let mut preflight = Preflight::default();
+        for decision in decisions {
+            match decision.candidates.len() {
+                0 => preflight.unmatched.push(decision.record.clone()),
+                1 => preflight.exact += 1,
+                _ => preflight.ambiguous += 1,
+            }
+        }
+        preflight
+    }
+}
+
+impl fmt::Display for Preflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- Match Preflight ---")?;
+        writeln!(f, "Exact:     {}", self.exact)?;
+        writeln!(f, "Ambiguous: {}", self.ambiguous)?;
+        writeln!(f, "Unmatched: {}", self.unmatched.len())?;
+        for record in &self.unmatched {
+            writeln!(
+                f,
+                "  {}  {}  {}",
+                record.date, record.description, record.amount
+            )?;
+        }
+        write!(f, "-----------------------")
+    }
+}
+
+/// Renders the changes between the original and updated ledger lines as a
+/// unified-diff-style listing: rewritten lines as `-`/`+` pairs and appended
+/// entries as `+` lines.
+pub fn preview_changes(original: &[String], updated: &[String]) -> String {
+    let mut preview = String::new();
+
+    for (index, (before, after)) in original.iter().zip(updated).enumerate() {
+        if before != after {
+            preview.push_str(&format!(
+                "@@ line {} @@\n- {}\n+ {}\n",
+                index + 1,
+                before,
+                after
+            ));
+        }
+    }
+
+    if updated.len() > original.len() {
+        preview.push_str("@@ appended @@\n");
+        for entry in &updated[original.len()..] {
+            for line in entry.lines() {
+                preview.push_str(&format!("+ {}\n", line));
+            }
+        }
+    }
+
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lists_unmatched_records() {
+        let summary = Summary {
+            cleared: 2,
+            exact_pass: 0,
+            ignored: 1,
+            added: 0,
+            unmatched: vec![StatementRecord {
+                date: "2025-01-05".to_string(),
+                description: "mystery charge".to_string(),
+                amount: "9.99".to_string(),
+            }],
+            duplicates_removed: 0,
+            filtered_out: 0,
+            noise: 0,
+            beyond_limit: 0,
+            malformed_amounts: 0,
+            remapped: 0,
+            unbalanced: Vec::new(),
+            decisions: Vec::new(),
+        };
+
+        let rendered = summary.to_string();
+
+        assert!(rendered.contains("Cleared:   2"));
+        assert!(rendered.contains("Ignored:   1"));
+        assert!(rendered.contains("Unmatched: 1"));
+        assert!(rendered.contains("  2025-01-05  mystery charge  9.99"));
+    }
+
+    #[test]
+    fn test_json_log_includes_schema_version() {
+        let mut summary = Summary::default();
+        summary.record(Decision {
+            record_index: 0,
+            record: StatementRecord {
+                date: "2025-01-02".to_string(),
+                description: "Rent".to_string(),
+                amount: "1000.00".to_string(),
+            },
+            candidates: vec![CandidateRef {
+                line: 4,
+                header: "2025-01-02 Rent".to_string(),
+            }],
+            action: Action::Cleared,
+            line: Some(4),
+        });
+
+        let log: serde_json::Value = serde_json::from_str(&summary.to_json_log().unwrap()).unwrap();
+
+        assert_eq!(summary.cleared, 1);
+        assert_eq!(log["schema_version"], JSON_LOG_SCHEMA_VERSION);
+        assert_eq!(log["decisions"][0]["action"], "cleared");
+        assert_eq!(log["decisions"][0]["candidates"][0]["line"], 4);
+        assert_eq!(log["decisions"][0]["record"]["description"], "Rent");
+    }
+
+    #[test]
+    fn test_preflight_tallies_candidates_per_record() {
+        let decision = |description: &str, candidates: usize| Decision {
+            record_index: 0,
+            record: StatementRecord {
+                date: "2025-01-02".to_string(),
+                description: description.to_string(),
+                amount: "4.00".to_string(),
+            },
+            candidates: (0..candidates)
+                .map(|line| CandidateRef {
+                    line,
+                    header: "2025-01-02 Coffee".to_string(),
+                })
+                .collect(),
+            action: Action::Ignored,
+            line: None,
+        };
+
+        let preflight = Preflight::from_decisions(&[
+            decision("coffee", 1),
+            decision("coffee", 2),
+            decision("tea", 1),
+            decision("mystery", 0),
+        ]);
+
+        assert_eq!(preflight.exact, 2);
+        assert_eq!(preflight.ambiguous, 1);
+        assert_eq!(preflight.unmatched.len(), 1);
+        assert!(preflight
+            .to_string()
+            .contains("Unmatched: 1\n  2025-01-02  mystery  4.00"));
+    }
+
+    #[test]
+    fn test_preview_changes() {
+        let original: Vec<String> = vec!["2025-01-01 Rent".into(), "    Assets:Bank".into()];
+        let mut updated = original.clone();
+        updated[0] = "* 2025-01-01 Rent".into();
+        updated.push("2025-01-02 Coffee\n    Expenses:Food  $4\n    Assets:Bank".into());
+
+        assert_eq!(
+            preview_changes(&original, &updated),
+            "@@ line 1 @@
+- 2025-01-01 Rent
++ * 2025-01-01 Rent
+@@ appended @@
++ 2025-01-02 Coffee
++     Expenses:Food  $4
++     Assets:Bank
+"
+        );
+    }
+}
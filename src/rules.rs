@@ -0,0 +1,176 @@
+//! Rules for categorizing CSV records that have no ledger match.
+//!
+//! A rules file holds one `pattern => account` rule per line. Patterns wrapped
+//! in slashes (`/^SQ \*/`) are regular expressions; anything else is a plain
+//! substring. Both are matched case-insensitively against the description.
+//! A rule may end in `@name` to draft its entries from the named template
+//! (`costco => Expenses:Groceries @tax`) instead of the default one.
+//! Blank lines and lines starting with `#` or `;` are ignored.
+
+use crate::ReconcileError;
+use regex::{Regex, RegexBuilder};
+use std::fs;
+
+/// A rule's left-hand side: a `/regex/` or a plain substring.
+#[derive(Clone, Debug)]
+pub(crate) enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Reads a pattern as written in a rules file.
+    pub(crate) fn parse(pattern: &str) -> Result<Pattern, regex::Error> {
+        match pattern
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Some(expression) => Ok(Pattern::Regex(
+                RegexBuilder::new(expression)
+                    .case_insensitive(true)
+                    .build()?,
+            )),
+            None => Ok(Pattern::Substring(pattern.to_lowercase())),
+        }
+    }
+
+    pub(crate) fn matches(&self, description: &str) -> bool {
+        match self {
+            Pattern::Substring(needle) => description.to_lowercase().contains(needle),
+            Pattern::Regex(regex) => regex.is_match(description),
+        }
+    }
+}
+
+/// One `pattern => account` line.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pattern: Pattern,
+    pub account: String,
+    /// The name of the template given with `@name`, if any.
+    pub template: Option<String>,
+}
+
+/// An ordered list of rules; the first rule that matches wins.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Reads and parses a rules file.
+    pub fn load(path: &str) -> Result<Rules, ReconcileError> {
+        let content = fs::read_to_string(path).map_err(ReconcileError::io(path))?;
+        Rules::parse(&content).map_err(|message| ReconcileError::Rules {
+            path: path.to_string(),
+            message,
+        })
+    }
+
+    /// Parses rules from text, reporting the first malformed line.
+    pub fn parse(content: &str) -> Result<Rules, String> {
+        let mut rules = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let (pattern, account) = line
+                .split_once("=>")
+                .map(|(pattern, account)| (pattern.trim(), account.trim()))
+                .filter(|(pattern, account)| !pattern.is_empty() && !account.is_empty())
+                .ok_or_else(|| format!("line {}: expected `pattern => account`", index + 1))?;
+
+            let (account, template) = match account.rsplit_once(" @") {
+                Some((account, name)) if !name.trim().is_empty() => {
+                    (account.trim_end(), Some(name.trim().to_string()))
+                }
+                _ => (account, None),
+            };
+
+            let pattern = Pattern::parse(pattern)
+                .map_err(|error| format!("line {}: {}", index + 1, error))?;
+
+            rules.push(Rule {
+                pattern,
+                account: account.to_string(),
+                template,
+            });
+        }
+
+        Ok(Rules { rules })
+    }
+
+    /// The first rule matching `description`.
+    pub fn rule_for(&self, description: &str) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.matches(description))
+    }
+
+    /// The template names the rules refer to.
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.template.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_for<'a>(rules: &'a Rules, description: &str) -> Option<&'a str> {
+        rules
+            .rule_for(description)
+            .map(|rule| rule.account.as_str())
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = Rules::parse(
+            "# groceries first
+whole foods => Expenses:Groceries
+/^SQ \\*/   => Expenses:Coffee
+market   => Expenses:Markets
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            account_for(&rules, "WHOLE FOODS MARKET #123"),
+            Some("Expenses:Groceries")
+        );
+        assert_eq!(
+            account_for(&rules, "sq *blue bottle"),
+            Some("Expenses:Coffee")
+        );
+        assert_eq!(
+            account_for(&rules, "Farmers Market"),
+            Some("Expenses:Markets")
+        );
+        assert_eq!(account_for(&rules, "Rent"), None);
+    }
+
+    #[test]
+    fn test_rule_can_name_a_template() {
+        let rules = Rules::parse("costco => Expenses:Groceries @tax\nbar => Expenses:Eating Out\n")
+            .unwrap();
+
+        let costco = rules.rule_for("COSTCO #12").unwrap();
+        assert_eq!(costco.account, "Expenses:Groceries");
+        assert_eq!(costco.template.as_deref(), Some("tax"));
+        assert_eq!(rules.rule_for("Bar Luce").unwrap().template, None);
+        assert_eq!(rules.templates().collect::<Vec<_>>(), ["tax"]);
+    }
+
+    #[test]
+    fn test_malformed_rule_reports_line() {
+        let error = Rules::parse("coffee => Expenses:Coffee\nno arrow here\n").unwrap_err();
+        assert!(error.starts_with("line 2"));
+
+        assert!(Rules::parse("/[unclosed/ => Expenses:Misc").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Entry templates for the transactions added for unmatched records.
+//!
+//! A template is the text of one ledger entry with placeholders that are
+//! filled in from the record: `{date}`, `{description}`, `{amount}` (what the
+//! record moves into the account, in the ledger's notation: positive for a
+//! withdrawal, negative for a deposit), `{-amount}` (the same, negated, which
+//! is the statement's own amount) and `{account}` (the account picked by a
+//! rule or menu action).
+//! Other accounts are written out as they should appear:
+//!
+//! ```text
+//! {date} {description}
+//!     {account}  {amount}
+//!     Expenses:Tips
+//!     Assets:Checking  {-amount}
+//! ```
+
+use crate::ReconcileError;
+use regex::Regex;
+use std::fs;
+
+/// The placeholders a template may use.
+const PLACEHOLDERS: [&str; 5] = ["date", "description", "amount", "-amount", "account"];
+
+/// The values filled into a template.
+pub struct Fields<'a> {
+    pub date: &'a str,
+    pub description: &'a str,
+    pub amount: &'a str,
+    pub negated: &'a str,
+    pub account: &'a str,
+}
+
+/// A parsed entry template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    text: String,
+}
+
+impl Template {
+    /// Reads and parses a template file.
+    pub fn load(path: &str) -> Result<Template, ReconcileError> {
+        let content = fs::read_to_string(path).map_err(ReconcileError::io(path))?;
+        Template::parse(&content).map_err(|message| ReconcileError::Template {
+            path: path.to_string(),
+            message,
+        })
+    }
+
+    /// Parses template text, rejecting placeholders it doesn't know.
+    pub fn parse(content: &str) -> Result<Template, String> {
+        let text = content.trim_end().lines().collect::<Vec<_>>().join("\n");
+        if text.trim().is_empty() {
+            return Err("the template is empty".to_string());
+        }
+
+        let placeholder = Regex::new(r"\{(-?[a-z]+)\}").expect("valid placeholder pattern");
+        if let Some(unknown) = placeholder
+            .captures_iter(&text)
+            .map(|captures| captures[1].to_string())
+            .find(|name| !PLACEHOLDERS.contains(&name.as_str()))
+        {
+            return Err(format!(
+                "unknown placeholder {{{}}} (known: {})",
+                unknown,
+                PLACEHOLDERS.map(|name| format!("{{{}}}", name)).join(", ")
+            ));
+        }
+
+        Ok(Template { text })
+    }
+
+    /// The entry for `fields`.
+    pub fn render(&self, fields: &Fields) -> String {
+        self.text
+            .replace("{date}", fields.date)
+            .replace("{description}", fields.description)
+            .replace("{-amount}", fields.negated)
+            .replace("{amount}", fields.amount)
+            .replace("{account}", fields.account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_fills_every_placeholder() {
+        let template = Template::parse(
+            "{date} * {description}\r\n    {account}  {amount}\r\n    Assets:Checking  {-amount}\r\n\r\n",
+        )
+        .unwrap();
+
+        let entry = template.render(&Fields {
+            date: "2025-01-03",
+            description: "bakery",
+            amount: "$5.00",
+            negated: "-$5.00",
+            account: "Expenses:Food",
+        });
+
+        assert_eq!(
+            entry,
+            "2025-01-03 * bakery\n    Expenses:Food  $5.00\n    Assets:Checking  -$5.00"
+        );
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_rejected() {
+        let error = Template::parse("{date} {payee}\n    {account}  {amount}").unwrap_err();
+        assert!(error.contains("{payee}"), "{}", error);
+
+        // Lot prices use braces too, but not around a bare word.
+        assert!(Template::parse("{date} Buy\n    Assets:Broker  10 AAPL {$150}").is_ok());
+        assert!(Template::parse("\n\n").is_err());
+    }
+}
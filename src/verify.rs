@@ -0,0 +1,163 @@
+//! `--verify-with-hledger`: asking hledger itself for the cleared balance of
+//! the updated ledger, so the run can be checked without trusting this
+//! tool's own sums.
+
+use crate::amount::{self, Amount, Separators};
+use log::warn;
+use rust_decimal::Decimal;
+use std::io;
+use std::process::Command;
+
+/// Runs `program -f ledger_path balance --cleared` for `account`, or for
+/// every account, and describes the total it reports, compared with
+/// `closing` when given. A missing hledger or a failed run is only warned
+/// about, and returns `None`.
+pub(crate) fn verify_with_hledger(
+    program: &str,
+    ledger_path: &str,
+    account: Option<&str>,
+    closing: Option<&Amount>,
+    tolerance: Decimal,
+    separators: Separators,
+) -> Option<String> {
+    let total = match cleared_total(program, ledger_path, account) {
+        Ok(total) => total,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "{} is not installed; skipping --verify-with-hledger.",
+                program
+            );
+            return None;
+        }
+        Err(err) => {
+            warn!("--verify-with-hledger: {}", err);
+            return None;
+        }
+    };
+    let account = account.unwrap_or("all accounts");
+    let mut verified = format!("hledger: the cleared balance of {} is {}", account, total);
+
+    let Some(closing) = closing else {
+        return Some(verified);
+    };
+    let reported = amount::parse(&total, separators).filter(|reported| {
+        amount::same_commodity(reported.commodity.as_deref(), closing.commodity.as_deref())
+    });
+    match reported {
+        Some(reported) if (reported.quantity - closing.quantity).abs() <= tolerance => {
+            verified.push_str(", as --closing-balance says");
+        }
+        Some(reported) => warn!(
+            "hledger's cleared balance of {} is {}, but --closing-balance is {}, {} apart.",
+            account,
+            total,
+            closing.format(separators),
+            Amount {
+                quantity: reported.quantity - closing.quantity,
+                commodity: closing.commodity.clone(),
+            }
+            .format(separators)
+        ),
+        None => warn!(
+            "hledger's cleared balance {:?} can't be compared with --closing-balance {}.",
+            total,
+            closing.format(separators)
+        ),
+    }
+    Some(verified)
+}
+
+/// The total of hledger's `balance --cleared` report, read from its CSV
+/// output.
+fn cleared_total(program: &str, ledger_path: &str, account: Option<&str>) -> io::Result<String> {
+    let mut command = Command::new(program);
+    command.args(["-f", ledger_path, "balance", "--cleared", "-O", "csv"]);
+    command.args(account);
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    total_of(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} printed no total", program),
+        )
+    })
+}
+
+/// The balance on the `total` row of a `balance -O csv` report.
+fn total_of(csv: &str) -> Option<String> {
+    csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(csv.as_bytes())
+        .records()
+        .filter_map(Result::ok)
+        .find(|row| row.get(0) == Some("total"))
+        .and_then(|row| row.get(1).map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test_total_is_read_from_the_csv_report() {
+        let report =
+            "\"account\",\"balance\"\n\"Assets:Checking\",\"$996.00\"\n\"total\",\"$996.00\"\n";
+        assert_eq!(total_of(report).as_deref(), Some("$996.00"));
+        assert_eq!(total_of("\"account\",\"balance\"\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hledger_is_run_on_the_ledger_and_a_missing_one_is_skipped() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stands in for hledger, echoing its arguments as the account row.
+        let program =
+            env::temp_dir().join(format!("ledger-reconcile-{}-hledger", std::process::id()));
+        fs::write(
+            &program,
+            "#!/bin/sh\nprintf '\"account\",\"balance\"\\n\"%s\",\"x\"\\n\"total\",\"$996.00\"\\n' \"$*\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        let program = program.to_string_lossy().into_owned();
+
+        assert_eq!(
+            cleared_total(&program, "out.ledger", Some("Assets:Checking")).unwrap(),
+            "$996.00"
+        );
+        let missing = cleared_total("ledger-reconcile-no-such-hledger", "out.ledger", None);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let verify = |program: &str, closing: &str| {
+            let closing = amount::parse(closing, Separators::default()).unwrap();
+            verify_with_hledger(
+                program,
+                "out.ledger",
+                Some("Assets:Checking"),
+                Some(&closing),
+                Decimal::ZERO,
+                Separators::default(),
+            )
+        };
+        assert_eq!(
+            verify(&program, "$996.00").as_deref(),
+            Some("hledger: the cleared balance of Assets:Checking is $996.00, as --closing-balance says")
+        );
+        // A mismatch is only warned about, like a missing hledger.
+        assert_eq!(
+            verify(&program, "$990.00").as_deref(),
+            Some("hledger: the cleared balance of Assets:Checking is $996.00")
+        );
+        assert_eq!(verify("ledger-reconcile-no-such-hledger", "$996.00"), None);
+        fs::remove_file(program).unwrap();
+    }
+}